@@ -0,0 +1,51 @@
+// Compare the time of a fixed depth search with one thread and with several,
+// checking that every thread count finds the same move.
+// Run with `cargo bench --bench parallel_search`.
+use std::time::{Duration, Instant};
+
+use tic_tac_toe::{Coordinates, Evaluator, Tile, TileMap, ai::{self, SearchProgress}};
+
+// Board size, win length, search depth and the moves played so far
+const POSITIONS: [(u32, u32, u32, &[&str]); 4] = [
+    (7, 5, 4, &["d4", "c3", "e5"]),
+    (8, 5, 4, &["d4", "e5", "e4", "d5"]),
+    (8, 5, 4, &["d4", "e5", "c3", "f6", "e3", "d6"]),
+    (6, 4, 5, &["c3", "d4", "d3"]),
+];
+
+fn main() {
+    let mut thread_counts = vec![1, 2, 4, ai::available_threads()];
+    thread_counts.sort_unstable();
+    thread_counts.dedup();
+
+    let mut totals = vec![Duration::ZERO; thread_counts.len()];
+    for (size, win_length, depth, moves) in POSITIONS {
+        let mut tile_map = TileMap::empty(size);
+        let mut player = Tile::X;
+        for notation in moves {
+            let coordinates = Coordinates::from_notation(notation).unwrap();
+            tile_map.0[coordinates.x as usize][coordinates.y as usize] = player;
+            player = player.opponent();
+        }
+
+        let mut expected = None;
+        print!("{}x{} k{} depth {}:", size, size, win_length, depth);
+        for (threads, total) in thread_counts.iter().zip(totals.iter_mut()) {
+            let start = Instant::now();
+            let result = ai::search_parallel(&tile_map, player, win_length, depth, *threads,
+                                             Evaluator::Handwritten, &SearchProgress::default());
+            let elapsed = start.elapsed();
+            *total += elapsed;
+            print!("  {} threads {:>8.1?}", threads, elapsed);
+
+            let found = (result.best_move, result.score);
+            assert_eq!(*expected.get_or_insert(found), found, "{} threads found another move", threads);
+        }
+        println!();
+    }
+
+    for (threads, total) in thread_counts.iter().zip(totals.iter()) {
+        println!("{} threads: {:>8.1?} total, speedup {:.2}x", threads, total,
+                 totals[0].as_secs_f64() / total.as_secs_f64());
+    }
+}
@@ -0,0 +1,595 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock, atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering}};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bevy::log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{constants, Coordinates, Evaluator, Tile, TileMap, bitboard::{self, Lines, Position}, learned::{self, ValueFunction},
+            threats};
+
+// Score of a won position; wins found sooner score higher
+pub const WIN_SCORE: i32 = 100_000_000;
+
+// Scores closer than this to WIN_SCORE are wins found by the search
+const WIN_THRESHOLD: i32 = WIN_SCORE - 1000;
+
+// A search adds its work to the progress every time it visited this many
+// positions, so that the counts follow long iterations
+const REPORT_NODES: u64 = 4096;
+
+// Human readable meaning of a search score
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Evaluation {
+    // The side to move wins with its Nth move
+    Win(u32),
+    // The opponent wins with its Nth move
+    Loss(u32),
+    Draw,
+    Heuristic(i32),
+}
+
+impl Evaluation {
+    // `exhaustive` tells whether the search saw every move until the end of
+    // the game, in which case a zero score is a proven draw
+    pub fn from_score(score: i32, exhaustive: bool) -> Self {
+        if score >= WIN_THRESHOLD {
+            Evaluation::Win(((WIN_SCORE - score + 1) / 2) as u32)
+        } else if score <= -WIN_THRESHOLD {
+            Evaluation::Loss(((WIN_SCORE + score + 1) / 2) as u32)
+        } else if score == 0 && exhaustive {
+            Evaluation::Draw
+        } else {
+            Evaluation::Heuristic(score)
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Evaluation::Win(moves) => format!("Win {}", moves),
+            Evaluation::Loss(moves) => format!("Loss {}", moves),
+            Evaluation::Draw => "Draw".to_string(),
+            Evaluation::Heuristic(score) => score.to_string(),
+        }
+    }
+}
+
+// Whether a search of `depth` plies reaches the end of every game line: it
+// must look as far as the board has empty tiles, and try all of them
+pub fn is_exhaustive(tile_map: &TileMap, depth: u32) -> bool {
+    let empty = tile_map.0.iter().flatten().filter(|tile| **tile == Tile::Empty).count();
+    depth as usize >= empty && !Position::from_tile_map(tile_map).prunes_moves()
+}
+
+// Result of a search, seen from the side to move
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub player: Tile,
+    pub best_move: Option<Coordinates>,
+    pub score: i32,
+    // Principal variation: the line both sides are expected to play
+    pub pv: Vec<Coordinates>,
+    // Deepest fully searched depth
+    pub depth: u32,
+}
+
+// Work done by a search until its latest published result
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    // Positions visited, or playouts for MCTS
+    pub nodes: u64,
+    // Positions whose remaining moves were pruned by alpha-beta
+    pub cutoffs: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub elapsed: Duration,
+}
+
+impl SearchStats {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+// Shared between a search running in the background and whoever waits for
+// it: holds the result of the deepest finished iteration and lets the
+// search be stopped early. Its clock starts when it is created.
+#[derive(Debug, Clone)]
+pub struct SearchProgress {
+    latest: Arc<Mutex<Option<SearchResult>>>,
+    stopped: Arc<AtomicBool>,
+    stats: Arc<Mutex<SearchStats>>,
+    started: Instant,
+}
+
+impl Default for SearchProgress {
+    fn default() -> Self {
+        SearchProgress {
+            latest: Default::default(),
+            stopped: Default::default(),
+            stats: Default::default(),
+            started: Instant::now(),
+        }
+    }
+}
+
+impl SearchProgress {
+    pub fn latest(&self) -> Option<SearchResult> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    pub fn publish(&self, result: &SearchResult) {
+        *self.latest.lock().unwrap() = Some(result.clone());
+        let mut stats = self.stats.lock().unwrap();
+        stats.depth = result.depth;
+        stats.score = result.score;
+        stats.elapsed = self.started.elapsed();
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        *self.stats.lock().unwrap()
+    }
+
+    // Count work done since the last call, from any thread of the search
+    pub fn add_work(&self, nodes: u64, cutoffs: u64) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.nodes += nodes;
+        stats.cutoffs += cutoffs;
+    }
+}
+
+// Find the best move for `player` using minimax with alpha-beta pruning,
+// looking `depth` plies ahead
+pub fn best_move(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32) -> Option<Coordinates> {
+    search(tile_map, player, win_length, depth, &SearchProgress::default()).best_move
+}
+
+// Iterative deepening search up to `depth` plies. The result of every
+// finished depth is published to `progress`; once the search is stopped the
+// unfinished depth is thrown away, though the first one always finishes.
+pub fn search(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32,
+              progress: &SearchProgress) -> SearchResult {
+    search_parallel(tile_map, player, win_length, depth, 1, Evaluator::Handwritten, progress)
+}
+
+// Number of threads to search with when all cores are used
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1)
+}
+
+// Same as `search`, with the root moves of every depth shared out between
+// `threads` threads. The best move is the same as with a single thread.
+// Forced sequences of threats are looked for first, since they often reach
+// deeper than the search: a winning one is played at once, and against one
+// of the opponent only the moves that break it are searched, deepening as
+// below.
+pub fn search_parallel(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32, threads: usize,
+                       evaluator: Evaluator, progress: &SearchProgress) -> SearchResult {
+    if let Some(pv) = threats::winning_line(tile_map, player, win_length, progress) {
+        let result = SearchResult {
+            player,
+            best_move: pv.first().copied(),
+            score: WIN_SCORE - pv.len() as i32,
+            depth: pv.len() as u32,
+            pv,
+        };
+        progress.publish(&result);
+        return result;
+    }
+    if let Some(moves) = threats::defences(tile_map, player, win_length, progress).filter(|moves| !moves.is_empty()) {
+        return deepen_scores(tile_map, player, win_length, depth, moves, evaluator, progress).0;
+    }
+
+    let mut search = Search::new(tile_map, win_length, evaluator, progress.clone());
+    let mut result = SearchResult { player, best_move: None, score: 0, pv: Vec::new(), depth: 0 };
+
+    for current_depth in 1..=depth.max(1) {
+        let (score, pv) = if threads > 1 {
+            parallel_root(tile_map, player, win_length, current_depth, threads, evaluator, progress)
+        } else {
+            let mut pv = Vec::new();
+            let score = search.negamax(player, current_depth, -WIN_SCORE - 1, WIN_SCORE + 1, &mut pv);
+            search.report_work();
+            (score, pv)
+        };
+        if progress.is_stopped() && result.best_move.is_some() {
+            break;
+        }
+        result = SearchResult { player, best_move: pv.first().copied(), score, pv, depth: current_depth };
+        progress.publish(&result);
+
+        // A forced result will not change by looking further
+        if progress.is_stopped() || score.abs() >= WIN_THRESHOLD {
+            break;
+        }
+    }
+    result
+}
+
+// Same as `search`, also giving the full window score of every move the
+// search considers at the deepest finished depth, so that a move can be drawn
+// from them
+pub fn search_scored(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32, evaluator: Evaluator,
+                     progress: &SearchProgress) -> (SearchResult, Vec<(Coordinates, i32)>) {
+    deepen_scores(tile_map, player, win_length, depth, candidate_moves(tile_map), evaluator, progress)
+}
+
+// Score `moves` with a full window at every depth up to `depth`, publishing
+// the best of each finished depth, and return the deepest scores
+fn deepen_scores(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32, moves: Vec<Coordinates>,
+                 evaluator: Evaluator, progress: &SearchProgress) -> (SearchResult, Vec<(Coordinates, i32)>) {
+    let mut result = SearchResult { player, best_move: None, score: 0, pv: Vec::new(), depth: 0 };
+    let mut deepest = Vec::new();
+    for current_depth in 1..=depth.max(1) {
+        let scores = score_moves(tile_map, player, win_length, current_depth, moves.clone(), evaluator, progress);
+        if progress.is_stopped() && result.best_move.is_some() {
+            break;
+        }
+        // The first of the best moves, as in the search
+        let Some((best_move, score)) = scores.iter().rev().max_by_key(|(_, score)| *score).copied() else { break };
+        result = SearchResult { player, best_move: Some(best_move), score, pv: vec![best_move], depth: current_depth };
+        progress.publish(&result);
+        deepest = scores;
+        if progress.is_stopped() || score.abs() >= WIN_THRESHOLD {
+            break;
+        }
+    }
+    (result, deepest)
+}
+
+// Search every root move on one of `threads` threads, taking the next move
+// from a shared counter. The lower bound of each window is one below the best
+// score found so far: moves that cannot be better are cut off as in the single
+// threaded search, while a move scoring the same still gets its exact score,
+// so that ties go to the first move in candidate order.
+fn parallel_root(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32, threads: usize,
+                 evaluator: Evaluator, progress: &SearchProgress) -> (i32, Vec<Coordinates>) {
+    let position = Position::from_tile_map(tile_map);
+    let moves: Vec<Coordinates> = position.candidate_moves().into_iter()
+        .map(|square| position.coordinates(square))
+        .collect();
+    let next = AtomicUsize::new(0);
+    let best = AtomicI32::new(-WIN_SCORE - 1);
+    let scored = Mutex::new(Vec::with_capacity(moves.len()));
+
+    thread::scope(|scope| {
+        for _ in 0..threads.min(moves.len()) {
+            scope.spawn(|| {
+                let mut search = Search::new(tile_map, win_length, evaluator, progress.clone());
+                while let Some(coordinates) = moves.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let alpha = best.load(Ordering::Relaxed) - 1;
+                    let mut line = Vec::new();
+                    let score = -search.score_move(*coordinates, player, depth, -WIN_SCORE - 1, -alpha, &mut line);
+                    best.fetch_max(score, Ordering::Relaxed);
+                    line.insert(0, *coordinates);
+                    scored.lock().unwrap().push((*coordinates, score, line));
+                }
+                search.report_work();
+            });
+        }
+    });
+
+    let scored = scored.into_inner().unwrap();
+    let index = |coordinates: &Coordinates| moves.iter().position(|candidate| candidate == coordinates);
+    match scored.into_iter().max_by(|a, b| a.1.cmp(&b.1).then_with(|| index(&b.0).cmp(&index(&a.0)))) {
+        Some((_, score, line)) => (score, line),
+        None => (0, Vec::new()),
+    }
+}
+
+// Score every empty tile for `player`, searching each one with a full window
+// so that the scores can be compared with each other
+pub fn analyse(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32) -> Vec<(Coordinates, i32)> {
+    let size = tile_map.0.len() as u16;
+    let moves = (0..size)
+        .flat_map(|x| (0..size).map(move |y| Coordinates { x, y }))
+        .filter(|coordinates| tile_map.0[coordinates.x as usize][coordinates.y as usize] == Tile::Empty)
+        .collect();
+    score_moves(tile_map, player, win_length, depth, moves, Evaluator::Handwritten, &SearchProgress::default())
+}
+
+// Full window scores of the moves the search would consider for `player`
+pub fn root_scores(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32, evaluator: Evaluator,
+                   progress: &SearchProgress) -> Vec<(Coordinates, i32)> {
+    score_moves(tile_map, player, win_length, depth, candidate_moves(tile_map), evaluator, progress)
+}
+
+fn score_moves(tile_map: &TileMap, player: Tile, win_length: u32, depth: u32, moves: Vec<Coordinates>,
+               evaluator: Evaluator, progress: &SearchProgress) -> Vec<(Coordinates, i32)> {
+    let mut search = Search::new(tile_map, win_length, evaluator, progress.clone());
+    let scores = moves.into_iter()
+        .map(|coordinates| {
+            let score = -search.score_move(coordinates, player, depth, -WIN_SCORE - 1, WIN_SCORE + 1,
+                                           &mut Vec::new());
+            (coordinates, score)
+        })
+        .collect();
+    search.report_work();
+    scores
+}
+
+// Pick one of the scored moves with a softmax over the scores: a move that
+// scores `temperature` less than another is e times less likely to be played.
+// `random` is a number in [0, 1) used to draw the move. Without a positive
+// temperature the first of the best moves is played.
+pub fn sample_move(scores: &[(Coordinates, i32)], temperature: f32, random: f32) -> Option<(Coordinates, i32)> {
+    if temperature <= 0.0 {
+        return scores.iter().rev().max_by_key(|(_, score)| *score).copied();
+    }
+    let best = scores.iter().map(|(_, score)| *score).max()?;
+    let weights: Vec<f32> = scores.iter()
+        .map(|(_, score)| ((*score - best) as f32 / temperature).exp())
+        .collect();
+    let mut target = random * weights.iter().sum::<f32>();
+    for (scored, weight) in scores.iter().zip(weights) {
+        if target < weight {
+            return Some(*scored);
+        }
+        target -= weight;
+    }
+    scores.last().copied()
+}
+
+// Working state of one search; the position is modified in place and
+// restored after every move that is tried
+struct Search {
+    position: Position,
+    lines: &'static Lines,
+    // Evaluates the positions where the search stops instead of the
+    // handwritten evaluation
+    learned: Option<&'static ValueFunction>,
+    // Number of moves played since the root
+    ply: i32,
+    progress: SearchProgress,
+    // Work not yet added to the progress
+    nodes: u64,
+    cutoffs: u64,
+}
+
+impl Search {
+    fn new(tile_map: &TileMap, win_length: u32, evaluator: Evaluator, progress: SearchProgress) -> Self {
+        let position = Position::from_tile_map(tile_map);
+        let learned = if evaluator == Evaluator::Learned { learned::value_function() } else { None };
+        Search {
+            position,
+            lines: bitboard::lines(position.size, win_length),
+            learned,
+            ply: 0,
+            progress,
+            nodes: 0,
+            cutoffs: 0,
+        }
+    }
+
+    fn report_work(&mut self) {
+        self.progress.add_work(self.nodes, self.cutoffs);
+        self.nodes = 0;
+        self.cutoffs = 0;
+    }
+
+    // Play `coordinates` for `player` and return the score from the opponent's
+    // side; `pv` receives the line expected after the move
+    fn score_move(&mut self, coordinates: Coordinates, player: Tile, depth: u32,
+                  alpha: i32, beta: i32, pv: &mut Vec<Coordinates>) -> i32 {
+        let square = self.position.square(coordinates);
+        self.position.set(square, player);
+        self.ply += 1;
+        self.nodes += 1;
+        if self.nodes >= REPORT_NODES {
+            self.report_work();
+        }
+        let score = if self.lines.is_win(self.position.pieces(player), square) {
+            pv.clear();
+            -(WIN_SCORE - self.ply)
+        } else {
+            self.negamax(player.opponent(), depth.saturating_sub(1), alpha, beta, pv)
+        };
+        self.ply -= 1;
+        self.position.set(square, Tile::Empty);
+        score
+    }
+
+    // Score of the position for `player`, who is the side to move; `pv`
+    // receives the best line found from here. Nodes one move from the
+    // horizon are cheap and finish even once the search is stopped, so that
+    // the first depth always finishes with a move to play.
+    fn negamax(&mut self, player: Tile, depth: u32, mut alpha: i32, beta: i32,
+               pv: &mut Vec<Coordinates>) -> i32 {
+        pv.clear();
+        if depth > 1 && self.progress.is_stopped() {
+            return 0;
+        }
+        let moves = self.position.candidate_moves();
+        if moves.is_empty() {
+            return 0;
+        }
+        if depth == 0 {
+            return match self.learned {
+                Some(value_function) => value_function.evaluate(&self.position, player, self.lines),
+                None => evaluate_position(&self.position, player, self.lines),
+            };
+        }
+
+        let mut line = Vec::new();
+        for square in moves {
+            let coordinates = self.position.coordinates(square);
+            let score = -self.score_move(coordinates, player, depth, -beta, -alpha, &mut line);
+            if score > alpha {
+                alpha = score;
+                pv.clear();
+                pv.push(coordinates);
+                pv.extend_from_slice(&line);
+            }
+            if alpha >= beta {
+                self.cutoffs += 1;
+                break;
+            }
+        }
+        alpha
+    }
+}
+
+// Empty tiles near existing pieces, closest to the centre first
+pub fn candidate_moves(map: &TileMap) -> Vec<Coordinates> {
+    let position = Position::from_tile_map(map);
+    position.candidate_moves().into_iter()
+        .map(|square| position.coordinates(square))
+        .collect()
+}
+
+// Weights of the heuristic evaluation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Weights {
+    // Value of a segment holding N pieces of one side and none of the other,
+    // by N
+    pub lines: [i32; bitboard::MAX_SIZE as usize + 1],
+    // Value of a piece for each half step closer to the centre than the
+    // corners
+    pub centre: i32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        let mut lines = [0; bitboard::MAX_SIZE as usize + 1];
+        for (pieces, weight) in lines.iter_mut().enumerate().skip(1) {
+            *weight = 4i32.pow(pieces as u32);
+        }
+        Weights { lines, centre: 0 }
+    }
+}
+
+impl Weights {
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+}
+
+// Weights used by the AI: those of the weights file when there is one,
+// otherwise the built-in ones. The file is read the first time they are
+// needed.
+pub fn weights() -> &'static Weights {
+    static WEIGHTS: OnceLock<Weights> = OnceLock::new();
+    WEIGHTS.get_or_init(|| match Weights::load(&PathBuf::from(constants::WEIGHTS_FILE)) {
+        Ok(weights) => weights,
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                warn!("Could not read the evaluation weights: {}", error);
+            }
+            Weights::default()
+        }
+    })
+}
+
+// Heuristic value of a position for `player`: every line segment of
+// `win_length` tiles that only one side occupies counts for that side,
+// weighted by how many of its pieces are already there, and pieces count
+// more the closer they are to the centre
+pub fn evaluate(map: &TileMap, player: Tile, win_length: u32) -> i32 {
+    let position = Position::from_tile_map(map);
+    evaluate_position(&position, player, bitboard::lines(position.size, win_length))
+}
+
+pub fn evaluate_position(position: &Position, player: Tile, lines: &Lines) -> i32 {
+    evaluate_with(position, player, lines, weights())
+}
+
+// Same as `evaluate_position` with the given weights instead of the AI's
+pub fn evaluate_with(position: &Position, player: Tile, lines: &Lines, weights: &Weights) -> i32 {
+    let (own_pieces, other_pieces) = (position.pieces(player), position.pieces(player.opponent()));
+    let segments: i32 = lines.masks.iter().map(|mask| {
+        let (own, other) = ((mask & own_pieces).count_ones(), (mask & other_pieces).count_ones());
+        if other == 0 && own > 0 {
+            weights.lines[own as usize]
+        } else if own == 0 && other > 0 {
+            -weights.lines[other as usize]
+        } else {
+            0
+        }
+    }).sum();
+    if weights.centre == 0 {
+        return segments;
+    }
+
+    let last = position.size as i32 - 1;
+    let closeness = |pieces: u64| -> i32 {
+        bitboard::squares(pieces).map(|square| {
+            let (x, y) = ((square / position.size) as i32, (square % position.size) as i32);
+            2 * last - (2 * x - last).abs() - (2 * y - last).abs()
+        }).sum()
+    };
+    segments + weights.centre * (closeness(own_pieces) - closeness(other_pieces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Positions in the text format of `Position::to_text`, with their win length
+    const POSITIONS: [(&str, u32); 5] = [
+        ("...../...../...../...../.....", 4),
+        ("...../...../..x../...../.....", 4),
+        ("....../....../..xo../...x../....o./......", 4),
+        ("......./......./..xo.../..ox.../...x.../......./.......", 4),
+        ("x.o/.x./o..", 3),
+    ];
+
+    #[test]
+    fn parallel_search_finds_the_serial_move() {
+        for (text, win_length) in POSITIONS {
+            let tile_map = Position::from_text(text).unwrap().to_tile_map();
+            let player = Position::from_text(text).unwrap().side_to_move();
+            let serial = search_parallel(&tile_map, player, win_length, 3, 1, Evaluator::Handwritten,
+                                         &SearchProgress::default());
+            let parallel = search_parallel(&tile_map, player, win_length, 3, 4, Evaluator::Handwritten,
+                                           &SearchProgress::default());
+            assert_eq!((serial.best_move, serial.score), (parallel.best_move, parallel.score), "{}", text);
+        }
+    }
+
+    #[test]
+    fn sample_move_plays_the_best_move_without_temperature() {
+        let scores: Vec<(Coordinates, i32)> = [(0, -5), (1, 30), (2, 30), (3, 10)].into_iter()
+            .map(|(x, score)| (Coordinates { x, y: 0 }, score))
+            .collect();
+        for random in [0.0, 0.5, 0.99] {
+            assert_eq!(sample_move(&scores, 0.0, random), Some(scores[1]));
+            assert_eq!(sample_move(&scores, 0.001, random).map(|(_, score)| score), Some(30));
+        }
+        assert_eq!(sample_move(&scores, 1000.0, 0.0), Some(scores[0]));
+        assert_eq!(sample_move(&[], 10.0, 0.5), None);
+    }
+
+    #[test]
+    fn stopped_search_finishes_the_first_depth() {
+        let progress = SearchProgress::default();
+        progress.stop();
+        for (text, win_length) in POSITIONS {
+            let tile_map = Position::from_text(text).unwrap().to_tile_map();
+            let player = Position::from_text(text).unwrap().side_to_move();
+            let [serial, parallel] = [1, 4].map(|threads| {
+                search_parallel(&tile_map, player, win_length, 5, threads, Evaluator::Handwritten, &progress)
+            });
+            assert_eq!((serial.best_move.is_some(), serial.depth), (true, 1), "{}", text);
+            assert_eq!((serial.best_move, serial.score), (parallel.best_move, parallel.score), "{}", text);
+            let (result, scores) = search_scored(&tile_map, player, win_length, 5, Evaluator::Handwritten, &progress);
+            assert_eq!((result.best_move.is_some(), result.depth, scores.len()),
+                       (true, 1, candidate_moves(&tile_map).len()), "{}", text);
+        }
+    }
+}
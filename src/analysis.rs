@@ -0,0 +1,181 @@
+use bevy::{prelude::*, tasks::{AsyncComputeTaskPool, Task}};
+use futures_lite::future;
+
+use crate::{constants, AiDepth, Board, Coordinates, MoveHistory, menu::get_menu_styles,
+            ai::{self, Evaluation}, game::{PlayingState, PlayingItem, GameButtonAction}};
+
+pub struct AnalysisPlugin;
+
+// Whether the overlay is shown and the search currently running for it
+#[derive(Default)]
+struct Analysis {
+    enabled: bool,
+    // Number of moves on the board when the running or last search started
+    analysed_moves: Option<usize>,
+    task: Option<Task<Vec<(Coordinates, Evaluation)>>>,
+}
+
+// Result of the last finished search, one entry per empty tile
+#[derive(Default)]
+struct AnalysisScores(Vec<(Coordinates, Evaluation)>);
+
+// Tag component for the sprites and texts of the overlay
+#[derive(Component)]
+struct AnalysisLabel;
+
+impl Plugin for AnalysisPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Analysis>()
+            .init_resource::<AnalysisScores>()
+            .add_system_set(SystemSet::on_update(PlayingState::Playing)
+                            .with_system(analysis_toggle)
+                            .with_system(analysis_request)
+                            .with_system(analysis_poll)
+                            .with_system(analysis_render))
+            .add_system_set(SystemSet::on_exit(PlayingState::Playing)
+                            .with_system(analysis_clear));
+    }
+}
+
+fn analysis_toggle(
+    interaction_query: Query<(&Interaction, &GameButtonAction), Changed<Interaction>>,
+    keys: Res<Input<KeyCode>>,
+    mut analysis: ResMut<Analysis>,
+    mut scores: ResMut<AnalysisScores>,
+    ) {
+    let clicked = interaction_query.iter().any(|(interaction, action)| {
+        *interaction == Interaction::Clicked && *action == GameButtonAction::ToggleAnalysis
+    });
+    if clicked || keys.just_pressed(KeyCode::A) {
+        analysis.enabled = !analysis.enabled;
+        analysis.analysed_moves = None;
+        analysis.task = None;
+        scores.0.clear();
+    }
+}
+
+// Start a new search whenever the position changes while the overlay is on
+fn analysis_request(
+    mut analysis: ResMut<Analysis>,
+    mut scores: ResMut<AnalysisScores>,
+    board: Res<Board>,
+    moves: Res<MoveHistory>,
+    ai_depth: Res<AiDepth>,
+    ) {
+    if !analysis.enabled || analysis.analysed_moves == Some(moves.0.len()) {
+        return;
+    }
+    // The turn state only changes at the end of the frame, the move list right away
+    let player = moves.side_to_move();
+    let tile_map = board.tile_map.clone();
+    let win_length = board.win_length;
+    let depth = ai_depth.0;
+
+    // Dropping the previous task cancels it
+    analysis.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+        let exhaustive = ai::is_exhaustive(&tile_map, depth);
+        ai::analyse(&tile_map, player, win_length, depth)
+            .into_iter()
+            .map(|(coordinates, score)| (coordinates, Evaluation::from_score(score, exhaustive)))
+            .collect()
+    }));
+    analysis.analysed_moves = Some(moves.0.len());
+    if !scores.0.is_empty() {
+        scores.0.clear();
+    }
+}
+
+fn analysis_poll(mut analysis: ResMut<Analysis>, mut scores: ResMut<AnalysisScores>) {
+    let finished = match analysis.task.as_mut() {
+        Some(task) => future::block_on(future::poll_once(task)),
+        None => return,
+    };
+    if let Some(result) = finished {
+        scores.0 = result;
+        analysis.task = None;
+    }
+}
+
+// Draw a coloured square and the score over every analysed tile
+fn analysis_render(
+    mut commands: Commands,
+    scores: Res<AnalysisScores>,
+    board: Res<Board>,
+    asset_server: Res<AssetServer>,
+    tile_query: Query<&Transform, With<Coordinates>>,
+    label_query: Query<Entity, With<AnalysisLabel>>,
+    ) {
+    if !scores.is_changed() {
+        return;
+    }
+    for label in label_query.iter() {
+        commands.entity(label).despawn_recursive();
+    }
+
+    let (font, _, _) = get_menu_styles(asset_server);
+    for (coordinates, evaluation) in scores.0.iter() {
+        let transform = match board.coord_to_tile.get(coordinates)
+            .and_then(|tile| tile_query.get(*tile).ok()) {
+            Some(transform) => transform,
+            None => continue,
+        };
+        let (x, y) = (transform.translation.x, -transform.translation.y);
+
+        commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: heat_color(evaluation),
+                custom_size: Some(Vec2::splat(board.tile_size * 0.8)),
+                ..default()
+            },
+            transform: Transform::from_xyz(x, y, 2.5),
+            ..default()
+        })
+        .insert(AnalysisLabel)
+        .insert(PlayingItem);
+
+        commands.spawn_bundle(Text2dBundle {
+            text: Text::from_section(evaluation.label(), TextStyle {
+                font: font.clone(),
+                font_size: board.tile_size * constants::ANALYSIS_FONT_RATIO,
+                color: constants::TEXT_COLOR,
+            })
+            .with_alignment(TextAlignment::CENTER),
+            transform: Transform::from_xyz(x, y, 4.0),
+            ..default()
+        })
+        .insert(AnalysisLabel)
+        .insert(PlayingItem);
+    }
+}
+
+// Green for good moves, red for bad ones, grey for neutral ones
+fn heat_color(evaluation: &Evaluation) -> Color {
+    match evaluation {
+        Evaluation::Win(_) => constants::WINNING_COLOR,
+        Evaluation::Loss(_) => constants::LOSING_COLOR,
+        Evaluation::Draw => constants::DRAWN_COLOR,
+        Evaluation::Heuristic(score) => {
+            let strength = (*score as f32 / constants::ANALYSIS_SCORE_SCALE).clamp(-1.0, 1.0);
+            let target = if strength > 0.0 { constants::WINNING_COLOR } else { constants::LOSING_COLOR };
+            let base = constants::DRAWN_COLOR;
+            let t = strength.abs();
+            Color::rgb(base.r() + (target.r() - base.r()) * t,
+                       base.g() + (target.g() - base.g()) * t,
+                       base.b() + (target.b() - base.b()) * t)
+        }
+    }
+}
+
+fn analysis_clear(
+    mut commands: Commands,
+    mut analysis: ResMut<Analysis>,
+    mut scores: ResMut<AnalysisScores>,
+    label_query: Query<Entity, With<AnalysisLabel>>,
+    ) {
+    analysis.analysed_moves = None;
+    analysis.task = None;
+    scores.0.clear();
+    for label in label_query.iter() {
+        commands.entity(label).despawn_recursive();
+    }
+}
@@ -0,0 +1,44 @@
+// Build the opening book of a board size and win length from searches of
+// every position reached by book moves.
+// Run with `cargo run --release --bin book -- SIZE WIN_LENGTH [PLIES [DEPTH]]`.
+use std::{env, process, time::Instant};
+
+use tic_tac_toe::{bitboard, book::{self, Book}, constants};
+
+// Search depth used when none is given, deeper than the AI searches in games
+const DEFAULT_DEPTH: u32 = 5;
+
+fn main() {
+    let args: Vec<u32> = env::args().skip(1)
+        .map(|arg| arg.parse().unwrap_or_else(|_| usage()))
+        .collect();
+    if !(2..=4).contains(&args.len()) {
+        usage();
+    }
+    let (size, win_length) = (args[0], args[1]);
+    let plies = args.get(2).copied().unwrap_or(constants::BOOK_PLIES);
+    let depth = args.get(3).copied().unwrap_or(DEFAULT_DEPTH);
+    if size > bitboard::MAX_SIZE || !(1..=size).contains(&win_length) {
+        usage();
+    }
+
+    let start = Instant::now();
+    let book = Book::generate(size, win_length, plies, depth, |count| {
+        if count % 50 == 0 {
+            println!("{} positions searched in {:.1?}", count, start.elapsed());
+        }
+    });
+    let path = book::path(size, win_length);
+    if let Err(error) = book.save(&path) {
+        eprintln!("Could not write {}: {}", path.display(), error);
+        process::exit(1);
+    }
+    println!("{}x{} k{}: {} positions in {:.1?}, written to {}",
+             size, size, win_length, book.positions.len(), start.elapsed(), path.display());
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: book SIZE WIN_LENGTH [PLIES [DEPTH]]");
+    eprintln!("PLIES defaults to {} and DEPTH to {}", constants::BOOK_PLIES, DEFAULT_DEPTH);
+    process::exit(2);
+}
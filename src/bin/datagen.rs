@@ -0,0 +1,186 @@
+// Play engine games and write every searched position with the search score,
+// the best move and the final outcome, for experiments outside the game.
+// Positions use the text format of `Position::to_text`. The file is CSV or
+// JSON Lines depending on its extension or --format.
+// Run with `cargo run --release --bin datagen -- [OPTIONS]`.
+use std::{env, fs::File, io::{self, BufWriter, Write}, path::PathBuf, process, time::Instant};
+
+use serde::Serialize;
+
+use tic_tac_toe::{GameOutcome, Tile, bitboard::Position, engine::{self, EngineConfig}};
+
+// Games between two progress lines
+const REPORT_INTERVAL: u32 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Csv,
+    JsonLines,
+}
+
+struct Options {
+    engine: EngineConfig,
+    games: u32,
+    boards: Vec<(u32, u32)>,
+    opening: u32,
+    seed: u64,
+    output: PathBuf,
+    format: Option<Format>,
+}
+
+// One line of the output
+#[derive(Serialize)]
+struct Record {
+    game: u32,
+    ply: usize,
+    size: u32,
+    win_length: u32,
+    position: String,
+    side_to_move: &'static str,
+    // Score and best move of the search for the side to move, which with a
+    // temperature need not be the move played
+    score: i32,
+    best_move: String,
+    // "x", "o" or "draw"
+    outcome: &'static str,
+    // Points of the side to move at the end: 1 for a win, 0.5 for a draw
+    result: f32,
+}
+
+const CSV_HEADER: &str = "game,ply,size,win_length,position,side_to_move,score,best_move,outcome,result";
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        usage();
+    });
+    let format = options.format.unwrap_or_else(|| {
+        match options.output.extension().and_then(|extension| extension.to_str()) {
+            Some("csv") => Format::Csv,
+            _ => Format::JsonLines,
+        }
+    });
+    if let Err(error) = generate(&options, format) {
+        eprintln!("Could not write {}: {}", options.output.display(), error);
+        process::exit(1);
+    }
+}
+
+fn generate(options: &Options, format: Format) -> io::Result<()> {
+    let rng = fastrand::Rng::with_seed(options.seed);
+    println!("Seed {}", options.seed);
+    let mut output = BufWriter::new(File::create(&options.output)?);
+    if format == Format::Csv {
+        writeln!(output, "{}", CSV_HEADER)?;
+    }
+
+    let start = Instant::now();
+    let mut positions = 0;
+    for game in 1..=options.games {
+        for (size, win_length) in options.boards.iter().copied() {
+            let opening = engine::random_opening(size, win_length, options.opening, &rng);
+            let played = engine::play_game(&options.engine, &options.engine, size, win_length, &opening, &rng);
+            let (outcome, winner) = match played.outcome {
+                GameOutcome::XWins => ("x", Some(Tile::X)),
+                GameOutcome::OWins => ("o", Some(Tile::O)),
+                GameOutcome::Draw => ("draw", None),
+            };
+
+            let mut position = Position::empty(size);
+            let mut player = Tile::X;
+            for (ply, (coordinates, search)) in played.moves.iter().zip(played.searches.iter()).enumerate() {
+                if let Some(search) = search {
+                    let record = Record {
+                        game,
+                        ply,
+                        size,
+                        win_length,
+                        position: position.to_text(),
+                        side_to_move: if player == Tile::X { "x" } else { "o" },
+                        score: search.score,
+                        best_move: search.best_move.unwrap_or(*coordinates).notation(),
+                        outcome,
+                        result: match winner {
+                            Some(winner) if winner == player => 1.0,
+                            Some(_) => 0.0,
+                            None => 0.5,
+                        },
+                    };
+                    write_record(&mut output, &record, format)?;
+                    positions += 1;
+                }
+                position.set(position.square(*coordinates), player);
+                player = player.opponent();
+            }
+        }
+        if game % REPORT_INTERVAL == 0 || game == options.games {
+            println!("{:>6} games  {} positions  after {:.1?}", game, positions, start.elapsed());
+        }
+    }
+    output.flush()?;
+    println!("Written to {}", options.output.display());
+    Ok(())
+}
+
+fn write_record(output: &mut impl Write, record: &Record, format: Format) -> io::Result<()> {
+    match format {
+        Format::JsonLines => writeln!(output, "{}", serde_json::to_string(record)?),
+        // No field holds a comma or a quote
+        Format::Csv => writeln!(output, "{},{},{},{},{},{},{},{},{},{}", record.game, record.ply, record.size,
+                                record.win_length, record.position, record.side_to_move, record.score,
+                                record.best_move, record.outcome, record.result),
+    }
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        engine: EngineConfig::parse("minimax,depth=3,temperature=25")?,
+        games: 100,
+        boards: vec![(7, 4)],
+        opening: 4,
+        seed: fastrand::u64(..),
+        output: PathBuf::from("selfplay.jsonl"),
+        format: None,
+    };
+    let mut depth = None;
+    let args: Vec<String> = env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = pair.get(1).ok_or_else(|| format!("missing value after {}", pair[0]))?;
+        let invalid = || format!("invalid value for {}: \"{}\"", pair[0], value);
+        match pair[0].as_str() {
+            "--engine" => options.engine = EngineConfig::parse(value)?,
+            "--depth" => depth = Some(value.parse().map_err(|_| invalid())?),
+            "--games" => options.games = value.parse().map_err(|_| invalid())?,
+            "--opening" => options.opening = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--output" => options.output = PathBuf::from(value),
+            "--format" => options.format = Some(match value.as_str() {
+                "csv" => Format::Csv,
+                "jsonl" => Format::JsonLines,
+                _ => return Err(invalid()),
+            }),
+            "--boards" => options.boards = value.split(',')
+                .map(|board| engine::parse_board(board).ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+            option => return Err(format!("unknown option {}", option)),
+        }
+    }
+    // The depth applies whatever order the options came in
+    if let Some(depth) = depth {
+        options.engine.depth = depth;
+    }
+    Ok(options)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: datagen [OPTIONS]");
+    eprintln!("  --engine SPEC     engine playing both sides (default minimax,depth=3,temperature=25)");
+    eprintln!("  --depth N         search depth of the engine");
+    eprintln!("  --games N         games per board (default 100)");
+    eprintln!("  --boards LIST     boards to play on, e.g. 7x7k4,8x8k5 (default 7x7k4)");
+    eprintln!("  --opening PLIES   random moves played before the engine takes over (default 4)");
+    eprintln!("  --seed N          seed of the random openings");
+    eprintln!("  --output FILE     file to write (default selfplay.jsonl)");
+    eprintln!("  --format FORMAT   csv or jsonl (default from the extension of the file)");
+    process::exit(2);
+}
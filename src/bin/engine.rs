@@ -0,0 +1,134 @@
+// The built-in AI as an engine speaking the engine protocol (see the
+// `protocol` module) on its standard input and output, so that it can play
+// as an external player or in other programs. The settings are given as in
+// the tournament runner, e.g. `engine minimax,depth=6,threads=4`.
+// Run with `cargo run --release --bin engine -- [SPEC]`.
+use std::{env, io::{self, BufRead}, process, thread::{self, JoinHandle}};
+
+use tic_tac_toe::{Coordinates, Tile, TileMap, ai::SearchProgress, bitboard::{self, Position},
+                  engine::{EngineConfig, EngineMove}, mcts, protocol::{Command, Limit, Reply}};
+
+// The search running in the background
+struct Search {
+    progress: SearchProgress,
+    thread: JoinHandle<()>,
+}
+
+impl Search {
+    fn stop(self) {
+        self.progress.stop();
+        let _ = self.thread.join();
+    }
+}
+
+fn main() {
+    let config = match env::args().nth(1) {
+        Some(spec) => EngineConfig::parse(&spec).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            eprintln!("Usage: engine [SPEC], e.g. minimax,depth=6 or mcts,playout=random");
+            process::exit(2);
+        }),
+        None => EngineConfig::default(),
+    };
+
+    let (mut size, mut win_length) = (3, 3);
+    let mut tile_map = TileMap::empty(size);
+    let mut search: Option<Search> = None;
+    for line in io::stdin().lock().lines() {
+        let Ok(line) = line else { break };
+        let Some(command) = Command::parse(&line) else {
+            eprintln!("Unknown command: {}", line);
+            continue;
+        };
+        match command {
+            Command::Hello(_) => println!("{}", Reply::Hello(format!("tic_tac_toe {:?}", config.engine))),
+            Command::IsReady => println!("{}", Reply::ReadyOk),
+            Command::NewGame { size: new_size, win_length: new_win_length } => {
+                if new_size > bitboard::MAX_SIZE || !(1..=new_size).contains(&new_win_length) {
+                    eprintln!("Unsupported board: {}", line);
+                    continue;
+                }
+                (size, win_length) = (new_size, new_win_length);
+                tile_map = TileMap::empty(size);
+            }
+            Command::Board(position) if position.size == size => tile_map = position.to_tile_map(),
+            Command::Moves(moves) => match play_moves(size, &moves) {
+                Some(played) => tile_map = played,
+                None => eprintln!("Moves that cannot be played: {}", line),
+            },
+            Command::Go(limit) => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+                search = Some(start_search(&config, &tile_map, win_length, limit));
+            }
+            Command::Stop => {
+                if let Some(search) = search.take() {
+                    search.stop();
+                }
+            }
+            Command::Quit => break,
+            Command::Board(_) => eprintln!("The board does not match the game: {}", line),
+        }
+    }
+    if let Some(search) = search.take() {
+        search.stop();
+    }
+}
+
+// The board after `moves` from the empty board, if they can all be played
+fn play_moves(size: u32, moves: &[Coordinates]) -> Option<TileMap> {
+    let mut tile_map = TileMap::empty(size);
+    let mut player = Tile::X;
+    for coordinates in moves.iter() {
+        let tile = tile_map.0.get_mut(coordinates.x as usize)?.get_mut(coordinates.y as usize)?;
+        if *tile != Tile::Empty {
+            return None;
+        }
+        *tile = player;
+        player = player.opponent();
+    }
+    Some(tile_map)
+}
+
+// Search the side to move on a thread of its own, which writes the result
+// and the best move once done
+fn start_search(config: &EngineConfig, tile_map: &TileMap, win_length: u32, limit: Limit) -> Search {
+    let mut config = *config;
+    let tile_map = tile_map.clone();
+    let progress = SearchProgress::default();
+    match limit {
+        Limit::MoveTime(time) => {
+            // Deepen until the time is up
+            config.depth = (tile_map.0.len() * tile_map.0.len()) as u32;
+            config.budget = mcts::Budget::Time(time);
+            let progress = progress.clone();
+            thread::spawn(move || {
+                thread::sleep(time);
+                progress.stop();
+            });
+        }
+        Limit::Depth(depth) => config.depth = depth,
+        Limit::None => {}
+    }
+
+    let thread_progress = progress.clone();
+    let thread = thread::spawn(move || {
+        let player = Position::from_tile_map(&tile_map).side_to_move();
+        if tile_map.outcome(win_length).is_some() {
+            println!("{}", Reply::BestMove(None));
+            return;
+        }
+        let EngineMove { played, search } = config.search(&tile_map, player, win_length, &fastrand::Rng::new(),
+                                                          &thread_progress);
+        // A search stopped before it found a move still answers with one,
+        // the first it would have tried
+        let played = played.or_else(|| {
+            let position = Position::from_tile_map(&tile_map);
+            position.candidate_moves().first().map(|square| position.coordinates(*square))
+        });
+        println!("{}", Reply::Info { depth: search.depth, score: search.score, pv: search.pv });
+        println!("{}", Reply::BestMove(played));
+    });
+    Search { progress, thread }
+}
@@ -0,0 +1,100 @@
+// Train the learned evaluator with TD(λ) on games it plays against itself
+// and write it to the file read by the AI. Training goes on from the file
+// when it already exists.
+// Run with `cargo run --release --bin learn -- [OPTIONS]`.
+use std::{env, path::PathBuf, process, time::Instant};
+
+use tic_tac_toe::{constants, GameOutcome, engine, history::ResultCount, learned::{Training, ValueFunction}};
+
+// Games between two reports of the results, after which the file is saved
+const REPORT_INTERVAL: u32 = 1000;
+
+struct Options {
+    training: Training,
+    games: u32,
+    boards: Vec<(u32, u32)>,
+    seed: u64,
+    output: PathBuf,
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        usage();
+    });
+    let rng = fastrand::Rng::with_seed(options.seed);
+    println!("Seed {}", options.seed);
+
+    let mut value_function = match ValueFunction::load(&options.output) {
+        Ok(value_function) => {
+            println!("Continuing from {} after {} games", options.output.display(), value_function.games);
+            value_function
+        }
+        Err(_) => ValueFunction::default(),
+    };
+
+    let start = Instant::now();
+    // Results from X's side since the last report
+    let mut count = ResultCount::default();
+    for game in 1..=options.games {
+        let (size, win_length) = options.boards[game as usize % options.boards.len()];
+        match value_function.train_game(size, win_length, &options.training, &rng) {
+            GameOutcome::XWins => count.wins += 1,
+            GameOutcome::Draw => count.draws += 1,
+            GameOutcome::OWins => count.losses += 1,
+        }
+
+        if game % REPORT_INTERVAL == 0 || game == options.games {
+            println!("{:>7} games  X +{} ={} -{}  after {:.1?}", game, count.wins, count.draws, count.losses,
+                     start.elapsed());
+            count = ResultCount::default();
+            if let Err(error) = value_function.save(&options.output) {
+                eprintln!("Could not write {}: {}", options.output.display(), error);
+                process::exit(1);
+            }
+        }
+    }
+    println!("Weights {:?}", value_function.weights);
+    println!("Written to {}", options.output.display());
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        training: Training { lambda: 0.7, rate: 0.01, exploration: 0.1 },
+        games: 20000,
+        boards: vec![(7, 4)],
+        seed: fastrand::u64(..),
+        output: PathBuf::from(constants::LEARNED_FILE),
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = pair.get(1).ok_or_else(|| format!("missing value after {}", pair[0]))?;
+        let invalid = || format!("invalid value for {}: \"{}\"", pair[0], value);
+        match pair[0].as_str() {
+            "--games" => options.games = value.parse().map_err(|_| invalid())?,
+            "--lambda" => options.training.lambda = value.parse().map_err(|_| invalid())?,
+            "--rate" => options.training.rate = value.parse().map_err(|_| invalid())?,
+            "--exploration" => options.training.exploration = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--output" => options.output = PathBuf::from(value),
+            "--boards" => options.boards = value.split(',')
+                .map(|board| engine::parse_board(board).ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+            option => return Err(format!("unknown option {}", option)),
+        }
+    }
+    Ok(options)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: learn [OPTIONS]");
+    eprintln!("  --games N          games to learn from (default 20000)");
+    eprintln!("  --boards LIST      boards to play on in turn, e.g. 7x7k4,8x8k5 (default 7x7k4)");
+    eprintln!("  --lambda L         decay of the eligibility traces (default 0.7)");
+    eprintln!("  --rate R           learning rate (default 0.01)");
+    eprintln!("  --exploration P    share of moves played at random (default 0.1)");
+    eprintln!("  --seed N           seed of the random moves");
+    eprintln!("  --output FILE      where the evaluator is read from and written (default {})",
+              constants::LEARNED_FILE);
+    process::exit(2);
+}
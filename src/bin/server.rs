@@ -0,0 +1,377 @@
+// A game server without a window. Players connect from the LAN screen of the
+// game with "Find game" and are paired with the next player asking for the
+// same board size and win length. The server checks every move against its
+// own board, keeps the clocks and appends every finished game to a results
+// file. See the `net` module for the messages.
+// Run with `cargo run --release --bin server -- [OPTIONS]`.
+use std::{collections::HashMap, env, fs::OpenOptions, io::Write, process, thread, time::{Duration, Instant}};
+
+use serde::Serialize;
+use tic_tac_toe::{GameOutcome, Tile, TileMap, constants, history, net::{self, Connection, Listener, Message, PROTOCOL_VERSION}};
+
+// How often the connections are looked at
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+// How long a new connection may take to ask for a game
+const GREETING_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_NAME_LENGTH: usize = 24;
+
+struct Options {
+    port: u16,
+    // Time for all the moves of one side, if games are played on the clock
+    time: Option<Duration>,
+    // Added to the clock of a side after each of its moves
+    increment: Duration,
+    results: String,
+}
+
+// A connected player
+struct Client {
+    connection: Connection,
+    name: String,
+}
+
+// One line of the results file
+#[derive(Serialize)]
+struct ResultRecord<'a> {
+    date: String,
+    x: &'a str,
+    o: &'a str,
+    board_size: u32,
+    win_length: u32,
+    // None for abandoned games
+    result: Option<GameOutcome>,
+    // Why the game ended when the board did not decide it
+    reason: Option<&'a str>,
+    moves: &'a [String],
+}
+
+// A game between two clients, kept after it ends in case they want a rematch
+struct Game {
+    // X first
+    players: [Client; 2],
+    size: u32,
+    win_length: u32,
+    tile_map: TileMap,
+    moves: Vec<String>,
+    // Time left to X and O, and when the side to move started thinking
+    clocks: Option<[Duration; 2]>,
+    turn_started: Instant,
+    finished: bool,
+    // The player who offered a draw and the number of moves on the board
+    // then; the offer stands until the other answers or a move is played
+    draw_offer: Option<(usize, usize)>,
+    rematch: [bool; 2],
+    // Whether a player left, so that the game can be dropped
+    closed: bool,
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        usage();
+    });
+    let listener = Listener::bind(options.port).unwrap_or_else(|error| {
+        eprintln!("Could not listen on port {}: {}", options.port, error);
+        process::exit(1);
+    });
+    let address = net::local_address().map_or("this machine".to_string(), |address| address.to_string());
+    println!("Listening on {}:{}", address, options.port);
+
+    let mut arriving: Vec<(Connection, Instant)> = Vec::new();
+    // The player waiting for an opponent on each board
+    let mut waiting: HashMap<(u32, u32), Client> = HashMap::new();
+    let mut games: Vec<Game> = Vec::new();
+    loop {
+        while let Ok(Some(connection)) = listener.accept() {
+            println!("{} connected", connection.peer());
+            arriving.push((connection, Instant::now()));
+        }
+        greet(&mut arriving, &mut waiting, &mut games, &options);
+
+        waiting.retain(|_, client| still_waiting(client));
+
+        for game in games.iter_mut() {
+            game.update(&options);
+        }
+        games.retain(|game| !game.closed);
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Queue the players asking for a game, and start one as soon as two want the
+// same board
+fn greet(arriving: &mut Vec<(Connection, Instant)>, waiting: &mut HashMap<(u32, u32), Client>,
+         games: &mut Vec<Game>, options: &Options) {
+    let mut index = 0;
+    while index < arriving.len() {
+        let (connection, arrived) = &arriving[index];
+        let request = match connection.try_receive() {
+            Ok(None) if arrived.elapsed() < GREETING_TIMEOUT => {
+                index += 1;
+                continue;
+            }
+            Ok(Some(Message::Seek { version, size, win_length, name })) if version == PROTOCOL_VERSION => {
+                match net::playable(size, win_length) {
+                    true => Ok((size, win_length, name)),
+                    false => Err(format!("a {}x{} board with {} in a row cannot be played", size, size, win_length)),
+                }
+            }
+            Ok(Some(Message::Seek { .. } | Message::Hello { .. } | Message::Watch { .. })) => {
+                Err(format!("this is a game server speaking version {}; find a game to play", PROTOCOL_VERSION))
+            }
+            Ok(_) => Err("no game was asked for".to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        let (connection, _) = arriving.remove(index);
+        match request {
+            Ok((size, win_length, name)) => {
+                let name: String = name.chars().filter(|c| !c.is_control()).take(MAX_NAME_LENGTH).collect();
+                let client = Client { connection, name };
+                match waiting.remove(&(size, win_length)).filter(still_waiting) {
+                    Some(opponent) => games.push(Game::start([opponent, client], size, win_length, options)),
+                    None => {
+                        println!("{} waits for a {}x{}k{} game", client.name, size, size, win_length);
+                        if client.connection.send(&Message::Waiting).is_ok() {
+                            waiting.insert((size, win_length), client);
+                        }
+                    }
+                }
+            }
+            Err(reason) => {
+                println!("{} turned away: {}", connection.peer(), reason);
+                let _ = connection.send(&Message::Reject { reason });
+            }
+        }
+    }
+}
+
+// Waiting players can only leave, by sending anything or by closing the
+// connection
+fn still_waiting(client: &Client) -> bool {
+    let stays = matches!(client.connection.try_receive(), Ok(None));
+    if !stays {
+        println!("{} stopped waiting", client.name);
+    }
+    stays
+}
+
+fn side_name(index: usize) -> &'static str {
+    if index == 0 { "X" } else { "O" }
+}
+
+// The outcome when the player at `index` wins
+fn win_for(index: usize) -> GameOutcome {
+    if index == 0 { GameOutcome::XWins } else { GameOutcome::OWins }
+}
+
+impl Game {
+    fn start(players: [Client; 2], size: u32, win_length: u32, options: &Options) -> Self {
+        let mut game = Game {
+            players,
+            size,
+            win_length,
+            tile_map: TileMap::empty(size),
+            moves: Vec::new(),
+            clocks: None,
+            turn_started: Instant::now(),
+            finished: false,
+            draw_offer: None,
+            rematch: [false; 2],
+            closed: false,
+        };
+        game.new_game(options);
+        game
+    }
+
+    // Tell both players their side and start the clocks
+    fn new_game(&mut self, options: &Options) {
+        println!("{} (X) vs {} (O) on {}x{}k{}", self.players[0].name, self.players[1].name,
+                 self.size, self.size, self.win_length);
+        self.tile_map = TileMap::empty(self.size);
+        self.moves.clear();
+        self.clocks = options.time.map(|time| [time; 2]);
+        self.turn_started = Instant::now();
+        self.finished = false;
+        self.draw_offer = None;
+        self.rematch = [false; 2];
+        for (player, side) in self.players.iter().zip([Tile::X, Tile::O]) {
+            let _ = player.connection.send(&Message::Welcome {
+                version: PROTOCOL_VERSION,
+                size: self.size,
+                win_length: self.win_length,
+                side,
+            });
+        }
+        self.send_clocks();
+    }
+
+    fn send_clocks(&self) {
+        if let Some([x, o]) = self.clocks {
+            self.send_both(&Message::Clock { x: x.as_millis() as u64, o: o.as_millis() as u64 });
+        }
+    }
+
+    fn send_both(&self, message: &Message) {
+        for player in self.players.iter() {
+            let _ = player.connection.send(message);
+        }
+    }
+
+    fn update(&mut self, options: &Options) {
+        for index in 0..2 {
+            loop {
+                let message = match self.players[index].connection.try_receive() {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(_) => Message::Bye,
+                };
+                self.receive(index, message, options);
+                if self.closed {
+                    return;
+                }
+            }
+        }
+
+        // The side to move loses once its time is up
+        if let (false, Some(clocks)) = (self.finished, self.clocks) {
+            let side = self.moves.len() % 2;
+            if self.turn_started.elapsed() >= clocks[side] {
+                let reason = format!("{} lost on time", side_name(side));
+                self.finish(Some(win_for(1 - side)), Some(reason), options);
+            }
+        }
+    }
+
+    // Act on a message of the player at `index`
+    fn receive(&mut self, index: usize, message: Message, options: &Options) {
+        let opponent = 1 - index;
+        match message {
+            Message::Move { .. } if self.finished => {}
+            Message::Move { ply, notation } => {
+                let side = if index == 0 { Tile::X } else { Tile::O };
+                match net::validate_move(&self.tile_map, self.win_length, self.moves.len(), side, ply, &notation) {
+                    Ok(coordinates) => {
+                        self.tile_map.0[coordinates.x as usize][coordinates.y as usize] = side;
+                        self.moves.push(coordinates.notation());
+                        if let Some(clocks) = self.clocks.as_mut() {
+                            clocks[index] = clocks[index].saturating_sub(self.turn_started.elapsed()) + options.increment;
+                        }
+                        self.turn_started = Instant::now();
+                        let _ = self.players[opponent].connection.send(&Message::Move { ply, notation: coordinates.notation() });
+                        self.send_clocks();
+                        if let Some(outcome) = self.tile_map.outcome(self.win_length) {
+                            self.finish(Some(outcome), None, options);
+                        }
+                    }
+                    Err(error) => {
+                        let reason = format!("{} sent a wrong move: {}", side_name(index), error);
+                        self.finish(Some(win_for(opponent)), Some(reason), options);
+                    }
+                }
+            }
+            Message::Resign if !self.finished => {
+                let _ = self.players[opponent].connection.send(&Message::Resign);
+                self.finish(Some(win_for(opponent)), Some(format!("{} resigned", side_name(index))), options);
+            }
+            // Offers and answers that crossed a move are dropped. Both players
+            // learn of an accepted draw from `Ended`.
+            Message::OfferDraw { ply } if !self.finished && ply == self.moves.len() => {
+                self.draw_offer = Some((index, ply));
+                let _ = self.players[opponent].connection.send(&Message::OfferDraw { ply });
+            }
+            Message::AcceptDraw { ply }
+                if !self.finished && ply == self.moves.len() && self.draw_offer == Some((opponent, ply)) => {
+                self.finish(Some(GameOutcome::Draw), Some(constants::DRAW_AGREED_STRING.to_string()), options);
+            }
+            Message::DeclineDraw { ply } if self.draw_offer == Some((opponent, ply)) => {
+                self.draw_offer = None;
+                let _ = self.players[opponent].connection.send(&Message::DeclineDraw { ply });
+            }
+            // A new game starts with the sides swapped once both asked for it
+            Message::Rematch if self.finished && !self.rematch[index] => {
+                self.rematch[index] = true;
+                let _ = self.players[opponent].connection.send(&Message::Rematch);
+                if self.rematch == [true; 2] {
+                    self.players.swap(0, 1);
+                    self.new_game(options);
+                }
+            }
+            Message::Chat { text } => {
+                let text = text.chars().filter(|c| !c.is_control()).take(constants::CHAT_MAX_LENGTH).collect();
+                let _ = self.players[opponent].connection.send(&Message::Chat { text });
+            }
+            Message::Bye => {
+                if !self.finished {
+                    self.finish(Some(win_for(opponent)), Some(format!("{} left", side_name(index))), options);
+                }
+                println!("{} left", self.players[index].name);
+                let _ = self.players[opponent].connection.send(&Message::Bye);
+                self.closed = true;
+            }
+            _ => {}
+        }
+    }
+
+    // Record the result. The players are told unless the board decided it,
+    // which they see for themselves.
+    fn finish(&mut self, outcome: Option<GameOutcome>, reason: Option<String>, options: &Options) {
+        self.finished = true;
+        if let Some(reason) = &reason {
+            self.send_both(&Message::Ended { outcome, reason: reason.clone() });
+        }
+        let record = ResultRecord {
+            date: history::current_date(),
+            x: &self.players[0].name,
+            o: &self.players[1].name,
+            board_size: self.size,
+            win_length: self.win_length,
+            result: outcome,
+            reason: reason.as_deref(),
+            moves: &self.moves,
+        };
+        println!("{} (X) vs {} (O): {:?}{}", record.x, record.o, outcome,
+                 reason.as_ref().map(|reason| format!(", {}", reason)).unwrap_or_default());
+        if let Err(error) = append_result(&options.results, &record) {
+            eprintln!("Could not write {}: {}", options.results, error);
+        }
+    }
+}
+
+fn append_result(path: &str, record: &ResultRecord) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(record)?)
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        port: constants::DEFAULT_PORT,
+        time: None,
+        increment: Duration::ZERO,
+        results: constants::SERVER_RESULTS_FILE.to_string(),
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = pair.get(1).ok_or_else(|| format!("missing value after {}", pair[0]))?;
+        let invalid = || format!("invalid value for {}: \"{}\"", pair[0], value);
+        match pair[0].as_str() {
+            "--port" => options.port = value.parse().map_err(|_| invalid())?,
+            "--time" => options.time = match value.parse().map_err(|_| invalid())? {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            },
+            "--increment" => options.increment = Duration::from_secs(value.parse().map_err(|_| invalid())?),
+            "--results" => options.results = value.clone(),
+            option => return Err(format!("unknown option {}", option)),
+        }
+    }
+    Ok(options)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: server [OPTIONS]");
+    eprintln!("  --port N          port to listen on (default {})", constants::DEFAULT_PORT);
+    eprintln!("  --time SECONDS    time for all the moves of a side, 0 for no clock (default 0)");
+    eprintln!("  --increment SECONDS  added to a clock after each move (default 0)");
+    eprintln!("  --results FILE    where finished games are appended (default {})", constants::SERVER_RESULTS_FILE);
+    process::exit(2);
+}
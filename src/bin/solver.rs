@@ -0,0 +1,73 @@
+// Build and check the databases of solved positions used by the AI.
+// Run with `cargo run --release --bin solver -- generate|verify [SIZE WIN_LENGTH]`;
+// without a size every small board is handled.
+use std::{env, process, time::Instant};
+
+use tic_tac_toe::{bitboard::Position, solver::{self, Database, Outcome}};
+
+// Board size and win length of the databases built by default
+const BOARDS: [(u32, u32); 3] = [(3, 3), (4, 3), (4, 4)];
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let boards = match args.len() {
+        1 => BOARDS.to_vec(),
+        3 => match (args[1].parse(), args[2].parse()) {
+            (Ok(size), Ok(win_length)) if size <= solver::MAX_SIZE && (1..=size).contains(&win_length) => {
+                vec![(size, win_length)]
+            }
+            _ => usage(),
+        },
+        _ => usage(),
+    };
+
+    let mut failed = false;
+    for (size, win_length) in boards {
+        let path = solver::path(size, win_length);
+        match args[0].as_str() {
+            "generate" => {
+                let start = Instant::now();
+                let database = Database::solve(size, win_length);
+                if let Err(error) = database.save(&path) {
+                    eprintln!("Could not write {}: {}", path.display(), error);
+                    process::exit(1);
+                }
+                let root = database.get(&Position::empty(size)).unwrap();
+                println!("{}x{} k{}: {} positions in {:.1?}, {} in {} moves, written to {}",
+                         size, size, win_length, database.len(), start.elapsed(),
+                         describe(root.outcome), root.distance, path.display());
+            }
+            "verify" => match Database::load(&path, size, win_length) {
+                Ok(database) => match database.verify() {
+                    Ok(count) => println!("{}: {} positions consistent", path.display(), count),
+                    Err(error) => {
+                        eprintln!("{}: {}", path.display(), error);
+                        failed = true;
+                    }
+                },
+                Err(error) => {
+                    eprintln!("Could not read {}: {}", path.display(), error);
+                    failed = true;
+                }
+            },
+            _ => usage(),
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn describe(outcome: Outcome) -> &'static str {
+    match outcome {
+        Outcome::Win => "first player wins",
+        Outcome::Draw => "draw",
+        Outcome::Loss => "second player wins",
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: solver generate|verify [SIZE WIN_LENGTH]");
+    eprintln!("SIZE is at most {}", solver::MAX_SIZE);
+    process::exit(2);
+}
@@ -0,0 +1,219 @@
+// Play two engine configurations against each other without a window and
+// estimate the Elo difference between them. Every random opening is played
+// twice with the colours swapped.
+// Run with `cargo run --release --bin tournament -- [OPTIONS]`.
+use std::{env, process, time::Instant};
+
+use tic_tac_toe::{GameOutcome, engine::{self, EngineConfig}, history::ResultCount};
+
+// Bounds of the 95% confidence interval in standard errors
+const CONFIDENCE_Z: f64 = 1.96;
+
+// Error rates of the sequential probability ratio test
+const SPRT_ALPHA: f64 = 0.05;
+const SPRT_BETA: f64 = 0.05;
+
+struct Options {
+    a: EngineConfig,
+    b: EngineConfig,
+    games: u32,
+    boards: Vec<(u32, u32)>,
+    opening: u32,
+    seed: u64,
+    // Elo differences of the two hypotheses of the SPRT
+    sprt: Option<(f64, f64)>,
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        usage();
+    });
+    let rng = fastrand::Rng::with_seed(options.seed);
+    println!("Seed {}", options.seed);
+
+    let start = Instant::now();
+    let mut boards = vec![ResultCount::default(); options.boards.len()];
+    let mut total = ResultCount::default();
+    'pairs: for _ in 0..options.games.div_ceil(2) {
+        for ((size, win_length), count) in options.boards.iter().zip(boards.iter_mut()) {
+            let opening = engine::random_opening(*size, *win_length, options.opening, &rng);
+            for a_plays_x in [true, false] {
+                let (x, o) = if a_plays_x { (&options.a, &options.b) } else { (&options.b, &options.a) };
+                let game = engine::play_game(x, o, *size, *win_length, &opening, &rng);
+                // Counted from A's side
+                let a_won = match game.outcome {
+                    GameOutcome::Draw => None,
+                    outcome => Some((outcome == GameOutcome::XWins) == a_plays_x),
+                };
+                for count in [&mut *count, &mut total] {
+                    match a_won {
+                        Some(true) => count.wins += 1,
+                        Some(false) => count.losses += 1,
+                        None => count.draws += 1,
+                    }
+                }
+            }
+        }
+
+        print!("{:>5} games  {}", total.total(), describe(&total));
+        if let Some((elo0, elo1)) = options.sprt {
+            let (llr, (lower, upper)) = (log_likelihood_ratio(&total, elo0, elo1), sprt_bounds());
+            println!("  LLR {:.2} [{:.2}, {:.2}]", llr, lower, upper);
+            if llr <= lower || llr >= upper {
+                let accepted = if llr >= upper { elo1 } else { elo0 };
+                println!("SPRT stopped: Elo {:+} accepted", accepted);
+                break 'pairs;
+            }
+        } else {
+            println!();
+        }
+    }
+
+    println!("\nFinished in {:.1?}", start.elapsed());
+    for ((size, win_length), count) in options.boards.iter().zip(boards.iter()) {
+        println!("{}x{} k{}: {}", size, size, win_length, describe(count));
+    }
+    println!("Total: {}", describe(&total));
+}
+
+// Wins, draws and losses of A, with its Elo difference to B
+fn describe(count: &ResultCount) -> String {
+    let (elo, lower, upper) = elo_interval(count);
+    format!("+{} ={} -{}  Elo {:+.1} [{:+.1}, {:+.1}]", count.wins, count.draws, count.losses, elo, lower, upper)
+}
+
+// Points per game, a draw counting half
+fn mean_score(count: &ResultCount) -> f64 {
+    (count.wins as f64 + count.draws as f64 / 2.0) / count.total().max(1) as f64
+}
+
+// Variance of the points of one game
+fn score_variance(count: &ResultCount) -> f64 {
+    let mean = mean_score(count);
+    let games = count.total().max(1) as f64;
+    (count.wins as f64 * (1.0 - mean).powi(2)
+        + count.draws as f64 * (0.5 - mean).powi(2)
+        + count.losses as f64 * mean.powi(2)) / games
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Elo difference and its 95% confidence interval
+fn elo_interval(count: &ResultCount) -> (f64, f64, f64) {
+    let mean = mean_score(count);
+    let error = (score_variance(count) / count.total().max(1) as f64).sqrt();
+    (elo_from_score(mean),
+     elo_from_score(mean - CONFIDENCE_Z * error),
+     elo_from_score(mean + CONFIDENCE_Z * error))
+}
+
+// Log-likelihood ratio of A being `elo1` rather than `elo0` stronger, with
+// the normal approximation of the game scores. While every game has had the
+// same result the variance is taken as if one more had been won and one lost.
+fn log_likelihood_ratio(count: &ResultCount, elo0: f64, elo1: f64) -> f64 {
+    let variance = match score_variance(count) {
+        variance if variance > 0.0 => variance,
+        _ => score_variance(&ResultCount { wins: count.wins + 1, draws: count.draws, losses: count.losses + 1 }),
+    };
+    let (score0, score1) = (score_from_elo(elo0), score_from_elo(elo1));
+    count.total() as f64 * (score1 - score0) * (2.0 * mean_score(count) - score0 - score1) / (2.0 * variance)
+}
+
+fn sprt_bounds() -> (f64, f64) {
+    ((SPRT_BETA / (1.0 - SPRT_ALPHA)).ln(), ((1.0 - SPRT_BETA) / SPRT_ALPHA).ln())
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        a: EngineConfig::parse("minimax")?,
+        b: EngineConfig::parse("mcts")?,
+        games: 100,
+        boards: vec![(7, 4)],
+        opening: 2,
+        seed: fastrand::u64(..),
+        sprt: None,
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = pair.get(1).ok_or_else(|| format!("missing value after {}", pair[0]))?;
+        let invalid = || format!("invalid value for {}: \"{}\"", pair[0], value);
+        match pair[0].as_str() {
+            "--a" => options.a = EngineConfig::parse(value)?,
+            "--b" => options.b = EngineConfig::parse(value)?,
+            "--games" => options.games = value.parse().map_err(|_| invalid())?,
+            "--opening" => options.opening = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--boards" => options.boards = value.split(',')
+                .map(|board| engine::parse_board(board).ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+            "--sprt" => options.sprt = Some(value.split_once(',')
+                .and_then(|(elo0, elo1)| Some((elo0.parse().ok()?, elo1.parse().ok()?)))
+                .ok_or_else(invalid)?),
+            option => return Err(format!("unknown option {}", option)),
+        }
+    }
+    Ok(options)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: tournament [OPTIONS]");
+    eprintln!("  --a SPEC          engine A, e.g. minimax,depth=5 (default minimax)");
+    eprintln!("  --b SPEC          engine B, e.g. mcts,iterations=5000 (default mcts)");
+    eprintln!("  --games N         games per board (default 100)");
+    eprintln!("  --boards LIST     boards to play on, e.g. 3x3k3,7x7k4 (default 7x7k4)");
+    eprintln!("  --opening PLIES   random moves played before the engines take over (default 2)");
+    eprintln!("  --seed N          seed of the random openings");
+    eprintln!("  --sprt ELO0,ELO1  stop once A is shown to be ELO0 or ELO1 stronger");
+    process::exit(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(wins: u32, draws: u32, losses: u32) -> ResultCount {
+        ResultCount { wins, draws, losses }
+    }
+
+    #[test]
+    fn elo_follows_the_mean_score() {
+        assert!(elo_from_score(0.5).abs() < 1e-9);
+        assert!((elo_from_score(0.75) - 190.85).abs() < 0.01);
+        assert!((elo_from_score(0.25) + 190.85).abs() < 0.01);
+        assert!((score_from_elo(elo_from_score(0.6)) - 0.6).abs() < 1e-9);
+    }
+
+    #[test]
+    fn elo_interval_surrounds_the_estimate() {
+        let (elo, lower, upper) = elo_interval(&count(30, 40, 10));
+        assert!((elo - elo_from_score(0.625)).abs() < 1e-9);
+        assert!(lower < elo && elo < upper);
+        let (elo, lower, upper) = elo_interval(&count(10, 0, 10));
+        assert!(elo.abs() < 1e-9);
+        assert!((lower + upper).abs() < 1e-6);
+    }
+
+    #[test]
+    fn log_likelihood_ratio_sign_follows_the_mean_score() {
+        assert!(log_likelihood_ratio(&count(60, 20, 20), 0.0, 10.0) > 0.0);
+        assert!(log_likelihood_ratio(&count(20, 20, 60), 0.0, 10.0) < 0.0);
+        // Every game drawn or won is still finite
+        assert!(log_likelihood_ratio(&count(0, 10, 0), 0.0, 10.0).is_finite());
+        assert!(log_likelihood_ratio(&count(10, 0, 0), 0.0, 10.0) > 0.0);
+    }
+
+    #[test]
+    fn sprt_bounds_match_the_error_rates() {
+        let (lower, upper) = sprt_bounds();
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+    }
+}
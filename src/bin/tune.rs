@@ -0,0 +1,90 @@
+// Tune the evaluation weights on positions of self-play games with Texel's
+// method and write them to the weights file read by the AI.
+// Run with `cargo run --release --bin tune -- [OPTIONS]`.
+use std::{env, path::PathBuf, process, time::Instant};
+
+use tic_tac_toe::{constants, ai, engine::{self, EngineConfig}, tuner};
+
+struct Options {
+    engine: EngineConfig,
+    games: u32,
+    boards: Vec<(u32, u32)>,
+    opening: u32,
+    seed: u64,
+    output: PathBuf,
+}
+
+fn main() {
+    let options = parse_options().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        usage();
+    });
+    let rng = fastrand::Rng::with_seed(options.seed);
+    println!("Seed {}", options.seed);
+
+    let start = Instant::now();
+    let mut samples = Vec::new();
+    for (size, win_length) in options.boards.iter() {
+        samples.extend(tuner::self_play_samples(&options.engine, *size, *win_length, options.games,
+                                                options.opening, &rng));
+        println!("{}x{} k{}: {} positions after {:.1?}", size, size, win_length, samples.len(), start.elapsed());
+    }
+    if samples.is_empty() {
+        eprintln!("The games had no quiet positions to tune on");
+        process::exit(1);
+    }
+
+    // Start from the weights the AI currently uses
+    let initial = *ai::weights();
+    let scale = tuner::fit_scale(&samples, &initial);
+    println!("Sigmoid scale {:.1}, error {:.6}", scale, tuner::mean_squared_error(&samples, &initial, scale));
+    let weights = tuner::tune(&samples, initial, scale, |pass, error| {
+        println!("Pass {}: error {:.6} after {:.1?}", pass, error, start.elapsed());
+    });
+
+    println!("Segment weights {:?}, centre {}", weights.lines, weights.centre);
+    if let Err(error) = weights.save(&options.output) {
+        eprintln!("Could not write {}: {}", options.output.display(), error);
+        process::exit(1);
+    }
+    println!("Written to {}", options.output.display());
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut options = Options {
+        engine: EngineConfig::parse("minimax,depth=2,temperature=25")?,
+        games: 200,
+        boards: vec![(7, 4)],
+        opening: 2,
+        seed: fastrand::u64(..),
+        output: PathBuf::from(constants::WEIGHTS_FILE),
+    };
+    let args: Vec<String> = env::args().skip(1).collect();
+    for pair in args.chunks(2) {
+        let value = pair.get(1).ok_or_else(|| format!("missing value after {}", pair[0]))?;
+        let invalid = || format!("invalid value for {}: \"{}\"", pair[0], value);
+        match pair[0].as_str() {
+            "--engine" => options.engine = EngineConfig::parse(value)?,
+            "--games" => options.games = value.parse().map_err(|_| invalid())?,
+            "--opening" => options.opening = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--output" => options.output = PathBuf::from(value),
+            "--boards" => options.boards = value.split(',')
+                .map(|board| engine::parse_board(board).ok_or_else(invalid))
+                .collect::<Result<_, _>>()?,
+            option => return Err(format!("unknown option {}", option)),
+        }
+    }
+    Ok(options)
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: tune [OPTIONS]");
+    eprintln!("  --engine SPEC     engine playing the games (default minimax,depth=2,temperature=25)");
+    eprintln!("  --games N         games per board (default 200)");
+    eprintln!("  --boards LIST     boards to play on, e.g. 7x7k4,8x8k5 (default 7x7k4)");
+    eprintln!("  --opening PLIES   random moves played before the engine takes over (default 2)");
+    eprintln!("  --seed N          seed of the random openings");
+    eprintln!("  --output FILE     where the weights are written (default {})", constants::WEIGHTS_FILE);
+    process::exit(2);
+}
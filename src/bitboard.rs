@@ -0,0 +1,382 @@
+use std::sync::OnceLock;
+
+use crate::{Coordinates, Tile, TileMap};
+
+// Largest board whose tiles fit in a u64
+pub const MAX_SIZE: u32 = 8;
+
+// Only tiles this close to an existing piece are considered as moves
+const MOVE_RADIUS: i32 = 2;
+
+// A position with one bit per tile for each side: tile (x, y) is bit
+// x * size + y, which is called its square
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: u64,
+    pub o: u64,
+    pub size: u32,
+}
+
+impl Position {
+    pub fn empty(size: u32) -> Self {
+        assert!(size <= MAX_SIZE, "boards larger than {} tiles do not fit in a bitboard", MAX_SIZE);
+        Position { x: 0, o: 0, size }
+    }
+
+    pub fn from_tile_map(tile_map: &TileMap) -> Self {
+        let mut position = Position::empty(tile_map.0.len() as u32);
+        for (x, line) in tile_map.0.iter().enumerate() {
+            for (y, tile) in line.iter().enumerate() {
+                position.set(position.square(Coordinates { x: x as u16, y: y as u16 }), *tile);
+            }
+        }
+        position
+    }
+
+    pub fn to_tile_map(&self) -> TileMap {
+        let mut tile_map = TileMap::empty(self.size);
+        for square in 0..self.size * self.size {
+            let coordinates = self.coordinates(square);
+            tile_map.0[coordinates.x as usize][coordinates.y as usize] = self.get(square);
+        }
+        tile_map
+    }
+
+    // The board as text: the rows from row 1 on separated by '/', with 'x',
+    // 'o' and '.' for the tiles, e.g. "x../.o./..."
+    pub fn to_text(&self) -> String {
+        (0..self.size)
+            .map(|x| (0..self.size).map(|y| match self.get(x * self.size + y) {
+                Tile::X => 'x',
+                Tile::O => 'o',
+                Tile::Empty => '.',
+            }).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    // Read a board written by `to_text`
+    pub fn from_text(text: &str) -> Option<Self> {
+        let rows: Vec<&str> = text.split('/').collect();
+        let size = rows.len() as u32;
+        if size > MAX_SIZE || rows.iter().any(|row| row.len() != rows.len()) {
+            return None;
+        }
+        let mut position = Position::empty(size);
+        for (x, row) in rows.iter().enumerate() {
+            for (y, tile) in row.chars().enumerate() {
+                let tile = match tile {
+                    'x' => Tile::X,
+                    'o' => Tile::O,
+                    '.' => Tile::Empty,
+                    _ => return None,
+                };
+                position.set(x as u32 * size + y as u32, tile);
+            }
+        }
+        Some(position)
+    }
+
+    pub fn square(&self, coordinates: Coordinates) -> u32 {
+        coordinates.x as u32 * self.size + coordinates.y as u32
+    }
+
+    pub fn coordinates(&self, square: u32) -> Coordinates {
+        Coordinates { x: (square / self.size) as u16, y: (square % self.size) as u16 }
+    }
+
+    pub fn pieces(&self, tile: Tile) -> u64 {
+        match tile {
+            Tile::X => self.x,
+            Tile::O => self.o,
+            Tile::Empty => self.empty_squares(),
+        }
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.x | self.o
+    }
+
+    // Bits of every tile on the board
+    pub fn board_mask(&self) -> u64 {
+        match self.size * self.size {
+            64 => u64::MAX,
+            tiles => (1 << tiles) - 1,
+        }
+    }
+
+    pub fn empty_squares(&self) -> u64 {
+        self.board_mask() & !self.occupied()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.empty_squares() == 0
+    }
+
+    // X moves first, so O is to move when X has more pieces
+    pub fn side_to_move(&self) -> Tile {
+        if self.x.count_ones() > self.o.count_ones() { Tile::O } else { Tile::X }
+    }
+
+    pub fn get(&self, square: u32) -> Tile {
+        let bit = 1 << square;
+        if self.x & bit != 0 {
+            Tile::X
+        } else if self.o & bit != 0 {
+            Tile::O
+        } else {
+            Tile::Empty
+        }
+    }
+
+    // Place `tile` on `square`, or clear it with Tile::Empty
+    pub fn set(&mut self, square: u32, tile: Tile) {
+        let bit = 1 << square;
+        self.x &= !bit;
+        self.o &= !bit;
+        match tile {
+            Tile::X => self.x |= bit,
+            Tile::O => self.o |= bit,
+            Tile::Empty => (),
+        }
+    }
+
+    // Zobrist hash of the position: the same on every run, so that it can be
+    // stored in files
+    pub fn zobrist_key(&self) -> u64 {
+        let keys = zobrist_keys();
+        let pieces = squares(self.x).fold(0, |key, square| key ^ keys[square as usize][0]);
+        squares(self.o).fold(pieces, |key, square| key ^ keys[square as usize][1])
+            ^ keys[self.size as usize][2]
+    }
+
+    // Empty tiles near existing pieces, closest to the centre first. Falls
+    // back to every empty tile when none is close to a piece.
+    pub fn candidate_moves(&self) -> Vec<u32> {
+        let tables = board_tables(self.size);
+        let empty = self.empty_squares();
+        let near = squares(self.occupied()).fold(0, |near, square| near | tables.near[square as usize]);
+        let moves = if near & empty == 0 { empty } else { near & empty };
+        tables.centre_order.iter().copied().filter(|square| moves & (1 << square) != 0).collect()
+    }
+
+    // Whether `candidate_moves` leaves out some empty tile here or after
+    // further moves. Tiles near a piece stay near once more are played, so
+    // only an empty board where one piece is not near every tile needs a look
+    // past the current position.
+    pub fn prunes_moves(&self) -> bool {
+        let tables = board_tables(self.size);
+        let board = self.board_mask();
+        if self.occupied() == 0 {
+            return squares(board).any(|square| tables.near[square as usize] & board != board);
+        }
+        let near = squares(self.occupied()).fold(0, |near, square| near | tables.near[square as usize]);
+        self.empty_squares() & !near != 0
+    }
+}
+
+// Set bits of `mask`, lowest first
+pub fn squares(mut mask: u64) -> impl Iterator<Item = u32> {
+    std::iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let square = mask.trailing_zeros();
+        mask &= mask - 1;
+        Some(square)
+    })
+}
+
+// Random keys of an X and an O on each square, the third one of each square
+// is used for the board size. Generated with splitmix64 from a fixed seed.
+fn zobrist_keys() -> &'static [[u64; 3]] {
+    static KEYS: OnceLock<Vec<[u64; 3]>> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        let mut state: u64 = 0x7469_635f_7461_635f;
+        let mut next = || {
+            state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+        (0..MAX_SIZE * MAX_SIZE).map(|_| [next(), next(), next()]).collect()
+    })
+}
+
+// Every segment of `win_length` tiles in a row on a board
+pub struct Lines {
+    pub masks: Vec<u64>,
+    // Masks of the segments going through each square
+    through: Vec<Vec<u64>>,
+}
+
+impl Lines {
+    fn new(size: u32, win_length: u32) -> Self {
+        let (size, length) = (size as i32, win_length as i32);
+        let mut masks = Vec::new();
+        for x in 0..size {
+            for y in 0..size {
+                for (dx, dy) in [(0, 1), (1, 0), (1, 1), (1, -1)] {
+                    let (end_x, end_y) = (x + dx * (length - 1), y + dy * (length - 1));
+                    if end_x < 0 || end_y < 0 || end_x >= size || end_y >= size {
+                        continue;
+                    }
+                    masks.push((0..length).fold(0, |mask, step| {
+                        mask | 1 << ((x + dx * step) * size + y + dy * step)
+                    }));
+                }
+            }
+        }
+        let through = (0..size * size)
+            .map(|square| masks.iter().copied().filter(|mask| mask & (1 << square) != 0).collect())
+            .collect();
+        Lines { masks, through }
+    }
+
+    // Whether the piece just placed on `square` completes a segment
+    pub fn is_win(&self, pieces: u64, square: u32) -> bool {
+        self.through[square as usize].iter().any(|mask| pieces & mask == *mask)
+    }
+
+    pub fn has_win(&self, pieces: u64) -> bool {
+        self.masks.iter().any(|mask| pieces & mask == *mask)
+    }
+}
+
+// Line masks of a board size and win length, built once for every
+// combination the first time any is needed
+pub fn lines(size: u32, win_length: u32) -> &'static Lines {
+    static LINES: OnceLock<Vec<Vec<Lines>>> = OnceLock::new();
+    let lines = LINES.get_or_init(|| {
+        (0..=MAX_SIZE)
+            .map(|size| (0..=MAX_SIZE).map(|win_length| Lines::new(size, win_length.max(1))).collect())
+            .collect()
+    });
+    &lines[size as usize][win_length.min(MAX_SIZE) as usize]
+}
+
+// Tables used by move generation for one board size
+struct BoardTables {
+    // Squares within MOVE_RADIUS of each square
+    near: Vec<u64>,
+    // Every square, closest to the centre first
+    centre_order: Vec<u32>,
+}
+
+// Every square of a board, closest to the centre first
+pub fn centre_order(size: u32) -> &'static [u32] {
+    &board_tables(size).centre_order
+}
+
+fn board_tables(size: u32) -> &'static BoardTables {
+    static TABLES: OnceLock<Vec<BoardTables>> = OnceLock::new();
+    let tables = TABLES.get_or_init(|| (0..=MAX_SIZE as i32).map(|size| {
+        let square = |x: i32, y: i32| (x * size + y) as u32;
+        let near = (0..size * size).map(|index| {
+            let (x, y) = (index / size, index % size);
+            let mut mask = 0;
+            for nx in (x - MOVE_RADIUS).max(0)..=(x + MOVE_RADIUS).min(size - 1) {
+                for ny in (y - MOVE_RADIUS).max(0)..=(y + MOVE_RADIUS).min(size - 1) {
+                    mask |= 1 << square(nx, ny);
+                }
+            }
+            mask
+        }).collect();
+
+        let centre = (size - 1) as f32 / 2.0;
+        let distance = |square: &u32| {
+            let (x, y) = ((*square as i32 / size) as f32, (*square as i32 % size) as f32);
+            (x - centre).abs() + (y - centre).abs()
+        };
+        let mut centre_order: Vec<u32> = (0..(size * size) as u32).collect();
+        centre_order.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        BoardTables { near, centre_order }
+    }).collect());
+    &tables[size as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GameOutcome;
+
+    fn tile_map_of(size: u32, pieces: u64) -> TileMap {
+        Position { x: pieces, o: 0, size }.to_tile_map()
+    }
+
+    #[test]
+    fn line_masks_are_the_rows_the_board_scan_finds() {
+        for size in 1..=MAX_SIZE {
+            for win_length in 1..=size {
+                let lines = lines(size, win_length);
+                let runs = (size - win_length + 1) as usize;
+                assert_eq!(lines.masks.len(), 2 * size as usize * runs + 2 * runs * runs);
+                for mask in lines.masks.iter().copied() {
+                    assert_eq!(mask.count_ones(), win_length);
+                    assert_eq!(tile_map_of(size, mask).winner(win_length), Some(Tile::X));
+                    for square in squares(mask).filter(|_| win_length > 1) {
+                        assert_eq!(tile_map_of(size, mask & !(1 << square)).winner(win_length), None);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn is_win_agrees_with_the_outcome() {
+        let rng = fastrand::Rng::with_seed(35);
+        for size in 3..=MAX_SIZE {
+            for win_length in 3..=size.min(5) {
+                let lines = lines(size, win_length);
+                for _ in 0..50 {
+                    let mut position = Position::empty(size);
+                    let mut empty: Vec<u32> = (0..size * size).collect();
+                    let mut player = Tile::X;
+                    while !empty.is_empty() {
+                        let square = empty.swap_remove(rng.usize(..empty.len()));
+                        position.set(square, player);
+                        let outcome = position.to_tile_map().outcome(win_length);
+                        let won = lines.is_win(position.pieces(player), square);
+                        assert_eq!(won, outcome == Some(GameOutcome::win_for(player)), "{}", position.to_text());
+                        assert_eq!(lines.has_win(position.pieces(player)), won);
+                        if outcome.is_some() {
+                            break;
+                        }
+                        player = player.opponent();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn candidate_moves_are_the_empty_tiles_near_a_piece() {
+        let rng = fastrand::Rng::with_seed(35);
+        for size in 1..=MAX_SIZE {
+            let empty_board = Position::empty(size);
+            assert_eq!(empty_board.candidate_moves(), centre_order(size));
+            for _ in 0..50 {
+                let mut position = Position::empty(size);
+                for _ in 0..rng.u32(1..=size) {
+                    let square = rng.u32(..size * size);
+                    position.set(square, if rng.bool() { Tile::X } else { Tile::O });
+                }
+                let near = |square: u32| squares(position.occupied()).any(|piece| {
+                    let (a, b) = (position.coordinates(square), position.coordinates(piece));
+                    (a.x as i32 - b.x as i32).abs() <= MOVE_RADIUS && (a.y as i32 - b.y as i32).abs() <= MOVE_RADIUS
+                });
+                let mut expected: Vec<u32> = squares(position.empty_squares()).filter(|square| near(*square)).collect();
+                let mut moves = position.candidate_moves();
+                if expected.is_empty() {
+                    expected = squares(position.empty_squares()).collect();
+                }
+                // In centre order, which is checked by the empty board above
+                let order: Vec<u32> = centre_order(size).iter().copied().filter(|square| moves.contains(square)).collect();
+                assert_eq!(moves, order);
+                moves.sort_unstable();
+                assert_eq!(moves, expected, "{}", position.to_text());
+            }
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants, Coordinates, Evaluator, Tile, TileMap, ai::{self, SearchProgress, SearchResult},
+            bitboard::{self, Position}};
+
+// At most this many moves are kept for each book position
+const BOOK_WIDTH: usize = 3;
+
+// Moves scoring further than this below the best one are left out
+const BOOK_MARGIN: i32 = 50;
+
+// A move scoring this much below the best one is played e^-1 times as often
+const BOOK_TEMPERATURE: f32 = 20.0;
+
+// Weight of the best move of a position
+const BEST_WEIGHT: f32 = 100.0;
+
+// One move of a book position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BookMove {
+    pub notation: String,
+    // How often the move is played relative to the other moves of the position
+    pub weight: u32,
+    // Score given to the move by the search that put it in the book
+    pub score: i32,
+}
+
+// Moves worth playing in the first positions of a game, by Zobrist key of
+// the position
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Book {
+    pub size: u32,
+    pub win_length: u32,
+    // Depth of the searches the moves were scored with
+    pub depth: u32,
+    pub positions: HashMap<u64, Vec<BookMove>>,
+}
+
+impl Book {
+    // Search every position reached by book moves during the first `plies`
+    // moves, keeping the best few moves of each. `searched` is called after
+    // each position with the number of positions in the book so far.
+    pub fn generate<F: FnMut(usize)>(size: u32, win_length: u32, plies: u32, depth: u32,
+                                      mut searched: F) -> Self {
+        let mut book = Book { size, win_length, depth, positions: HashMap::new() };
+        let mut pending = vec![TileMap::empty(size)];
+        while let Some(tile_map) = pending.pop() {
+            let position = Position::from_tile_map(&tile_map);
+            let key = position.zobrist_key();
+            let player = position.side_to_move();
+            if book.positions.contains_key(&key) || position.is_full()
+                || bitboard::lines(size, win_length).has_win(position.pieces(player.opponent())) {
+                continue;
+            }
+
+            let mut scores = ai::root_scores(&tile_map, player, win_length, depth, Evaluator::Handwritten,
+                                             &SearchProgress::default());
+            scores.sort_by_key(|(_, score)| -score);
+            let best = scores.first().map(|(_, score)| *score).unwrap_or_default();
+            let moves: Vec<BookMove> = scores.iter()
+                .take(BOOK_WIDTH)
+                .filter(|(_, score)| best - score <= BOOK_MARGIN)
+                .map(|(coordinates, score)| BookMove {
+                    notation: coordinates.notation(),
+                    weight: (BEST_WEIGHT * ((score - best) as f32 / BOOK_TEMPERATURE).exp()).round().max(1.0) as u32,
+                    score: *score,
+                })
+                .collect();
+
+            let moves_played = position.occupied().count_ones();
+            if moves_played + 1 < plies {
+                for (coordinates, _) in scores.iter().take(moves.len()) {
+                    let mut next = tile_map.clone();
+                    next.0[coordinates.x as usize][coordinates.y as usize] = player;
+                    pending.push(next);
+                }
+            }
+            book.positions.insert(key, moves);
+            searched(book.positions.len());
+        }
+        book
+    }
+
+    // Book move of the position, drawn by weight when `varied`, otherwise
+    // the one with the highest weight
+    pub fn choose(&self, tile_map: &TileMap, varied: bool, random: f32) -> Option<(Coordinates, i32)> {
+        let moves = self.positions.get(&Position::from_tile_map(tile_map).zobrist_key())?;
+        let chosen = if varied {
+            let mut target = random * moves.iter().map(|book_move| book_move.weight).sum::<u32>() as f32;
+            moves.iter()
+                .find(|book_move| {
+                    target -= book_move.weight as f32;
+                    target < 0.0
+                })
+                .or(moves.last())?
+        } else {
+            moves.iter().max_by_key(|book_move| book_move.weight)?
+        };
+        let coordinates = Coordinates::from_notation(&chosen.notation)?;
+        // Guard against a different position with the same key
+        let tile = tile_map.0.get(coordinates.x as usize)?.get(coordinates.y as usize)?;
+        (*tile == Tile::Empty).then_some((coordinates, chosen.score))
+    }
+
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+}
+
+// Where the book of a board size and win length is kept
+pub fn path(size: u32, win_length: u32) -> PathBuf {
+    PathBuf::from(constants::BOOK_DIRECTORY).join(format!("{}x{}k{}.json", size, size, win_length))
+}
+
+// Book of a board size and win length, loaded from its file the first time
+// it is asked for. None when the file does not exist.
+pub fn book(size: u32, win_length: u32) -> Option<Arc<Book>> {
+    type Loaded = HashMap<(u32, u32), Option<Arc<Book>>>;
+    static LOADED: OnceLock<Mutex<Loaded>> = OnceLock::new();
+    let mut loaded = LOADED.get_or_init(Default::default).lock().unwrap();
+    loaded.entry((size, win_length))
+        .or_insert_with(|| Book::load(&path(size, win_length)).ok().map(Arc::new))
+        .clone()
+}
+
+// Book move of `player` during the first moves of a game, a varied one drawn
+// from `rng`
+pub fn lookup(tile_map: &TileMap, player: Tile, win_length: u32, varied: bool,
+              rng: &fastrand::Rng) -> Option<SearchResult> {
+    let moves_played = tile_map.0.iter().flatten().filter(|tile| **tile != Tile::Empty).count();
+    if moves_played >= constants::BOOK_PLIES as usize {
+        return None;
+    }
+    let book = book(tile_map.0.len() as u32, win_length)?;
+    let (coordinates, score) = book.choose(tile_map, varied, rng.f32())?;
+    Some(SearchResult { player, best_move: Some(coordinates), score, pv: vec![coordinates], depth: book.depth })
+}
@@ -0,0 +1,190 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{constants, GameState, GameMode, GameOutcome, MoveHistory, Tile, TimeControl, replay::Replay, history::SavedGame, menu::{get_menu_styles, MenuState},
+            game::{Ending, PlayingItem, PlayingState}};
+
+pub struct ClockPlugin;
+
+// Time left to each side of the current game. Only the clock of the side to
+// move runs, and only while the game is played with no menu open; a side
+// gains the increment after each of its moves.
+pub(crate) struct Clocks {
+    // X, then O
+    pub remaining: [Duration; 2],
+    pub increment: Duration,
+    // Whether running out loses the game here. Games played through a game
+    // server are decided by its clocks, which it sends after every move.
+    pub flags: bool,
+    // Moves already given the increment
+    credited: usize,
+}
+
+impl Clocks {
+    pub fn new(remaining: [Duration; 2], increment: Duration, flags: bool, credited: usize) -> Self {
+        Clocks { remaining, increment, flags, credited }
+    }
+
+    pub fn get(&self, side: Tile) -> Duration {
+        self.remaining[if side == Tile::O { 1 } else { 0 }]
+    }
+}
+
+#[derive(Component)]
+struct ClockText;
+
+impl Plugin for ClockPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::Game).with_system(clock_setup))
+            .add_system_set(SystemSet::on_update(PlayingState::Playing).with_system(clock_tick))
+            .add_system_set(SystemSet::on_update(GameState::Game).with_system(clock_display));
+    }
+}
+
+// Start both clocks from the time control, or from where a saved game left
+// them, and show them above the board. Replays have no clocks, and network
+// games only those of a game server.
+fn clock_setup(mut commands: Commands,
+               time_control: Res<TimeControl>,
+               mode: Res<GameMode>,
+               replay: Option<Res<Replay>>,
+               saved: Option<Res<SavedGame>>,
+               asset_server: Res<AssetServer>) {
+    if *mode != GameMode::Network {
+        match (time_control.0, replay) {
+            (Some((time, increment)), None) => {
+                // The increments of the saved moves are already on the clocks
+                let (remaining, credited) = match saved.as_deref() {
+                    Some(SavedGame { clock: Some(clock), moves, .. }) => (clock.map(Duration::from_millis), moves.len()),
+                    _ => ([Duration::from_secs(time.into()); 2], 0),
+                };
+                commands.insert_resource(Clocks::new(remaining, Duration::from_secs(increment.into()), true, credited));
+            }
+            _ => commands.remove_resource::<Clocks>(),
+        }
+    }
+
+    let (font, _, _) = get_menu_styles(asset_server);
+    let style = TextStyle {
+        font,
+        font_size: constants::CLOCK_FONT_SIZE,
+        color: constants::TEXT_COLOR,
+    };
+    commands.spawn_bundle(Text2dBundle {
+        text: Text::from_sections([
+            TextSection::new("", style.clone()),
+            TextSection::new("", style),
+        ])
+        .with_alignment(TextAlignment::CENTER),
+        transform: Transform::from_xyz(0.0, constants::LENGTH / 2.0 + constants::CLOCK_MARGIN, 1.0),
+        ..default()
+    })
+    .insert(Name::new("Clocks"))
+    .insert(ClockText)
+    .insert(PlayingItem);
+}
+
+// Run the clock of the side to move, which loses once it runs out. Clocks
+// stand still while the game is paused, which network games never are.
+fn clock_tick(time: Res<Time>,
+              clocks: Option<ResMut<Clocks>>,
+              moves: Res<MoveHistory>,
+              mode: Res<GameMode>,
+              menu_state: Res<State<MenuState>>,
+              mut ending: ResMut<Ending>,
+              mut playing_states: ResMut<State<PlayingState>>) {
+    let Some(mut clocks) = clocks else { return };
+    while clocks.credited < moves.0.len() {
+        let side = clocks.credited % 2;
+        let increment = clocks.increment;
+        clocks.remaining[side] += increment;
+        clocks.credited += 1;
+    }
+    if *menu_state.current() != MenuState::Disabled && *mode != GameMode::Network {
+        return;
+    }
+
+    let side = moves.side_to_move();
+    let index = if side == Tile::O { 1 } else { 0 };
+    clocks.remaining[index] = clocks.remaining[index].saturating_sub(time.delta());
+    if clocks.flags && clocks.remaining[index].is_zero() {
+        let (winner, reason) = match side {
+            Tile::O => (GameOutcome::XWins, constants::O_LOST_ON_TIME_STRING),
+            _ => (GameOutcome::OWins, constants::X_LOST_ON_TIME_STRING),
+        };
+        ending.decide(&mut playing_states, Some(winner), reason.to_string());
+    }
+}
+
+// Show both clocks, the one running brighter than the other
+fn clock_display(clocks: Option<Res<Clocks>>,
+                 moves: Res<MoveHistory>,
+                 playing_states: Res<State<PlayingState>>,
+                 mut text_query: Query<&mut Text, With<ClockText>>) {
+    let running = match *playing_states.current() {
+        PlayingState::Playing => Some(moves.side_to_move()),
+        _ => None,
+    };
+    for mut text in text_query.iter_mut() {
+        for (section, side) in text.sections.iter_mut().zip([Tile::X, Tile::O]) {
+            let Some(clocks) = &clocks else {
+                section.value.clear();
+                continue;
+            };
+            let name = if side == Tile::X { "X" } else { "O" };
+            section.value = format!("  {} {}  ", name, clock_text(clocks.get(side)));
+            section.style.color = match running == Some(side) {
+                true => constants::TEXT_COLOR,
+                false => constants::CLOCK_IDLE_COLOR,
+            };
+        }
+    }
+}
+
+// Minutes and seconds, with tenths once under ten seconds
+pub fn clock_text(time: Duration) -> String {
+    let tenths = time.as_millis() / 100;
+    if tenths < 100 {
+        format!("0:{:02}.{}", tenths / 10, tenths % 10)
+    } else {
+        format!("{}:{:02}", tenths / 600, tenths / 10 % 60)
+    }
+}
+
+// Time the AI may spend on its next move with `remaining` left on its clock:
+// an equal share of the moves expected to come, most of the increment, and
+// never so much that it could run out
+pub fn move_budget(remaining: Duration, increment: Duration) -> Duration {
+    let budget = remaining / constants::CLOCK_MOVES_TO_GO + increment * 3 / 4;
+    budget.min(remaining.saturating_sub(constants::CLOCK_SAFETY_MARGIN) / 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_text_shows_tenths_under_ten_seconds() {
+        assert_eq!(clock_text(Duration::from_millis(9_950)), "0:09.9");
+        assert_eq!(clock_text(Duration::from_millis(450)), "0:00.4");
+        assert_eq!(clock_text(Duration::ZERO), "0:00.0");
+        assert_eq!(clock_text(Duration::from_secs(10)), "0:10");
+        assert_eq!(clock_text(Duration::from_secs(65)), "1:05");
+        assert_eq!(clock_text(Duration::from_secs(600)), "10:00");
+    }
+
+    #[test]
+    fn move_budget_leaves_time_on_the_clock() {
+        let increment = Duration::from_secs(2);
+        assert_eq!(move_budget(constants::CLOCK_SAFETY_MARGIN / 2, increment), Duration::ZERO);
+        assert_eq!(move_budget(constants::CLOCK_SAFETY_MARGIN, increment), Duration::ZERO);
+        for millis in [150, 1_000, 5_000, 60_000, 600_000] {
+            let remaining = Duration::from_millis(millis);
+            assert!(move_budget(remaining, increment) <= remaining / 2, "{:?}", remaining);
+        }
+        // With plenty of time left, a share of it and most of the increment
+        let remaining = Duration::from_secs(60);
+        assert_eq!(move_budget(remaining, increment), remaining / constants::CLOCK_MOVES_TO_GO + increment * 3 / 4);
+    }
+}
@@ -1,7 +1,27 @@
-use bevy::prelude::Color;
+use std::time::Duration;
+
+use bevy::prelude::{Color, KeyCode};
 
 pub const DEFAULT_BOARD_SIZE: u32 = 3;
 pub const DEFAULT_AI_DEPTH: u32 = 4;
+pub const DEFAULT_WIN_LENGTH: u32 = 3;
+// The AI looks its move up in the opening book during this many first moves
+pub const BOOK_PLIES: u32 = 6;
+pub const DEFAULT_MCTS_ITERATIONS: u32 = 20000;
+// Search time given to external engines, and how much later than that their
+// move may arrive before they are taken to have hung
+pub const EXTERNAL_MOVE_TIME: Duration = Duration::from_millis(1000);
+pub const EXTERNAL_TIME_MARGIN: Duration = Duration::from_millis(2000);
+// Network games are hosted on this port unless the joining address names another
+pub const DEFAULT_PORT: u16 = 7878;
+pub const DEFAULT_JOIN_ADDRESS: &str = "127.0.0.1";
+pub const MAX_ADDRESS_LENGTH: usize = 64;
+// How long the other side may take to answer when a connection is made
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// On the clock the AI spends about this share of its time on a move, keeping
+// this much in hand
+pub const CLOCK_MOVES_TO_GO: u32 = 20;
+pub const CLOCK_SAFETY_MARGIN: Duration = Duration::from_millis(100);
 
 pub const MIN_TILE_SIZE: f32 = 10.0;
 pub const MAX_TILE_SIZE: f32 = 200.0;
@@ -22,19 +42,152 @@ pub const BUTTON_MARGIN: f32 = 20.0;
 pub const BUTTON_FONT_SIZE: f32 = 40.0;
 
 pub const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+pub const HINT_COLOR: Color = Color::rgb(0.9, 0.8, 0.2);
+pub const CURSOR_COLOR: Color = Color::rgb(0.3, 0.3, 0.6);
+pub const WINNING_COLOR: Color = Color::rgb(0.2, 0.7, 0.2);
+pub const LOSING_COLOR: Color = Color::rgb(0.8, 0.2, 0.2);
+pub const DRAWN_COLOR: Color = Color::rgb(0.45, 0.45, 0.45);
+// The clock of the side not to move
+pub const CLOCK_IDLE_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
 
 pub const LENGTH: f32 = 600.0;
 
 pub const GAME_STRING_FONT_SIZE: f32 = 80.0;
 pub const GAME_STRING_MARGIN: f32 = 50.0;
 
+pub const STATS_FONT_SIZE: f32 = 22.0;
+pub const RECENT_GAMES_COUNT: usize = 8;
+
+pub const HUD_FONT_SIZE: f32 = 40.0;
+pub const HUD_MARGIN: f32 = 40.0;
+// The clocks are shown centred this far above the board
+pub const CLOCK_FONT_SIZE: f32 = 30.0;
+pub const CLOCK_MARGIN: f32 = 30.0;
+
+// Heuristic scores of this size are shown at full heatmap colour
+pub const ANALYSIS_SCORE_SCALE: f32 = 200.0;
+pub const ANALYSIS_FONT_RATIO: f32 = 0.22;
+
+pub const EVAL_BAR_WIDTH: f32 = 24.0;
+pub const EVAL_BAR_MARGIN: f32 = 20.0;
+// Heuristic scores of this size fill about three quarters of the bar
+pub const EVAL_BAR_SCORE_SCALE: f32 = 200.0;
+pub const PV_TEXT_WIDTH: f32 = 280.0;
+// Chat of network games, at the bottom left of the board
+pub const CHAT_WIDTH: f32 = 250.0;
+pub const CHAT_LINES: usize = 8;
+pub const CHAT_MAX_LENGTH: usize = 80;
+// Shows and hides the search statistics during a game
+pub const DEBUG_PANEL_KEY: KeyCode = KeyCode::F3;
+// Opens and closes the pause menu during a game
+pub const PAUSE_KEY: KeyCode = KeyCode::Escape;
+
+pub const REPLAY_DEFAULT_SPEED: f32 = 1.0;
+pub const REPLAY_MIN_SPEED: f32 = 0.25;
+pub const REPLAY_MAX_SPEED: f32 = 8.0;
+pub const REPLAY_BUTTON_SIZE: f32 = 50.0;
+pub const REPLAY_BUTTON_MARGIN: f32 = 5.0;
+pub const REPLAY_TIMELINE_WIDTH: f32 = 400.0;
+pub const REPLAY_TIMELINE_HEIGHT: f32 = 20.0;
+
 pub const GAME_STRING: &str = "Tic Tac Toe";
 pub const PLAY_AI_STRING: &str = "Play vs AI";
 pub const PLAY_AGAINST_PLAYER_STRING: &str = "Play 1vs1";
+pub const PLAY_CUSTOM_STRING: &str = "Custom game";
+pub const PLAY_LAN_STRING: &str = "Play LAN";
+pub const HOST_STRING: &str = "Host";
+pub const JOIN_STRING: &str = "Join";
+pub const WATCH_STRING: &str = "Watch";
+pub const FIND_GAME_STRING: &str = "Find game";
+pub const DEFAULT_PLAYER_NAME: &str = "Player";
+pub const SPECTATORS_STRING: &str = "Spectators";
+pub const NEW_GAME_STRING: &str = "New game";
+pub const HOST_LEFT_STRING: &str = "Host left";
+pub const RESIGN_STRING: &str = "Resign";
+pub const REMATCH_STRING: &str = "Rematch";
+pub const X_RESIGNED_STRING: &str = "X resigned";
+pub const O_RESIGNED_STRING: &str = "O resigned";
+pub const OFFER_DRAW_STRING: &str = "Offer draw";
+pub const DRAW_OFFERED_STRING: &str = "Offered";
+pub const DRAW_DECLINED_STRING: &str = "Declined";
+pub const ACCEPT_DRAW_STRING: &str = "Accept draw";
+pub const DECLINE_DRAW_STRING: &str = "Decline";
+pub const DRAW_AGREED_STRING: &str = "Draw agreed";
+pub const LAN_HELP_STRING: &str = "Host a game, or type the address of a host and join it";
+pub const ADDRESS_STRING: &str = "Address";
+pub const YOU_STRING: &str = "You";
+pub const OPPONENT_STRING: &str = "Opponent";
+pub const YOU_RESIGNED_STRING: &str = "You resigned";
+pub const OPPONENT_RESIGNED_STRING: &str = "Opponent resigned";
+pub const OPPONENT_LEFT_STRING: &str = "Opponent left";
+pub const WRONG_MOVE_STRING: &str = "Opponent sent a wrong move";
 pub const SETTINGS_STRING: &str = "Settings";
 pub const QUIT_STRING: &str = "Quit";
 pub const AI_DEPTH_SETTING_STRING: &str = "AI Depth";
+pub const AI_THREADS_SETTING_STRING: &str = "AI threads";
+pub const ALL_THREADS_STRING: &str = "All";
 pub const MATRIX_SIZE_SETTING_STRING: &str = "Matrix size";
+pub const WIN_LENGTH_SETTING_STRING: &str = "Win length";
+pub const HINT_LIMIT_SETTING_STRING: &str = "Hints";
+pub const TIME_CONTROL_SETTING_STRING: &str = "Clock";
+pub const CLOCK_OFF_STRING: &str = "Off";
+pub const X_LOST_ON_TIME_STRING: &str = "X lost on time";
+pub const O_LOST_ON_TIME_STRING: &str = "O lost on time";
+pub const DIFFICULTY_SETTING_STRING: &str = "Difficulty";
+pub const BEGINNER_STRING: &str = "Beginner";
+pub const EASY_STRING: &str = "Easy";
+pub const MEDIUM_STRING: &str = "Medium";
+pub const HARD_STRING: &str = "Hard";
+pub const PERFECT_STRING: &str = "Perfect";
+pub const OPENING_BOOK_STRING: &str = "Opening book";
+pub const BOOK_OFF_STRING: &str = "Off";
+pub const BOOK_BEST_STRING: &str = "Best";
+pub const BOOK_VARIED_STRING: &str = "Varied";
+pub const EVALUATOR_STRING: &str = "Evaluation";
+pub const HANDWRITTEN_STRING: &str = "Handwritten";
+pub const LEARNED_STRING: &str = "Learned";
+pub const PLAYERS_SETTING_STRING: &str = "Players";
+pub const X_PLAYER_STRING: &str = "X player";
+pub const O_PLAYER_STRING: &str = "O player";
+pub const X_ENGINE_STRING: &str = "X engine";
+pub const O_ENGINE_STRING: &str = "O engine";
+pub const MOUSE_STRING: &str = "Mouse";
+pub const KEYBOARD_STRING: &str = "Keyboard";
+pub const AI_STRING: &str = "AI";
+pub const RANDOM_STRING: &str = "Random";
+pub const EXTERNAL_STRING: &str = "External";
+pub const PLAYOUT_STRING: &str = "Playouts";
+pub const BUDGET_STRING: &str = "Budget";
+pub const MINIMAX_STRING: &str = "Minimax";
+pub const MCTS_STRING: &str = "MCTS";
+pub const RANDOM_PLAYOUT_STRING: &str = "Random";
+pub const HEURISTIC_PLAYOUT_STRING: &str = "Heuristic";
+pub const NO_LIMIT_STRING: &str = "No limit";
+pub const HINT_STRING: &str = "Hint";
+pub const ANALYSIS_STRING: &str = "Analysis";
+pub const STATISTICS_STRING: &str = "Statistics";
+pub const RECENT_GAMES_STRING: &str = "Recent games";
+pub const X_WINS_STRING: &str = "X wins";
+pub const O_WINS_STRING: &str = "O wins";
+pub const DRAW_STRING: &str = "Draw";
+pub const ABANDONED_STRING: &str = "Abandoned";
 pub const BACK_STRING: &str = "Back";
+pub const PAUSED_STRING: &str = "Paused";
+pub const RESUME_STRING: &str = "Resume";
+pub const RESTART_STRING: &str = "Restart";
+pub const SAVE_STRING: &str = "Save";
+pub const SAVED_STRING: &str = "Saved";
+pub const QUIT_TO_MENU_STRING: &str = "Quit to menu";
+pub const CONFIRM_QUIT_STRING: &str = "Quit this game?";
+pub const YES_STRING: &str = "Yes";
+pub const NO_STRING: &str = "No";
+pub const LOAD_GAME_STRING: &str = "Load game";
 
 pub const FONT_LOCATION: &str = "fonts/FiraSans-Bold.ttf";
+pub const HISTORY_FILE: &str = "history.jsonl";
+pub const SOLVED_DIRECTORY: &str = "solved";
+pub const BOOK_DIRECTORY: &str = "books";
+pub const WEIGHTS_FILE: &str = "weights.json";
+pub const LEARNED_FILE: &str = "learned.json";
+pub const SERVER_RESULTS_FILE: &str = "results.jsonl";
+pub const SAVED_GAME_FILE: &str = "saved_game.json";
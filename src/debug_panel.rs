@@ -0,0 +1,82 @@
+use bevy::{prelude::*, text::Text2dBounds};
+
+use crate::{constants, menu::get_menu_styles, game::{PlayingState, PlayingItem, EngineReport}};
+
+pub struct DebugPanelPlugin;
+
+// Whether the statistics of the reported search are shown, kept between games
+#[derive(Default)]
+struct DebugPanel {
+    visible: bool,
+}
+
+#[derive(Component)]
+struct DebugPanelText;
+
+impl Plugin for DebugPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugPanel>()
+            .add_system_set(SystemSet::on_enter(PlayingState::Playing)
+                            .with_system(debug_panel_setup))
+            .add_system_set(SystemSet::on_update(PlayingState::Playing)
+                            .with_system(debug_panel_toggle)
+                            .with_system(debug_panel_display));
+    }
+}
+
+// Spawn the panel under the line text on the right of the board
+fn debug_panel_setup(mut commands: Commands, asset_server: Res<AssetServer>, panel: Res<DebugPanel>) {
+    let (font, _, _) = get_menu_styles(asset_server);
+    commands.spawn_bundle(Text2dBundle {
+        text: Text::from_section("", TextStyle {
+            font,
+            font_size: constants::STATS_FONT_SIZE,
+            color: constants::TEXT_COLOR,
+        })
+        .with_alignment(TextAlignment::TOP_LEFT),
+        text_2d_bounds: Text2dBounds {
+            size: Vec2::new(constants::PV_TEXT_WIDTH, constants::LENGTH / 4.0),
+        },
+        transform: Transform::from_xyz(constants::LENGTH / 2.0 + constants::HUD_MARGIN,
+                                       -constants::LENGTH / 4.0, 1.0),
+        visibility: Visibility { is_visible: panel.visible },
+        ..default()
+    })
+    .insert(Name::new("DebugPanel"))
+    .insert(DebugPanelText)
+    .insert(PlayingItem);
+}
+
+fn debug_panel_toggle(
+    keys: Res<Input<KeyCode>>,
+    mut panel: ResMut<DebugPanel>,
+    mut visibility_query: Query<&mut Visibility, With<DebugPanelText>>,
+    ) {
+    if !keys.just_pressed(constants::DEBUG_PANEL_KEY) {
+        return;
+    }
+    panel.visible = !panel.visible;
+    for mut visibility in visibility_query.iter_mut() {
+        visibility.is_visible = panel.visible;
+    }
+}
+
+// Show the statistics of the reported search while it runs
+fn debug_panel_display(
+    report: Res<EngineReport>,
+    panel: Res<DebugPanel>,
+    mut text_query: Query<&mut Text, With<DebugPanelText>>,
+    ) {
+    if !panel.visible {
+        return;
+    }
+    let stats = report.progress.stats();
+    let value = format!("Nodes {}\n{:.0} nodes/s\nCutoffs {}\nDepth {}  Score {}\nTime {:.1?}",
+                        stats.nodes, stats.nodes_per_second(), stats.cutoffs,
+                        stats.depth, stats.score, stats.elapsed);
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value != value {
+            text.sections[0].value = value.clone();
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use std::time::Duration;
+
+use crate::{constants, bitboard, AiEngine, Coordinates, Evaluator, GameOutcome, OpeningBook, Tile, TileMap,
+            ai::{self, SearchProgress, SearchResult}, book, learned, mcts, solver};
+
+// How an engine searches, taken from the settings when the game starts
+#[derive(Debug, Clone, Copy)]
+pub struct EngineConfig {
+    pub engine: AiEngine,
+    pub depth: u32,
+    pub threads: usize,
+    // Softmax temperature of the minimax move choice, zero for the best move
+    pub temperature: f32,
+    pub budget: mcts::Budget,
+    pub playout: mcts::Playout,
+    pub book: OpeningBook,
+    pub evaluator: Evaluator,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            engine: AiEngine::Minimax,
+            depth: constants::DEFAULT_AI_DEPTH,
+            threads: 1,
+            temperature: 0.0,
+            budget: mcts::Budget::Iterations(constants::DEFAULT_MCTS_ITERATIONS),
+            playout: mcts::Playout::Heuristic,
+            book: OpeningBook::Off,
+            evaluator: Evaluator::Handwritten,
+        }
+    }
+}
+
+impl EngineConfig {
+    // Read a configuration written as the engine name followed by settings
+    // that differ from the default, e.g. "minimax,depth=5,book=best,eval=learned"
+    // or "mcts,time=500,playout=random"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut parts = spec.split(',');
+        let engine = match parts.next() {
+            Some("minimax") => AiEngine::Minimax,
+            Some("mcts") => AiEngine::Mcts,
+            _ => return Err(format!("unknown engine in \"{}\", expected minimax or mcts", spec)),
+        };
+        let mut config = EngineConfig { engine, ..EngineConfig::default() };
+        for part in parts {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("expected key=value, got \"{}\"", part))?;
+            let invalid = || format!("invalid value for {}: \"{}\"", key, value);
+            match key {
+                "depth" => config.depth = value.parse().map_err(|_| invalid())?,
+                "threads" => config.threads = value.parse().map_err(|_| invalid())?,
+                "temperature" => config.temperature = match value.parse::<f32>() {
+                    Ok(temperature) if temperature.is_finite() && temperature >= 0.0 => temperature,
+                    _ => return Err(invalid()),
+                },
+                "iterations" => config.budget = mcts::Budget::Iterations(value.parse().map_err(|_| invalid())?),
+                "time" => config.budget = mcts::Budget::Time(Duration::from_millis(value.parse().map_err(|_| invalid())?)),
+                "playout" => config.playout = match value {
+                    "random" => mcts::Playout::Random,
+                    "heuristic" => mcts::Playout::Heuristic,
+                    _ => return Err(invalid()),
+                },
+                "book" => config.book = match value {
+                    "off" => OpeningBook::Off,
+                    "best" => OpeningBook::Best,
+                    "varied" => OpeningBook::Varied,
+                    _ => return Err(invalid()),
+                },
+                "eval" => config.evaluator = match value {
+                    "handwritten" => Evaluator::Handwritten,
+                    "learned" if learned::value_function().is_none() => {
+                        return Err(format!("no learned evaluator has been trained into {}", constants::LEARNED_FILE));
+                    }
+                    "learned" => Evaluator::Learned,
+                    _ => return Err(invalid()),
+                },
+                _ => return Err(format!("unknown setting \"{}\"", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    // Choose the move of `player`. With minimax and a positive temperature the
+    // move is drawn from the scores the search gives every root move, so that
+    // weaker but plausible moves are sometimes played. At zero temperature the
+    // first moves come from the opening book when there is one, and a solved
+    // board is looked up instead of searched. MCTS always searches, since the
+    // book holds minimax moves. Every random choice is drawn from `rng`.
+    pub fn search(&self, tile_map: &TileMap, player: Tile, win_length: u32, rng: &fastrand::Rng,
+                  progress: &SearchProgress) -> EngineMove {
+        if self.engine == AiEngine::Mcts {
+            return mcts::search(tile_map, player, win_length, self.budget, self.playout, rng, progress).into();
+        }
+        if self.temperature == 0.0 {
+            if self.book != OpeningBook::Off {
+                if let Some(result) = book::lookup(tile_map, player, win_length, self.book == OpeningBook::Varied, rng) {
+                    progress.publish(&result);
+                    return result.into();
+                }
+            }
+            if let Some(result) = solver::lookup(tile_map, player, win_length) {
+                progress.publish(&result);
+                return result.into();
+            }
+            return ai::search_parallel(tile_map, player, win_length, self.depth, self.threads, self.evaluator, progress).into();
+        }
+        let (search, scores) = ai::search_scored(tile_map, player, win_length, self.depth, self.evaluator, progress);
+        let played = ai::sample_move(&scores, self.temperature, rng.f32())
+            .map(|(coordinates, _)| coordinates)
+            .or(search.best_move);
+        EngineMove { played, search }
+    }
+}
+
+// The move an engine plays and the search it was chosen from. The search keeps
+// its own best move and score, which a move drawn with a temperature need not be.
+#[derive(Debug, Clone)]
+pub struct EngineMove {
+    pub played: Option<Coordinates>,
+    pub search: SearchResult,
+}
+
+impl From<SearchResult> for EngineMove {
+    fn from(search: SearchResult) -> Self {
+        EngineMove { played: search.best_move, search }
+    }
+}
+
+// A finished game between two engines
+#[derive(Debug, Clone)]
+pub struct SelfPlayGame {
+    // Every move, the opening included
+    pub moves: Vec<Coordinates>,
+    // The search behind each move, None for the moves of the opening
+    pub searches: Vec<Option<SearchResult>>,
+    pub outcome: GameOutcome,
+}
+
+// Play the game from `opening` to its end, each engine searching the moves
+// of its side with its random choices drawn from `rng`
+pub fn play_game(x: &EngineConfig, o: &EngineConfig, size: u32, win_length: u32,
+                 opening: &[Coordinates], rng: &fastrand::Rng) -> SelfPlayGame {
+    let mut tile_map = TileMap::empty(size);
+    let (mut moves, mut searches) = (Vec::new(), Vec::new());
+    let mut player = Tile::X;
+    let mut next = opening.iter().copied();
+    loop {
+        if let Some(outcome) = tile_map.outcome(win_length) {
+            return SelfPlayGame { moves, searches, outcome };
+        }
+        let config = if player == Tile::X { x } else { o };
+        let (coordinates, search) = match next.next() {
+            Some(coordinates) => (coordinates, None),
+            None => {
+                let chosen = config.search(&tile_map, player, win_length, rng, &SearchProgress::default());
+                let coordinates = chosen.played.expect("an engine found no move on a board with empty tiles");
+                (coordinates, Some(chosen.search))
+            }
+        };
+        tile_map.0[coordinates.x as usize][coordinates.y as usize] = player;
+        moves.push(coordinates);
+        searches.push(search);
+        player = player.opponent();
+    }
+}
+
+// `plies` random moves from the empty board that do not end the game
+pub fn random_opening(size: u32, win_length: u32, plies: u32, rng: &fastrand::Rng) -> Vec<Coordinates> {
+    let mut tile_map = TileMap::empty(size);
+    let mut moves = Vec::new();
+    let mut player = Tile::X;
+    while (moves.len() as u32) < plies {
+        let playable: Vec<Coordinates> = (0..size as u16)
+            .flat_map(|x| (0..size as u16).map(move |y| Coordinates { x, y }))
+            .filter(|coordinates| {
+                let mut next = tile_map.clone();
+                let tile = &mut next.0[coordinates.x as usize][coordinates.y as usize];
+                if *tile != Tile::Empty {
+                    return false;
+                }
+                *tile = player;
+                next.outcome(win_length).is_none()
+            })
+            .collect();
+        if playable.is_empty() {
+            break;
+        }
+        let coordinates = playable[rng.usize(..playable.len())];
+        tile_map.0[coordinates.x as usize][coordinates.y as usize] = player;
+        moves.push(coordinates);
+        player = player.opponent();
+    }
+    moves
+}
+
+// A board size and win length written as e.g. "7x7k4"
+pub fn parse_board(board: &str) -> Option<(u32, u32)> {
+    let (rows, rest) = board.split_once('x')?;
+    let (columns, win_length) = rest.split_once('k')?;
+    let (rows, columns): (u32, u32) = (rows.parse().ok()?, columns.parse().ok()?);
+    let win_length = win_length.parse().ok()?;
+    (rows == columns && rows <= bitboard::MAX_SIZE && (1..=rows).contains(&win_length)).then_some((rows, win_length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boards_are_square_with_a_fitting_win_length() {
+        assert_eq!(parse_board("3x3k3"), Some((3, 3)));
+        assert_eq!(parse_board("7x7k4"), Some((7, 4)));
+        for board in ["3x9k3", "3xk3", "3xfook3", "x3k3", "3x3k", "3x3k4", "3x3k0", "3x3", ""] {
+            assert_eq!(parse_board(board), None, "{:?}", board);
+        }
+        let too_big = bitboard::MAX_SIZE + 1;
+        assert_eq!(parse_board(&format!("{}x{}k3", too_big, too_big)), None);
+    }
+}
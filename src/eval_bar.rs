@@ -0,0 +1,152 @@
+use bevy::{prelude::*, tasks::AsyncComputeTaskPool, text::Text2dBounds};
+
+use crate::{constants, AiDepth, Board, MoveHistory, Tile, menu::get_menu_styles, player::Players,
+            ai::{self, Evaluation, SearchResult}, game::{PlayingState, PlayingItem, EngineReport}};
+
+pub struct EvalBarPlugin;
+
+// Part of the bar filled from the bottom with X's colour
+#[derive(Component)]
+struct EvalBarFill;
+
+#[derive(Component)]
+struct PrincipalVariationText;
+
+impl Plugin for EvalBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(PlayingState::Playing)
+                            .with_system(eval_bar_setup))
+            .add_system_set(SystemSet::on_update(PlayingState::Playing)
+                            .with_system(eval_request)
+                            .with_system(eval_display));
+    }
+}
+
+// Spawn the bar on the left of the board and the line text on its right
+fn eval_bar_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let x = -constants::LENGTH / 2.0 - constants::EVAL_BAR_MARGIN - constants::EVAL_BAR_WIDTH / 2.0;
+
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: Color::BLUE,
+            custom_size: Some(Vec2::new(constants::EVAL_BAR_WIDTH, constants::LENGTH)),
+            ..default()
+        },
+        transform: Transform::from_xyz(x, 0.0, 1.0),
+        ..default()
+    })
+    .insert(Name::new("EvalBar"))
+    .insert(PlayingItem);
+
+    commands.spawn_bundle(SpriteBundle {
+        sprite: Sprite {
+            color: Color::RED,
+            custom_size: Some(Vec2::new(constants::EVAL_BAR_WIDTH, constants::LENGTH / 2.0)),
+            ..default()
+        },
+        transform: Transform::from_xyz(x, -constants::LENGTH / 4.0, 2.0),
+        ..default()
+    })
+    .insert(Name::new("EvalBarFill"))
+    .insert(EvalBarFill)
+    .insert(PlayingItem);
+
+    let (font, _, _) = get_menu_styles(asset_server);
+    commands.spawn_bundle(Text2dBundle {
+        text: Text::from_section("", TextStyle {
+            font,
+            font_size: constants::STATS_FONT_SIZE,
+            color: constants::TEXT_COLOR,
+        })
+        .with_alignment(TextAlignment::TOP_LEFT),
+        text_2d_bounds: Text2dBounds {
+            size: Vec2::new(constants::PV_TEXT_WIDTH, constants::LENGTH / 2.0),
+        },
+        transform: Transform::from_xyz(constants::LENGTH / 2.0 + constants::HUD_MARGIN,
+                                       constants::LENGTH / 2.0, 1.0),
+        ..default()
+    })
+    .insert(Name::new("PrincipalVariation"))
+    .insert(PrincipalVariationText)
+    .insert(PlayingItem);
+}
+
+// Evaluate every new position in the background, e.g. while a person is to
+// move or while watching a game. Engines report their own search instead.
+fn eval_request(
+    mut report: ResMut<EngineReport>,
+    board: Res<Board>,
+    moves: Res<MoveHistory>,
+    players: Res<Players>,
+    ai_depth: Res<AiDepth>,
+    ) {
+    if !moves.is_changed() {
+        return;
+    }
+
+    let player = moves.side_to_move();
+    if players.get(player).reports_search() || board.tile_map.outcome(board.win_length).is_some() {
+        return;
+    }
+    let progress = report.restart();
+    let tile_map = board.tile_map.clone();
+    let win_length = board.win_length;
+    let depth = ai_depth.0;
+    AsyncComputeTaskPool::get().spawn(async move {
+        ai::search(&tile_map, player, win_length, depth, &progress);
+    }).detach();
+}
+
+// Show the latest result of the reported search
+fn eval_display(
+    report: Res<EngineReport>,
+    mut shown: Local<Option<SearchResult>>,
+    board: Res<Board>,
+    mut fill_query: Query<(&mut Sprite, &mut Transform), With<EvalBarFill>>,
+    mut text_query: Query<&mut Text, With<PrincipalVariationText>>,
+    ) {
+    let latest = report.progress.latest();
+    if latest == *shown {
+        return;
+    }
+    let result = match &latest {
+        Some(result) => result,
+        None => return,
+    };
+
+    // Scores are given for the side to move, the bar shows them for X
+    let score = if result.player == Tile::X { result.score } else { -result.score };
+    let exhaustive = ai::is_exhaustive(&board.tile_map, result.depth);
+    let evaluation = Evaluation::from_score(score, exhaustive);
+
+    let share = match evaluation {
+        Evaluation::Win(_) => 1.0,
+        Evaluation::Loss(_) => 0.0,
+        Evaluation::Draw => 0.5,
+        Evaluation::Heuristic(score) =>
+            0.5 + 0.5 * (score as f32 / constants::EVAL_BAR_SCORE_SCALE).tanh(),
+    };
+    for (mut sprite, mut transform) in fill_query.iter_mut() {
+        let height = constants::LENGTH * share;
+        sprite.custom_size = Some(Vec2::new(constants::EVAL_BAR_WIDTH, height));
+        transform.translation.y = -constants::LENGTH / 2.0 + height / 2.0;
+    }
+
+    let verdict = match evaluation {
+        Evaluation::Win(moves) => format!("X wins in {}", moves),
+        Evaluation::Loss(moves) => format!("O wins in {}", moves),
+        Evaluation::Draw => constants::DRAW_STRING.to_string(),
+        Evaluation::Heuristic(score) => format!("{:+}", score),
+    };
+    let mut side = result.player;
+    let line: Vec<String> = result.pv.iter().map(|coordinates| {
+        let notation = format!("{:?} {}", side, coordinates.notation());
+        side = side.opponent();
+        notation
+    }).collect();
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = format!("Depth {}: {}\n{}", result.depth, verdict, line.join("  "));
+    }
+
+    *shown = latest;
+}
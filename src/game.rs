@@ -1,55 +1,267 @@
-use bevy::{prelude::*, app::AppExit, utils::HashMap};
-use bevy::math::Vec3Swizzles;
-use crate::{GameState, TileMap, MatrixSize, Tile, constants, Coordinates, Bounds2, Board, menu::{get_menu_styles, MenuButtonAction}, despawn_screen};
+use std::marker::PhantomData;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy::ecs::system::SystemParam;
+use bevy::tasks::{AsyncComputeTaskPool, Task};
+use futures_lite::future;
+use crate::{GameState, TileMap, MatrixSize, Tile, constants, Coordinates, Bounds2, Board, menu::{get_menu_styles, ChangedButton, MenuButtonAction, MenuState}, despawn_screen,
+            AiDepth, AiThreads, Difficulty, PlayerKind, XPlayer, OPlayer, XEngine, OEngine, MctsBudget, MctsPlayout, OpeningBook, Evaluator, ExternalEngine, WinLength, GameMode, GameOutcome, MoveHistory, HintLimit, history::{self, GameRecord, SavedGame}, replay::Replay,
+            online::Session, clock::Clocks, ai::{self, SearchProgress},
+            player::{Player, Players, Turn, MousePlayer, KeyboardPlayer, EnginePlayer, RandomPlayer, ExternalPlayer}, engine::EngineConfig};
 
 pub struct GamePlugin;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum PlayingState {
+pub(crate) enum PlayingState {
     Init,
     Playing,
     GameOver,
+    Replaying,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum WhoseTurn {
+pub(crate) enum WhoseTurn {
     Noone,
     XTurn,
     OTurn,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash, Component)]
-struct PlayingItem;
+pub(crate) struct PlayingItem;
+
+// All actions that can be triggered from a button on the game screen
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GameButtonAction {
+    Hint,
+    ToggleAnalysis,
+}
+
+#[derive(Component)]
+struct HintButtonText;
+
+// Highlight drawn over the tile suggested by a hint
+#[derive(Component)]
+struct HintMarker;
+
+// Hints asked for in the current game and the tile last suggested, with the
+// search looking for the next one
+#[derive(Default)]
+struct Hint {
+    used: u32,
+    suggestion: Option<Coordinates>,
+    task: Option<Task<Option<Coordinates>>>,
+    progress: SearchProgress,
+}
+
+// Column on the left of the board holding the game screen buttons
+#[derive(Component)]
+pub(crate) struct GameButtonColumn;
+
+// Highlight drawn over the cursor of a keyboard player
+#[derive(Component)]
+struct CursorMarker;
+
+// Progress of the search whose score and line are currently shown:
+// the AI thinking about its move, or an evaluation of the position
+#[derive(Default)]
+pub(crate) struct EngineReport {
+    pub progress: SearchProgress,
+}
+
+impl EngineReport {
+    // Stop the search currently reported and report a new one instead
+    pub fn restart(&mut self) -> SearchProgress {
+        self.progress.stop();
+        self.progress = SearchProgress::default();
+        self.progress.clone()
+    }
+}
+
+// How a game ended when the board did not decide it, e.g. by resignation.
+// Without an outcome the game was abandoned.
+pub(crate) struct Decision {
+    pub outcome: Option<GameOutcome>,
+    pub reason: String,
+}
+
+// The decision ending the current game, if any
+#[derive(Default)]
+pub(crate) struct Ending(pub Option<Decision>);
+
+impl Ending {
+    // End the game being played with `outcome` for `reason`
+    pub fn decide(&mut self, playing_states: &mut State<PlayingState>, outcome: Option<GameOutcome>, reason: String) {
+        // The board may have ended the game in the same frame
+        if *playing_states.current() == PlayingState::Playing && playing_states.set(PlayingState::GameOver).is_ok() {
+            self.0 = Some(Decision { outcome, reason });
+        }
+    }
+
+    // Outcome of the finished game, decided or on the board
+    pub fn outcome(&self, board: &Board) -> Option<GameOutcome> {
+        match &self.0 {
+            Some(decision) => decision.outcome,
+            None => board.tile_map.outcome(board.win_length),
+        }
+    }
+}
+
+fn clear_ending(mut ending: ResMut<Ending>) {
+    ending.0 = None;
+}
+
+// Everything needed to play a move on the board
+#[derive(SystemParam)]
+pub(crate) struct MoveContext<'w, 's> {
+    commands: Commands<'w, 's>,
+    board: ResMut<'w, Board>,
+    moves: ResMut<'w, MoveHistory>,
+    whose_turn: ResMut<'w, State<WhoseTurn>>,
+    playing_states: ResMut<'w, State<PlayingState>>,
+}
+
+impl<'w, 's> MoveContext<'w, 's> {
+    // Place the piece of the side to move on `coordinates` and pass the turn,
+    // ending the game if the move decides it. Returns false if the tile is taken.
+    pub fn play(&mut self, coordinates: Coordinates) -> bool {
+        let (tile, next_turn) = match self.whose_turn.current() {
+            WhoseTurn::XTurn => (Tile::X, WhoseTurn::OTurn),
+            WhoseTurn::OTurn => (Tile::O, WhoseTurn::XTurn),
+            WhoseTurn::Noone => return false,
+        };
+        let selected_tile: &mut Tile =
+            &mut self.board.tile_map.0[coordinates.x as usize][coordinates.y as usize];
+        if *selected_tile != Tile::Empty {
+            return false;
+        }
+
+        *selected_tile = tile;
+        let current_turn = self.whose_turn.current().clone();
+        self.whose_turn.set(next_turn).unwrap();
+        spawn_piece(&mut self.commands,
+                    self.board.coord_to_tile.get(&coordinates),
+                    &current_turn);
+        self.moves.0.push(coordinates);
+
+        if self.board.tile_map.outcome(self.board.win_length).is_some() {
+            self.playing_states.set(PlayingState::GameOver).unwrap();
+        }
+        true
+    }
+}
+
+// Sprite drawn on top of a tile, tagged with the tile it belongs to
+#[derive(Clone, Eq, PartialEq, Debug, Hash, Component)]
+pub(crate) struct Piece(pub Coordinates);
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         app.add_state(PlayingState::Init)
             .add_state(WhoseTurn::Noone)
-            .add_system_set(SystemSet::on_enter(GameState::Game).with_system(game_setup))
+            .init_resource::<EngineReport>()
+            .init_resource::<Ending>()
+            .add_system_set(SystemSet::on_enter(GameState::Game)
+                            .with_system(game_setup)
+                            .with_system(clear_ending))
+            .add_system_set(SystemSet::on_enter(PlayingState::Playing)
+                            .with_system(game_buttons_setup)
+                            .with_system(players_setup))
             .add_system_set(SystemSet::on_update(PlayingState::Playing)
-                            .with_system(input_handling)
+                            .with_system(play_turn)
+                            .with_system(show_cursor)
                             .with_system(render_piece)
-                            .with_system(game_button_action))
+                            .with_system(game_button_action)
+                            .with_system(hint_action)
+                            .with_system(hint_poll)
+                            .with_system(show_hint)
+                            .with_system(clear_hint))
             .add_system_set(SystemSet::on_exit(PlayingState::Playing)
+                            .with_system(stop_searches)
+                            .with_system(stop_hint))
+            .add_system_set(SystemSet::on_enter(MenuState::Paused)
+                            .with_system(pause_searches))
+            .add_system_set(SystemSet::on_update(GameState::Game)
+                            .with_system(game_buttons_display))
+            .add_system_set(SystemSet::on_enter(PlayingState::GameOver)
+                            .with_system(game_over_setup)
+                            .with_system(record_game))
+            .add_system_set(SystemSet::on_update(PlayingState::GameOver)
+                            .with_system(render_piece)
+                            .with_system(game_button_action))
+            .add_system_set(SystemSet::on_exit(GameState::Game)
                             .with_system(despawn_screen::<PlayingItem>));
     }
 }
 
+// The board of the game about to start
+#[derive(SystemParam)]
+pub(crate) struct BoardSettings<'w, 's> {
+    size: Res<'w, MatrixSize>,
+    win_length: Res<'w, WinLength>,
+    mode: Res<'w, GameMode>,
+    session: Option<Res<'w, Session>>,
+    replay: Option<Res<'w, Replay>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> BoardSettings<'w, 's> {
+    // A replayed game is shown on the board it was recorded on, and a game
+    // over the network is played on the board the host chose
+    pub fn size_and_win_length(&self) -> (u32, u32) {
+        match (&self.replay, &self.session) {
+            (Some(replay), _) => (replay.record.board_size, replay.record.win_length),
+            (None, Some(session)) if *self.mode == GameMode::Network => (session.size, session.win_length),
+            _ => (self.size.0, self.win_length.0),
+        }
+    }
+}
+
 fn game_setup(mut commands: Commands,
               mut playing_states: ResMut<State<PlayingState>>,
               mut whose_turn: ResMut<State<WhoseTurn>>,
               asset_server: Res<AssetServer>,
-              size: Res<MatrixSize>) {
+              settings: BoardSettings,
+              saved: Option<Res<SavedGame>>) {
 
-    // Mark that the following player is the one that plays with X
-    let _ = playing_states.set(PlayingState::Playing);
-    let _ = whose_turn.set(WhoseTurn::XTurn); 
+    let (size, win_length) = settings.size_and_win_length();
+
+    let next_state = match settings.replay {
+        Some(_) => PlayingState::Replaying,
+        None => PlayingState::Playing,
+    };
+    // Restarting a game being played enters it again, setting it up anew
+    if *playing_states.current() == next_state {
+        let _ = playing_states.restart();
+    } else {
+        let _ = playing_states.set(next_state);
+    }
     // Create an empty TileMap and insert the resource
-    let tile_map = TileMap::empty((*size).0);
+    let mut tile_map = TileMap::empty(size);
+
+    // A saved game goes on from the moves it was saved with
+    let mut moves = MoveHistory::default();
+    if let Some(saved) = saved {
+        for coordinates in saved.moves.iter().filter_map(|notation| Coordinates::from_notation(notation)) {
+            let side = moves.side_to_move();
+            let tile = tile_map.0.get_mut(coordinates.x as usize).and_then(|row| row.get_mut(coordinates.y as usize));
+            if let Some(tile) = tile.filter(|tile| **tile == Tile::Empty) {
+                *tile = side;
+                moves.0.push(coordinates);
+            }
+        }
+        commands.remove_resource::<SavedGame>();
+    }
 
-    let mut coord_to_tile = HashMap::with_capacity((*size).0 as usize * (*size).0 as usize);
+    // Mark that the following player is the one that plays with X
+    let _ = whose_turn.set(match moves.side_to_move() {
+        Tile::O => WhoseTurn::OTurn,
+        _ => WhoseTurn::XTurn,
+    });
+
+    let mut coord_to_tile = HashMap::with_capacity(size as usize * size as usize);
 
-    let tile_size = constants::LENGTH / (*size).0 as f32;
+    let tile_size = constants::LENGTH / size as f32;
 
     let start_x = -(constants::LENGTH / 2.0);
     let start_y = -(constants::LENGTH / 2.0);
@@ -58,23 +270,35 @@ fn game_setup(mut commands: Commands,
     let board_size = Vec2::new(constants::LENGTH, constants::LENGTH);
 
 
-    // Spawn the Back button
+    // Spawn the Back button at the bottom of the button column
     let (_, button_style, button_text_style) = get_menu_styles(asset_server);
-    commands.spawn_bundle(ButtonBundle {
-        style: button_style.clone(),
-        color: constants::NORMAL_BUTTON.into(),
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        color: Color::NONE.into(),
         ..default()
     })
-    .insert(Name::new("BackButton"))
-    .insert(Transform::from_translation(board_position))
-    .insert(GlobalTransform::default())
-    .insert(MenuButtonAction::BackToMainMenu)
+    .insert(Name::new("GameButtons"))
+    .insert(GameButtonColumn)
     .insert(PlayingItem)
     .with_children(|parent| {
-        parent.spawn_bundle(TextBundle::from_section(
-            constants::BACK_STRING,
-            button_text_style.clone(),
-        ));
+        parent.spawn_bundle(ButtonBundle {
+            style: button_style.clone(),
+            color: constants::NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(Name::new("BackButton"))
+        .insert(Transform::from_translation(board_position))
+        .insert(GlobalTransform::default())
+        .insert(MenuButtonAction::BackToMainMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                constants::BACK_STRING,
+                button_text_style.clone(),
+            ));
+        });
     });
 
 
@@ -121,6 +345,11 @@ fn game_setup(mut commands: Commands,
         }
     });
 
+    for coordinates in moves.0.iter() {
+        let tile = tile_map.0[coordinates.x as usize][coordinates.y as usize];
+        commands.entity(coord_to_tile[coordinates]).insert(tile);
+    }
+
     let board = Board {
         tile_map,
         bounds: Bounds2 {
@@ -128,64 +357,218 @@ fn game_setup(mut commands: Commands,
            size: constants::LENGTH, 
         },
         tile_size,
+        win_length: win_length.min(size),
         coord_to_tile 
     };
     commands.insert_resource(board);
+    commands.insert_resource(moves);
 }
 
-fn input_handling(windows: Res<Windows>,
-                  mut board: ResMut<Board>,
-                  mut whose_turn: ResMut<State<WhoseTurn>>,
-                  buttons: Res<Input<MouseButton>>,
-                  mut commands: Commands) {
-    let window = windows.get_primary().unwrap();
-    
-    if buttons.just_pressed(MouseButton::Left) {
-        if let Some(pos) = window.cursor_position() {
-            let tile_coordinates = board.mouse_position(window, pos);
-
-            if let Some(coordinates) = tile_coordinates {
-                println!("{:?}", coordinates);
-                let selected_tile: &mut Tile = 
-                    &mut board.tile_map.0[coordinates.x as usize][coordinates.y as usize];
-
-                if *selected_tile == Tile::Empty {
-                    match whose_turn.current() {
-                        WhoseTurn::XTurn => {
-                            *selected_tile = Tile::X;
-                            whose_turn.set(WhoseTurn::OTurn).unwrap();
-                            spawn_piece(&mut commands,
-                                        board.coord_to_tile.get(&coordinates),
-                                        &WhoseTurn::XTurn);
-                        }
-                        WhoseTurn::OTurn => {
-                            *selected_tile = Tile::O;
-                            whose_turn.set(WhoseTurn::XTurn).unwrap();
-                            spawn_piece(&mut commands,
-                                        board.coord_to_tile.get(&coordinates),
-                                        &WhoseTurn::OTurn);
-                        }
-                        _ => ()
-                    }
-                }
-                else {
-                    println!("Tile already pressed");
-                }
-
-                // display the board
-                board.tile_map.console_output();
-            } 
-        }
-    }
-}
-
-
-fn game_button_action(
-    interaction_query: Query<(&Interaction, &MenuButtonAction),(Changed<Interaction>, With<Button>)>,
+// Settings deciding who plays each side and how the AI searches
+#[derive(SystemParam)]
+pub(crate) struct PlayerSettings<'w, 's> {
+    pub mode: Res<'w, GameMode>,
+    pub x_player: Res<'w, XPlayer>,
+    pub o_player: Res<'w, OPlayer>,
+    pub ai_depth: Res<'w, AiDepth>,
+    pub ai_threads: Res<'w, AiThreads>,
+    pub difficulty: Res<'w, Difficulty>,
+    pub x_engine: Res<'w, XEngine>,
+    pub o_engine: Res<'w, OEngine>,
+    pub budget: Res<'w, MctsBudget>,
+    pub playout: Res<'w, MctsPlayout>,
+    pub book: Res<'w, OpeningBook>,
+    pub evaluator: Res<'w, Evaluator>,
+    pub external: Res<'w, ExternalEngine>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> PlayerSettings<'w, 's> {
+    pub fn engine_config(&self, side: Tile) -> EngineConfig {
+        EngineConfig {
+            engine: if side == Tile::X { self.x_engine.0 } else { self.o_engine.0 },
+            depth: self.difficulty.depth(*self.ai_depth),
+            threads: self.ai_threads.count(),
+            temperature: self.difficulty.temperature(),
+            budget: self.budget.0,
+            playout: self.playout.0,
+            book: *self.book,
+            evaluator: *self.evaluator,
+        }
+    }
+
+    // In games against the AI the human plays X and the AI plays O
+    pub fn players(&self) -> Players {
+        let (x, o) = match *self.mode {
+            GameMode::VsAi => (PlayerKind::Mouse, PlayerKind::Engine),
+            GameMode::VsPlayer => (PlayerKind::Mouse, PlayerKind::Mouse),
+            GameMode::Custom => (self.x_player.0, self.o_player.0),
+            // Set up by the network session instead
+            GameMode::Network => (PlayerKind::Mouse, PlayerKind::Mouse),
+        };
+        Players { x: self.create(x, Tile::X), o: self.create(o, Tile::O) }
+    }
+
+    fn create(&self, kind: PlayerKind, side: Tile) -> Box<dyn Player> {
+        match kind {
+            PlayerKind::Mouse => Box::new(MousePlayer),
+            PlayerKind::Keyboard => Box::<KeyboardPlayer>::default(),
+            PlayerKind::Engine => Box::new(EnginePlayer::new(self.engine_config(side))),
+            PlayerKind::Random => Box::new(RandomPlayer),
+            PlayerKind::External => Box::new(ExternalPlayer::new(self.external.0.as_deref(),
+                                                                 self.engine_config(side))),
+        }
+    }
+}
+
+fn players_setup(mut commands: Commands, settings: PlayerSettings, session: Option<Res<Session>>) {
+    let players = match session {
+        Some(session) if *settings.mode == GameMode::Network => session.players(),
+        _ => settings.players(),
+    };
+    commands.insert_resource(players);
+}
+
+// What the players give the game screen, ignored while a menu is open over it
+#[derive(SystemParam)]
+pub(crate) struct GameInput<'w, 's> {
+    windows: Res<'w, Windows>,
+    buttons: Res<'w, Input<MouseButton>>,
+    keys: Res<'w, Input<KeyCode>>,
+    menu_state: Res<'w, State<MenuState>>,
+    mode: Res<'w, GameMode>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> GameInput<'w, 's> {
+    pub fn menu_open(&self) -> bool {
+        *self.menu_state.current() != MenuState::Disabled
+    }
+
+    // Network games go on under the menu, since the other side plays on
+    pub fn paused(&self) -> bool {
+        self.menu_open() && *self.mode != GameMode::Network
+    }
+}
+
+// Ask the player of the side to move for its move and play it
+fn play_turn(input: GameInput,
+             mut players: ResMut<Players>,
+             mut report: ResMut<EngineReport>,
+             clocks: Option<Res<Clocks>>,
+             mut game: MoveContext) {
+    // Paused games wait, their searches stopped until they are resumed
+    if input.paused() {
+        return;
+    }
+    let window = input.windows.get_primary().unwrap();
+    let clicked = match window.cursor_position() {
+        Some(pos) if !input.menu_open() && input.buttons.just_pressed(MouseButton::Left) => {
+            game.board.mouse_position(window, pos)
+        }
+        _ => None,
+    };
+
+    let side = game.moves.side_to_move();
+    let mut turn = Turn {
+        tile_map: &game.board.tile_map,
+        side,
+        win_length: game.board.win_length,
+        clicked,
+        keys: &input.keys,
+        report: &mut report,
+        clock: clocks.map(|clocks| (clocks.get(side), clocks.increment)),
+    };
+    if let Some(coordinates) = players.get_mut(side).poll_move(&mut turn) {
+        debug!("{:?} plays {}", side, coordinates.notation());
+        if !game.play(coordinates) {
+            debug!("Tile already pressed");
+        }
+
+        // display the board
+        game.board.tile_map.console_output();
+    }
+}
+
+// Highlight the cursor of a keyboard player while it is their turn
+fn show_cursor(
+    mut commands: Commands,
+    mut shown: Local<Option<Coordinates>>,
+    players: Res<Players>,
+    moves: Res<MoveHistory>,
+    board: Res<Board>,
+    tile_query: Query<&Transform, With<Coordinates>>,
+    marker_query: Query<Entity, With<CursorMarker>>,
+    ) {
+    // Redraw when the cursor moved, or when its marker is missing in a new game
+    let cursor = players.get(moves.side_to_move()).cursor();
+    let drawn = !marker_query.is_empty();
+    if cursor == *shown && drawn == cursor.is_some() {
+        return;
+    }
+    for marker in marker_query.iter() {
+        commands.entity(marker).despawn_recursive();
+    }
+
+    let transform = cursor
+        .and_then(|coordinates| board.coord_to_tile.get(&coordinates))
+        .and_then(|tile| tile_query.get(*tile).ok());
+    if let Some(transform) = transform {
+        commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: constants::CURSOR_COLOR,
+                custom_size: Some(Vec2::splat(board.tile_size * 0.9)),
+                ..default()
+            },
+            transform: Transform::from_xyz(transform.translation.x, -transform.translation.y, 1.5),
+            ..default()
+        })
+        .insert(CursorMarker)
+        .insert(PlayingItem);
+    }
+    *shown = cursor;
+}
+
+fn stop_searches(mut players: ResMut<Players>, report: Res<EngineReport>) {
+    report.progress.stop();
+    players.x.cancel();
+    players.o.cancel();
+}
+
+// Network games go on while the pause menu is open over them
+fn pause_searches(mode: Res<GameMode>, players: ResMut<Players>, report: Res<EngineReport>) {
+    if *mode != GameMode::Network {
+        stop_searches(players, report);
+    }
+}
+
+// Hide the game buttons while a menu is open over the game
+fn game_buttons_display(menu_state: Res<State<MenuState>>,
+                        mut column_query: Query<&mut Style, With<GameButtonColumn>>) {
+    let display = match menu_state.current() {
+        MenuState::Disabled => Display::Flex,
+        _ => Display::None,
+    };
+    for mut style in column_query.iter_mut() {
+        if style.display != display {
+            style.display = display;
+        }
+    }
+}
+
+pub(crate) fn game_button_action(
+    interaction_query: Query<(&Interaction, &MenuButtonAction), ChangedButton>,
     mut game_state: ResMut<State<GameState>>,
-    mut playing_states: ResMut<State<PlayingState>>
+    mut playing_states: ResMut<State<PlayingState>>,
+    menu_state: Res<State<MenuState>>,
     )
 {
+    // The Back button of the settings opened from the pause menu is not this one
+    if *menu_state.current() != MenuState::Disabled {
+        return;
+    }
     for (interaction, menu_button_action) in interaction_query.iter() {
         if interaction == &Interaction::Clicked {
             if let MenuButtonAction::BackToMainMenu = menu_button_action {
@@ -196,7 +579,219 @@ fn game_button_action(
     }   
 }
 
-fn spawn_piece (commands: &mut Commands,
+// Add the Hint and Analysis buttons above the Back button
+fn game_buttons_setup(mut commands: Commands,
+                      asset_server: Res<AssetServer>,
+                      hint_limit: Res<HintLimit>,
+                      column_query: Query<Entity, With<GameButtonColumn>>) {
+    commands.insert_resource(Hint::default());
+
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+    for column in column_query.iter() {
+        commands.entity(column).with_children(|parent| {
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style.clone(),
+                color: constants::NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(Name::new("HintButton"))
+            .insert(GameButtonAction::Hint)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                    hint_label(&hint_limit, 0),
+                    button_text_style.clone(),
+                ))
+                .insert(HintButtonText);
+            });
+
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style.clone(),
+                color: constants::NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(Name::new("AnalysisButton"))
+            .insert(GameButtonAction::ToggleAnalysis)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                    constants::ANALYSIS_STRING,
+                    button_text_style.clone(),
+                ));
+            });
+        });
+    }
+}
+
+fn hint_label(hint_limit: &HintLimit, used: u32) -> String {
+    match hint_limit.0 {
+        Some(limit) => format!("{} ({})", constants::HINT_STRING, limit.saturating_sub(used)),
+        None => constants::HINT_STRING.to_string(),
+    }
+}
+
+// Ask the engine for the best move of the side to move, searching in the
+// background so that the game goes on meanwhile
+fn hint_action(
+    interaction_query: Query<(&Interaction, &GameButtonAction), Changed<Interaction>>,
+    mut hint: ResMut<Hint>,
+    board: Res<Board>,
+    whose_turn: Res<State<WhoseTurn>>,
+    hint_limit: Res<HintLimit>,
+    ai_depth: Res<AiDepth>,
+    ) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked || *action != GameButtonAction::Hint {
+            continue;
+        }
+        if hint.task.is_some() || hint_limit.0.is_some_and(|limit| hint.used >= limit) {
+            continue;
+        }
+        let player = match whose_turn.current() {
+            WhoseTurn::XTurn => Tile::X,
+            WhoseTurn::OTurn => Tile::O,
+            WhoseTurn::Noone => continue,
+        };
+        let tile_map = board.tile_map.clone();
+        let win_length = board.win_length;
+        let depth = ai_depth.0;
+        let progress = SearchProgress::default();
+        hint.progress = progress.clone();
+        hint.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+            ai::search(&tile_map, player, win_length, depth, &progress).best_move
+        }));
+    }
+}
+
+fn stop_hint(hint: Res<Hint>) {
+    hint.progress.stop();
+}
+
+// Show the suggestion once the search finished
+fn hint_poll(mut hint: ResMut<Hint>) {
+    let Some(task) = hint.task.as_mut() else { return };
+    let Some(suggestion) = future::block_on(future::poll_once(task)) else { return };
+    hint.task = None;
+    if suggestion.is_some() {
+        hint.suggestion = suggestion;
+        hint.used += 1;
+    }
+}
+
+// Highlight the suggested tile and update the number of hints left
+fn show_hint(
+    mut commands: Commands,
+    hint: Res<Hint>,
+    board: Res<Board>,
+    hint_limit: Res<HintLimit>,
+    tile_query: Query<&Transform, With<Coordinates>>,
+    marker_query: Query<Entity, With<HintMarker>>,
+    mut text_query: Query<&mut Text, With<HintButtonText>>,
+    ) {
+    if !hint.is_changed() {
+        return;
+    }
+    for marker in marker_query.iter() {
+        commands.entity(marker).despawn_recursive();
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = hint_label(&hint_limit, hint.used);
+    }
+
+    let transform = hint.suggestion
+        .and_then(|coordinates| board.coord_to_tile.get(&coordinates))
+        .and_then(|tile| tile_query.get(*tile).ok());
+    if let Some(transform) = transform {
+        commands.spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                color: constants::HINT_COLOR,
+                custom_size: Some(Vec2::splat(board.tile_size * 0.7)),
+                ..default()
+            },
+            transform: Transform::from_xyz(transform.translation.x, -transform.translation.y, 2.0),
+            ..default()
+        })
+        .insert(HintMarker)
+        .insert(PlayingItem);
+    }
+}
+
+// A hint is only valid until the next move is played, which also stops the
+// search for one
+fn clear_hint(moves: Res<MoveHistory>, mut hint: ResMut<Hint>) {
+    if !moves.is_changed() {
+        return;
+    }
+    if hint.task.is_some() {
+        hint.progress.stop();
+        hint.task = None;
+    }
+    if hint.suggestion.is_some() {
+        hint.suggestion = None;
+    }
+}
+
+// Show the result of the game next to the board, with the reason when the
+// board did not decide it
+fn game_over_setup(mut commands: Commands,
+                   board: Res<Board>,
+                   ending: Res<Ending>,
+                   asset_server: Res<AssetServer>) {
+    let result = match ending.outcome(&board) {
+        Some(GameOutcome::XWins) => constants::X_WINS_STRING,
+        Some(GameOutcome::OWins) => constants::O_WINS_STRING,
+        Some(GameOutcome::Draw) => constants::DRAW_STRING,
+        None => constants::ABANDONED_STRING,
+    };
+    let text = match &ending.0 {
+        Some(decision) => format!("{}\n{}", result, decision.reason),
+        None => result.to_string(),
+    };
+    let (font, _, _) = get_menu_styles(asset_server);
+
+    commands.spawn_bundle(Text2dBundle {
+        text: Text::from_section(text, TextStyle {
+            font,
+            font_size: constants::HUD_FONT_SIZE,
+            color: constants::TEXT_COLOR,
+        })
+        .with_alignment(TextAlignment::CENTER_LEFT),
+        transform: Transform::from_xyz(constants::LENGTH / 2.0 + constants::HUD_MARGIN, 0.0, 1.0),
+        ..default()
+    })
+    .insert(Name::new("GameOverText"))
+    .insert(PlayingItem);
+}
+
+// Append the finished game to the history file
+fn record_game(board: Res<Board>,
+               moves: Res<MoveHistory>,
+               mode: Res<GameMode>,
+               ending: Res<Ending>,
+               ai_depth: Res<AiDepth>,
+               difficulty: Res<Difficulty>) {
+    let result = match ending.outcome(&board) {
+        Some(result) => result,
+        None => return,
+    };
+    let record = GameRecord {
+        date: history::current_date(),
+        mode: *mode,
+        board_size: board.tile_map.0.len() as u32,
+        win_length: board.win_length,
+        ai_depth: match *mode {
+            GameMode::VsAi => Some(difficulty.depth(*ai_depth)),
+            GameMode::VsPlayer | GameMode::Custom | GameMode::Network => None,
+        },
+        result,
+        reason: ending.0.as_ref().map(|decision| decision.reason.clone()),
+        moves: moves.0.iter().map(Coordinates::notation).collect(),
+    };
+
+    if let Err(error) = history::append_record(&record) {
+        error!("Could not save the game history: {}", error);
+    }
+}
+
+pub(crate) fn spawn_piece (commands: &mut Commands,
                 entity: Option<&Entity>,
                 whose_turn: &WhoseTurn) {
 
@@ -213,12 +808,12 @@ fn spawn_piece (commands: &mut Commands,
     }
 }
 
-fn render_piece (mut commands: Commands,
+pub(crate) fn render_piece (mut commands: Commands,
                  board: Res<Board>,
                  tile_changed: Query<(&Coordinates, &Transform, &Tile), Added<Tile>>) {
 
-    for (_, pos, tile_type) in tile_changed.iter() {
-        println!("{:?}", pos);
+    for (coordinates, pos, tile_type) in tile_changed.iter() {
+        trace!("{:?} piece at {}", tile_type, pos.translation);
         commands.spawn_bundle(SpriteBundle {
             sprite: Sprite {
                 color: match tile_type {
@@ -234,6 +829,7 @@ fn render_piece (mut commands: Commands,
 
             }
         )
+        .insert(Piece(*coordinates))
         .insert(PlayingItem);
     }
 
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants, GameMode, GameOutcome};
+
+// One finished game, stored as a single JSON line in the history file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameRecord {
+    pub date: String,
+    pub mode: GameMode,
+    pub board_size: u32,
+    pub win_length: u32,
+    pub ai_depth: Option<u32>,
+    pub result: GameOutcome,
+    // Why the game ended when the board did not decide it, e.g. a resignation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub moves: Vec<String>,
+}
+
+// A game left unfinished from the pause menu, to be continued later. Only
+// one is kept, each save replacing the previous one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub date: String,
+    pub mode: GameMode,
+    pub board_size: u32,
+    pub win_length: u32,
+    // Time control of the game and the milliseconds left to X and O
+    pub time_control: Option<(u32, u32)>,
+    pub clock: Option<[u64; 2]>,
+    pub moves: Vec<String>,
+}
+
+// Wins, draws and losses seen from the human player's side
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ResultCount {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl ResultCount {
+    pub fn total(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // Percentages of wins, draws and losses
+    pub fn rates(&self) -> (f32, f32, f32) {
+        let total = self.total().max(1) as f32;
+        (
+            100.0 * self.wins as f32 / total,
+            100.0 * self.draws as f32 / total,
+            100.0 * self.losses as f32 / total,
+        )
+    }
+}
+
+impl GameRecord {
+    // In games against the AI the human always plays X
+    fn add_to(&self, count: &mut ResultCount) {
+        match self.result {
+            GameOutcome::XWins => count.wins += 1,
+            GameOutcome::Draw => count.draws += 1,
+            GameOutcome::OWins => count.losses += 1,
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        let mode = match self.mode {
+            GameMode::VsAi => format!("vs AI d{}", self.ai_depth.unwrap_or_default()),
+            GameMode::VsPlayer => "1vs1".to_string(),
+            GameMode::Custom => "custom".to_string(),
+            GameMode::Network => "LAN".to_string(),
+        };
+        let result = match self.result {
+            GameOutcome::XWins => constants::X_WINS_STRING,
+            GameOutcome::OWins => constants::O_WINS_STRING,
+            GameOutcome::Draw => constants::DRAW_STRING,
+        };
+        let reason = self.reason.as_ref().map(|reason| format!(", {}", reason)).unwrap_or_default();
+        format!("{}  {}x{} k{}  {}  {}{} ({} moves)",
+                self.date, self.board_size, self.board_size, self.win_length,
+                mode, result, reason, self.moves.len())
+    }
+}
+
+pub fn append_record(record: &GameRecord) -> std::io::Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(constants::HISTORY_FILE)?;
+    let line = serde_json::to_string(record)?;
+    writeln!(file, "{}", line)
+}
+
+// Read every record from the history file, skipping lines that do not parse
+pub fn load_records() -> Vec<GameRecord> {
+    let file = match OpenOptions::new().read(true).open(constants::HISTORY_FILE) {
+        Ok(file) => file,
+        Err(_) => return Vec::new(),
+    };
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect()
+}
+
+pub fn save_game(game: &SavedGame) -> std::io::Result<()> {
+    fs::write(constants::SAVED_GAME_FILE, serde_json::to_string(game)?)
+}
+
+pub fn load_saved_game() -> Option<SavedGame> {
+    let contents = fs::read_to_string(constants::SAVED_GAME_FILE).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+// Group the results of the games played against the AI by the given key
+pub fn ai_stats_by<F>(records: &[GameRecord], key: F) -> BTreeMap<u32, ResultCount>
+where
+    F: Fn(&GameRecord) -> u32,
+{
+    let mut stats: BTreeMap<u32, ResultCount> = BTreeMap::new();
+    for record in records.iter().filter(|record| record.mode == GameMode::VsAi) {
+        record.add_to(stats.entry(key(record)).or_default());
+    }
+    stats
+}
+
+// Current UTC time formatted as "YYYY-MM-DD HH:MM"
+pub fn current_date() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let days = (seconds / 86400) as i64;
+    let minutes = (seconds % 86400) / 60;
+
+    // Convert days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+                       - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", year, month, day, minutes / 60, minutes % 60)
+}
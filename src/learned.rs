@@ -0,0 +1,191 @@
+use std::{fs::File, io::{self, BufReader, BufWriter}, path::PathBuf, sync::OnceLock};
+
+use bevy::log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{constants, GameOutcome, Tile, bitboard::{self, Lines, Position}};
+
+// Open segments are counted by how many pieces they are short of a row,
+// from 1 up to this many
+const MISSING: usize = bitboard::MAX_SIZE as usize - 1;
+
+// Open segments of the side to move and of its opponent, the difference in
+// centre control, and a constant
+pub const FEATURES: usize = 2 * MISSING + 2;
+
+// Counts are scaled down so that the sum stays small while learning
+const FEATURE_SCALE: f32 = 0.1;
+
+// Search score of a value of 1, far below the scores of found wins
+const SCORE_SCALE: f32 = 10_000.0;
+
+// Settings of TD(λ) learning
+#[derive(Debug, Clone, Copy)]
+pub struct Training {
+    // Decay of the eligibility of earlier positions
+    pub lambda: f32,
+    pub rate: f32,
+    // Share of moves played at random to explore
+    pub exploration: f32,
+}
+
+// A linear value function squashed into (-1, 1), giving the chances of the
+// side to move
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValueFunction {
+    pub weights: [f32; FEATURES],
+    // Games learned from so far
+    pub games: u64,
+}
+
+impl Default for ValueFunction {
+    fn default() -> Self {
+        ValueFunction { weights: [0.0; FEATURES], games: 0 }
+    }
+}
+
+impl ValueFunction {
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &PathBuf) -> io::Result<Self> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    // Value of the position for the side to move
+    pub fn value(&self, position: &Position, lines: &Lines) -> f32 {
+        value_of(&self.weights, &features(position, lines))
+    }
+
+    // Search score of the position for `player`, in the units of the
+    // handwritten evaluation
+    pub fn evaluate(&self, position: &Position, player: Tile, lines: &Lines) -> i32 {
+        let value = self.value(position, lines) * SCORE_SCALE;
+        if player == position.side_to_move() { value as i32 } else { -value as i32 }
+    }
+
+    // Play one game against itself from the empty board and learn from it.
+    // Every move is the one leaving the opponent the lowest value, except for
+    // exploring moves drawn at random, after which the eligibility of the
+    // earlier positions is dropped since they did not lead there.
+    pub fn train_game(&mut self, size: u32, win_length: u32, training: &Training,
+                      rng: &fastrand::Rng) -> GameOutcome {
+        let lines = bitboard::lines(size, win_length);
+        let mut position = Position::empty(size);
+        let mut player = Tile::X;
+        let mut trace = [0.0; FEATURES];
+        loop {
+            let current = features(&position, lines);
+            let value = value_of(&self.weights, &current);
+            let moves = position.candidate_moves();
+
+            let exploring = rng.f32() < training.exploration;
+            let square = if exploring {
+                moves[rng.usize(..moves.len())]
+            } else {
+                self.greedy_move(&mut position, player, lines, &moves)
+            };
+            position.set(square, player);
+            let won = lines.is_win(position.pieces(player), square);
+
+            // The value of the next position is from the opponent's side
+            let (target, outcome) = if won {
+                let outcome = if player == Tile::X { GameOutcome::XWins } else { GameOutcome::OWins };
+                (1.0, Some(outcome))
+            } else if position.is_full() {
+                (0.0, Some(GameOutcome::Draw))
+            } else {
+                (-self.value(&position, lines), None)
+            };
+
+            if exploring && outcome.is_none() {
+                trace = [0.0; FEATURES];
+            } else {
+                // The sign alternates since the positions alternate sides
+                let slope = 1.0 - value * value;
+                for (eligibility, feature) in trace.iter_mut().zip(current.iter()) {
+                    *eligibility = -training.lambda * *eligibility + slope * feature;
+                }
+                let error = target - value;
+                for (weight, eligibility) in self.weights.iter_mut().zip(trace.iter()) {
+                    *weight += training.rate * error * eligibility;
+                }
+            }
+
+            if let Some(outcome) = outcome {
+                self.games += 1;
+                return outcome;
+            }
+            player = player.opponent();
+        }
+    }
+
+    // A winning move if there is one, otherwise the move after which the
+    // opponent's value is lowest
+    fn greedy_move(&self, position: &mut Position, player: Tile, lines: &Lines, moves: &[u32]) -> u32 {
+        let mut best = (moves[0], f32::NEG_INFINITY);
+        for square in moves.iter().copied() {
+            position.set(square, player);
+            let value = if lines.is_win(position.pieces(player), square) {
+                f32::INFINITY
+            } else {
+                -self.value(position, lines)
+            };
+            position.set(square, Tile::Empty);
+            if value > best.1 {
+                best = (square, value);
+            }
+        }
+        best.0
+    }
+}
+
+fn value_of(weights: &[f32; FEATURES], features: &[f32; FEATURES]) -> f32 {
+    weights.iter().zip(features.iter()).map(|(weight, feature)| weight * feature).sum::<f32>().tanh()
+}
+
+// Inputs of the value function, from the side of the player to move
+fn features(position: &Position, lines: &Lines) -> [f32; FEATURES] {
+    let mover = position.side_to_move();
+    let (own_pieces, other_pieces) = (position.pieces(mover), position.pieces(mover.opponent()));
+    let mut features = [0.0; FEATURES];
+    for mask in lines.masks.iter() {
+        let (own, other) = ((mask & own_pieces).count_ones(), (mask & other_pieces).count_ones());
+        let missing = |pieces: u32| (mask.count_ones() - pieces).clamp(1, MISSING as u32) as usize - 1;
+        if other == 0 && own > 0 {
+            features[missing(own)] += FEATURE_SCALE;
+        } else if own == 0 && other > 0 {
+            features[MISSING + missing(other)] += FEATURE_SCALE;
+        }
+    }
+
+    // Half steps closer to the centre than the corners, as a share of the
+    // distance from a corner to the centre
+    let last = position.size as i32 - 1;
+    let closeness = |pieces: u64| -> f32 {
+        bitboard::squares(pieces).map(|square| {
+            let (x, y) = ((square / position.size) as i32, (square % position.size) as i32);
+            (2 * last - (2 * x - last).abs() - (2 * y - last).abs()) as f32 / (2 * last).max(1) as f32
+        }).sum()
+    };
+    features[2 * MISSING] = FEATURE_SCALE * (closeness(own_pieces) - closeness(other_pieces));
+    features[2 * MISSING + 1] = 1.0;
+    features
+}
+
+// The learned value function when one has been trained, read from its file
+// the first time it is needed
+pub fn value_function() -> Option<&'static ValueFunction> {
+    static VALUE_FUNCTION: OnceLock<Option<ValueFunction>> = OnceLock::new();
+    VALUE_FUNCTION.get_or_init(|| match ValueFunction::load(&PathBuf::from(constants::LEARNED_FILE)) {
+        Ok(value_function) => Some(value_function),
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                warn!("Could not read the learned evaluator: {}", error);
+            }
+            None
+        }
+    }).as_ref()
+}
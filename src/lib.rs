@@ -0,0 +1,490 @@
+use serde::{Deserialize, Serialize};
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{menu::MenuPlugin, game::GamePlugin, replay::ReplayPlugin, analysis::AnalysisPlugin,
+            eval_bar::EvalBarPlugin, debug_panel::DebugPanelPlugin, online::OnlinePlugin, clock::ClockPlugin, pause::PausePlugin, resign::ResignPlugin};
+
+pub mod constants;
+mod menu;
+mod game;
+pub mod history;
+mod replay;
+pub mod ai;
+pub mod bitboard;
+mod analysis;
+mod eval_bar;
+mod debug_panel;
+pub mod mcts;
+pub mod solver;
+pub mod book;
+pub mod threats;
+pub mod engine;
+pub mod tuner;
+pub mod learned;
+pub mod protocol;
+pub mod net;
+mod online;
+mod clock;
+mod pause;
+mod resign;
+mod player;
+
+#[derive(Clone, Eq, PartialEq, Debug, Hash)]
+enum GameState {
+    Menu,
+    Game,
+}
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct MatrixSize (pub u32);
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct AiDepth (pub u32);
+
+// Number of threads the AI searches with, all cores when None
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct AiThreads (pub Option<u32>);
+
+impl AiThreads {
+    pub fn count(&self) -> usize {
+        match self.0 {
+            Some(threads) => threads.max(1) as usize,
+            None => ai::available_threads(),
+        }
+    }
+}
+
+// Named strength of the AI: how deep it searches and how often it plays a
+// weaker move on purpose
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub enum Difficulty {
+    Beginner,
+    Easy,
+    Medium,
+    Hard,
+    Perfect,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 5] = [
+        Difficulty::Beginner,
+        Difficulty::Easy,
+        Difficulty::Medium,
+        Difficulty::Hard,
+        Difficulty::Perfect,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Difficulty::Beginner => constants::BEGINNER_STRING,
+            Difficulty::Easy => constants::EASY_STRING,
+            Difficulty::Medium => constants::MEDIUM_STRING,
+            Difficulty::Hard => constants::HARD_STRING,
+            Difficulty::Perfect => constants::PERFECT_STRING,
+        }
+    }
+
+    // Search depth of this level, never deeper than the AI depth setting
+    pub fn depth(&self, ai_depth: AiDepth) -> u32 {
+        let depth = match self {
+            Difficulty::Beginner => 1,
+            Difficulty::Easy => 2,
+            Difficulty::Medium => 3,
+            Difficulty::Hard | Difficulty::Perfect => ai_depth.0,
+        };
+        depth.min(ai_depth.0)
+    }
+
+    // Softmax temperature over the scores of the AI's candidate moves,
+    // zero means the best move is always played
+    pub fn temperature(&self) -> f32 {
+        match self {
+            Difficulty::Beginner => 200.0,
+            Difficulty::Easy => 80.0,
+            Difficulty::Medium => 25.0,
+            Difficulty::Hard => 8.0,
+            Difficulty::Perfect => 0.0,
+        }
+    }
+}
+
+// Number of pieces in a row needed to win, capped by the matrix size
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct WinLength (pub u32);
+
+// Maximum number of hints per game, unlimited when None
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct HintLimit (pub Option<u32>);
+
+// Seconds on each clock at the start of a game and seconds added after every
+// move, or None to play without clocks
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct TimeControl(pub Option<(u32, u32)>);
+
+impl TimeControl {
+    // Written like "3+2" in minutes, or "30s+2" under a minute
+    pub fn label(&self) -> String {
+        match self.0 {
+            Some((time, increment)) if time < 60 => format!("{}s+{}", time, increment),
+            Some((time, increment)) => format!("{}+{}", time / 60, increment),
+            None => constants::CLOCK_OFF_STRING.to_string(),
+        }
+    }
+}
+
+// Search algorithm the AI uses for a side
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AiEngine {
+    Minimax,
+    Mcts,
+}
+
+// Who plays a side in a custom game
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PlayerKind {
+    Mouse,
+    Keyboard,
+    // The AI, searching with the engine set for the side
+    Engine,
+    Random,
+    // A program speaking the engine protocol, see `protocol`
+    External,
+}
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct XPlayer (pub PlayerKind);
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct OPlayer (pub PlayerKind);
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct XEngine (pub AiEngine);
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct OEngine (pub AiEngine);
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct MctsBudget (pub mcts::Budget);
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub struct MctsPlayout (pub mcts::Playout);
+
+// How the minimax AI uses the opening book when it plays its best moves
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub enum OpeningBook {
+    Off,
+    // Always the move with the highest weight
+    Best,
+    // Moves drawn by weight, so that games vary
+    Varied,
+}
+
+// Which evaluation the minimax AI uses where its search stops
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy)]
+pub enum Evaluator {
+    Handwritten,
+    // The value function learned by self-play, only offered once one has
+    // been trained
+    Learned,
+}
+
+// Command starting the engine of external players, given with `--engine`
+pub struct ExternalEngine (pub Option<String>);
+
+// Which kind of game was started from the main menu
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GameMode {
+    VsAi,
+    VsPlayer,
+    // Sides played as set in the player settings
+    Custom,
+    // Against another copy of the game over the local network
+    Network,
+}
+
+// How a finished game ended
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GameOutcome {
+    XWins,
+    OWins,
+    Draw,
+}
+
+impl GameOutcome {
+    // The outcome of the game when `side` wins it
+    pub fn win_for(side: Tile) -> Self {
+        match side {
+            Tile::O => GameOutcome::OWins,
+            _ => GameOutcome::XWins,
+        }
+    }
+}
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Tile {
+    Empty,
+    X,
+    O,
+}
+
+#[derive(Debug, Component, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Coordinates {
+    pub x: u16,
+    pub y: u16,
+}
+
+impl Tile {
+    pub fn opponent(&self) -> Tile {
+        match self {
+            Tile::X => Tile::O,
+            Tile::O => Tile::X,
+            Tile::Empty => Tile::Empty,
+        }
+    }
+}
+
+impl Coordinates {
+    // Column as a letter followed by the 1-based row, e.g. "b3"
+    pub fn notation(&self) -> String {
+        format!("{}{}", (b'a' + self.y as u8) as char, self.x + 1)
+    }
+
+    pub fn from_notation(notation: &str) -> Option<Self> {
+        let mut chars = notation.chars();
+        let column = chars.next()?;
+        if !column.is_ascii_lowercase() {
+            return None;
+        }
+        let row: u16 = chars.as_str().parse().ok()?;
+        if row == 0 {
+            return None;
+        }
+        Some(Coordinates {
+            x: row - 1,
+            y: (column as u8 - b'a') as u16,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TileMap (pub Vec<Vec<Tile>>);
+
+// Moves played so far in the current game, in order
+#[derive(Debug, Clone, Default)]
+pub struct MoveHistory (pub Vec<Coordinates>);
+
+impl MoveHistory {
+    // X always starts, so the side to move follows from the number of moves
+    pub fn side_to_move(&self) -> Tile {
+        if self.0.len().is_multiple_of(2) { Tile::X } else { Tile::O }
+    }
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TileSize {
+    Fixed(f32),
+    Adaptive {min: f32, max: f32},
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum BoardPosition {
+    // Centered board
+    Centered {offset: Vec3},
+    // Custom position
+    Custom(Vec3),
+}
+
+impl Default for TileSize {
+    fn default() -> Self {
+        Self::Adaptive {
+            min: constants::MIN_TILE_SIZE,
+            max: constants:: MAX_TILE_SIZE,
+        }
+    }
+}
+
+impl Default for BoardPosition {
+    fn default() -> Self {
+        Self::Centered { offset: Default::default() }
+    }
+}
+
+impl TileMap {
+    // Create an empty tile map
+    pub fn empty(size: u32) -> Self {
+        let map = (0..size).
+                    map(|_| (0..size).map(|_| Tile::Empty).collect())
+                 .collect();
+        TileMap(map)
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.0.iter().all(|line| line.iter().all(|tile| *tile != Tile::Empty))
+    }
+
+    // Return the piece that has `win_length` tiles in a row on any line
+    pub fn winner(&self, win_length: u32) -> Option<Tile> {
+        let size = self.0.len() as i32;
+        let directions = [(0, 1), (1, 0), (1, 1), (1, -1)];
+        for x in 0..size {
+            for y in 0..size {
+                let tile = self.0[x as usize][y as usize];
+                if tile == Tile::Empty {
+                    continue;
+                }
+                for (dx, dy) in directions {
+                    let in_row = (0..win_length as i32)
+                        .take_while(|step| {
+                            let (nx, ny) = (x + dx * step, y + dy * step);
+                            nx >= 0 && ny >= 0 && nx < size && ny < size
+                                && self.0[nx as usize][ny as usize] == tile
+                        })
+                        .count();
+                    if in_row == win_length as usize {
+                        return Some(tile);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Check whether the piece at `coordinates` is part of `win_length` in a row
+    pub fn is_winning_move(&self, coordinates: &Coordinates, win_length: u32) -> bool {
+        let size = self.0.len() as i32;
+        let (x, y) = (coordinates.x as i32, coordinates.y as i32);
+        let tile = self.0[x as usize][y as usize];
+        if tile == Tile::Empty {
+            return false;
+        }
+        let count_towards = |dx: i32, dy: i32| {
+            (1..win_length as i32)
+                .take_while(|step| {
+                    let (nx, ny) = (x + dx * step, y + dy * step);
+                    nx >= 0 && ny >= 0 && nx < size && ny < size
+                        && self.0[nx as usize][ny as usize] == tile
+                })
+                .count()
+        };
+        [(0, 1), (1, 0), (1, 1), (1, -1)].iter().any(|(dx, dy)| {
+            1 + count_towards(*dx, *dy) + count_towards(-dx, -dy) >= win_length as usize
+        })
+    }
+
+    // The game is over once someone has won or no empty tile is left
+    pub fn outcome(&self, win_length: u32) -> Option<GameOutcome> {
+        match self.winner(win_length) {
+            Some(Tile::X) => Some(GameOutcome::XWins),
+            Some(Tile::O) => Some(GameOutcome::OWins),
+            _ if self.is_full() => Some(GameOutcome::Draw),
+            _ => None,
+        }
+    }
+
+    // Log the board, one line per row
+    pub fn console_output(&self) {
+        let rows: Vec<String> = self.0.iter()
+            .map(|line| line.iter().map(|element| format!("{:?}", element)).collect::<Vec<_>>().join(" "))
+            .collect();
+        debug!("\n{}", rows.join("\n"));
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Bounds2 {
+    pub position: Vec2, 
+    pub size: f32,
+}
+
+impl Bounds2 {
+    pub fn in_bounds(&self, coords: Vec2) -> bool {
+        coords.x >= self.position.x 
+        && coords.x <= self.position.x + self.size
+        && coords.y >= self.position.y
+        && coords.y <= self.position.y + self.size
+    }
+}
+
+#[derive(Debug)]
+struct Board {
+    tile_map: TileMap,
+    tile_size: f32,
+    win_length: u32,
+    bounds: Bounds2,
+    coord_to_tile: HashMap<Coordinates, Entity>,
+}
+
+impl Board {
+    pub fn mouse_position(&self, window: &Window, position: Vec2) -> Option<Coordinates> {
+        let window_size = Vec2::new(window.width(), window.height());
+        let mut position = position - window_size / 2.;
+
+        position.y = -position.y;
+
+
+        if !self.bounds.in_bounds(position) {
+            return None;
+        }
+
+        let coordinates = position - self.bounds.position;
+        Some(Coordinates {
+            x: (coordinates.y / self.tile_size ) as u16,
+            y: (coordinates.x / self.tile_size ) as u16,
+        })
+    }
+}
+
+// Start the game window
+pub fn run() {
+    // `--engine COMMAND` gives the program played by external players
+    let args: Vec<String> = std::env::args().collect();
+    let external = args.iter().position(|arg| arg == "--engine").and_then(|index| args.get(index + 1)).cloned();
+    App::new()
+        .add_plugins(DefaultPlugins)
+        .insert_resource(MatrixSize(constants::DEFAULT_BOARD_SIZE))
+        .insert_resource(AiDepth(constants::DEFAULT_AI_DEPTH))
+        .insert_resource(AiThreads(None))
+        .insert_resource(Difficulty::Perfect)
+        .insert_resource(WinLength(constants::DEFAULT_WIN_LENGTH))
+        .insert_resource(GameMode::VsPlayer)
+        .insert_resource(HintLimit(None))
+        .insert_resource(TimeControl(None))
+        .insert_resource(XPlayer(PlayerKind::Mouse))
+        .insert_resource(OPlayer(PlayerKind::Engine))
+        .insert_resource(XEngine(AiEngine::Minimax))
+        .insert_resource(OEngine(AiEngine::Minimax))
+        .insert_resource(MctsBudget(mcts::Budget::Iterations(constants::DEFAULT_MCTS_ITERATIONS)))
+        .insert_resource(MctsPlayout(mcts::Playout::Heuristic))
+        .insert_resource(OpeningBook::Varied)
+        .insert_resource(Evaluator::Handwritten)
+        .insert_resource(ExternalEngine(external))
+        .add_startup_system(setup)
+        .add_state(GameState::Menu)
+        .add_plugin(MenuPlugin)
+        .add_plugin(GamePlugin)
+        .add_plugin(ReplayPlugin)
+        .add_plugin(AnalysisPlugin)
+        .add_plugin(EvalBarPlugin)
+        .add_plugin(DebugPanelPlugin)
+        .add_plugin(OnlinePlugin)
+        .add_plugin(ClockPlugin)
+        .add_plugin(PausePlugin)
+        .add_plugin(ResignPlugin)
+        .run();
+}
+
+fn setup(mut commands: Commands) {
+   commands.spawn_bundle(Camera2dBundle::default()); 
+   // Read the evaluation weights before the first search needs them, once
+   // problems with their files can be logged
+   ai::weights();
+   learned::value_function();
+}
+
+fn despawn_screen<T: Component>(to_despawn: Query<Entity, With<T>>, mut commands: Commands) {
+    for entity in &to_despawn {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
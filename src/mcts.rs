@@ -0,0 +1,220 @@
+use std::time::{Duration, Instant};
+
+use crate::{Tile, TileMap, ai::{self, SearchProgress, SearchResult},
+            bitboard::{self, Lines, Position}};
+
+// Exploration constant of the UCT formula
+const EXPLORATION: f32 = 1.41;
+
+// The result is published and the stop flag checked this often
+const REPORT_INTERVAL: u32 = 500;
+
+// A win rate of 100% is reported as this score, 0% as its negation
+const RATE_SCORE_SCALE: f32 = 1000.0;
+
+// How long the search may run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Iterations(u32),
+    Time(Duration),
+}
+
+// How the games are finished from a new node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Playout {
+    // Uniformly random moves
+    Random,
+    // Win when possible, otherwise block the opponent's win, otherwise play
+    // next to the last moves most of the time
+    Heuristic,
+}
+
+struct Node {
+    // Square of the move leading to this node and the side that played it
+    square: Option<u32>,
+    player: Tile,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    untried: Vec<u32>,
+    visits: u32,
+    // Sum of the playout results from `player`'s side: 1 for a win, 0.5 for a draw
+    reward: f32,
+    // Winner, or None for a draw, when the game ended with this move
+    terminal: Option<Option<Tile>>,
+}
+
+impl Node {
+    fn new(square: Option<u32>, player: Tile, parent: Option<usize>,
+           position: &Position, terminal: Option<Option<Tile>>, rng: &fastrand::Rng) -> Self {
+        let mut untried = if terminal.is_some() { Vec::new() } else { position.candidate_moves() };
+        rng.shuffle(&mut untried);
+        Node { square, player, parent, children: Vec::new(), untried, visits: 0, reward: 0.0, terminal }
+    }
+
+    fn win_rate(&self) -> f32 {
+        self.reward / self.visits.max(1) as f32
+    }
+}
+
+// Monte Carlo tree search for `player` using UCT until the budget is spent
+// or the search is stopped. The result of the search so far is published to
+// `progress` regularly; the reported depth is the length of the most visited
+// line. Moves are tried and played out in the order drawn from `rng`.
+pub fn search(tile_map: &TileMap, player: Tile, win_length: u32, budget: Budget, playout: Playout,
+              rng: &fastrand::Rng, progress: &SearchProgress) -> SearchResult {
+    let start = Instant::now();
+    let root = Position::from_tile_map(tile_map);
+    let lines = bitboard::lines(root.size, win_length);
+    let mut nodes = vec![Node::new(None, player.opponent(), None, &root, None, rng)];
+    let mut iterations = 0;
+    let mut reported = 0;
+
+    loop {
+        iterations += 1;
+        let mut position = root;
+
+        // Selection: walk down fully expanded nodes
+        let mut current = 0;
+        while nodes[current].untried.is_empty() && !nodes[current].children.is_empty() {
+            let parent_visits = (nodes[current].visits.max(1) as f32).ln();
+            current = *nodes[current].children.iter()
+                .max_by(|a, b| uct(&nodes[**a], parent_visits).total_cmp(&uct(&nodes[**b], parent_visits)))
+                .unwrap();
+            let node = &nodes[current];
+            position.set(node.square.unwrap(), node.player);
+        }
+
+        // Expansion: add one untried move
+        if let Some(square) = nodes[current].untried.pop() {
+            let mover = nodes[current].player.opponent();
+            position.set(square, mover);
+            let terminal = if lines.is_win(position.pieces(mover), square) {
+                Some(Some(mover))
+            } else if position.is_full() {
+                Some(None)
+            } else {
+                None
+            };
+            let child = Node::new(Some(square), mover, Some(current), &position, terminal, rng);
+            nodes.push(child);
+            let index = nodes.len() - 1;
+            nodes[current].children.push(index);
+            current = index;
+        }
+
+        // Simulation: the winner of the game from here
+        let node = &nodes[current];
+        let winner = match node.terminal {
+            Some(winner) => winner,
+            None => play_out(&mut position, node.player.opponent(), node.square, lines, playout, rng),
+        };
+
+        // Backpropagation
+        let mut next = Some(current);
+        while let Some(index) = next {
+            let node = &mut nodes[index];
+            node.visits += 1;
+            node.reward += match winner {
+                Some(tile) if tile == node.player => 1.0,
+                Some(_) => 0.0,
+                None => 0.5,
+            };
+            next = node.parent;
+        }
+
+        let finished = match budget {
+            Budget::Iterations(limit) => iterations >= limit,
+            Budget::Time(limit) => start.elapsed() >= limit,
+        };
+        if finished || iterations.is_multiple_of(REPORT_INTERVAL) {
+            let result = current_result(&nodes, &root, player);
+            progress.add_work((iterations - reported) as u64, 0);
+            reported = iterations;
+            progress.publish(&result);
+            if finished || progress.is_stopped() || nodes[0].children.is_empty() {
+                return result;
+            }
+        }
+    }
+}
+
+fn uct(node: &Node, ln_parent_visits: f32) -> f32 {
+    if node.visits == 0 {
+        return f32::INFINITY;
+    }
+    node.win_rate() + EXPLORATION * (ln_parent_visits / node.visits as f32).sqrt()
+}
+
+// Most visited move, and the line of most visited moves after it
+fn current_result(nodes: &[Node], root: &Position, player: Tile) -> SearchResult {
+    let most_visited = |index: usize| {
+        nodes[index].children.iter().copied().max_by_key(|child| nodes[*child].visits)
+    };
+    let mut pv = Vec::new();
+    let mut next = most_visited(0);
+    while let Some(index) = next {
+        pv.push(root.coordinates(nodes[index].square.unwrap()));
+        next = most_visited(index);
+    }
+
+    let score = match most_visited(0).map(|index| &nodes[index]) {
+        Some(node) if node.terminal == Some(Some(player)) => ai::WIN_SCORE - 1,
+        Some(node) => ((node.win_rate() - 0.5) * 2.0 * RATE_SCORE_SCALE) as i32,
+        None => 0,
+    };
+    SearchResult { player, best_move: pv.first().copied(), score, depth: pv.len() as u32, pv }
+}
+
+// Play the game to its end, `player` moving first, and return the winner
+fn play_out(position: &mut Position, mut player: Tile, last_move: Option<u32>, lines: &Lines,
+            playout: Playout, rng: &fastrand::Rng) -> Option<Tile> {
+    let mut empty: Vec<u32> = bitboard::squares(position.empty_squares()).collect();
+    let mut recent = [last_move, None];
+
+    while !empty.is_empty() {
+        let index = match playout {
+            Playout::Random => rng.usize(..empty.len()),
+            Playout::Heuristic => heuristic_move(position, &empty, player, &recent, lines, rng),
+        };
+        let square = empty.swap_remove(index);
+        position.set(square, player);
+        if lines.is_win(position.pieces(player), square) {
+            return Some(player);
+        }
+        recent = [Some(square), recent[0]];
+        player = player.opponent();
+    }
+    None
+}
+
+// Index into `empty` of the move a heuristic playout makes. Only the tiles
+// around the last two moves are checked for wins and blocks, since that is
+// where new threats appear.
+fn heuristic_move(position: &Position, empty: &[u32], player: Tile,
+                  recent: &[Option<u32>; 2], lines: &Lines, rng: &fastrand::Rng) -> usize {
+    let near_recent = |square: u32| {
+        let coordinates = position.coordinates(square);
+        recent.iter().flatten().any(|last| {
+            let last = position.coordinates(*last);
+            (coordinates.x as i32 - last.x as i32).abs() <= 1
+                && (coordinates.y as i32 - last.y as i32).abs() <= 1
+        })
+    };
+    let nearby: Vec<usize> = (0..empty.len()).filter(|index| near_recent(empty[*index])).collect();
+
+    for tile in [player, player.opponent()] {
+        let pieces = position.pieces(tile);
+        for index in nearby.iter() {
+            let square = empty[*index];
+            if lines.is_win(pieces | 1 << square, square) {
+                return *index;
+            }
+        }
+    }
+
+    if !nearby.is_empty() && rng.bool() {
+        nearby[rng.usize(..nearby.len())]
+    } else {
+        rng.usize(..empty.len())
+    }
+}
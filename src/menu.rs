@@ -1,7 +1,9 @@
 use bevy::{prelude::*, app::AppExit};
 
 use crate::{constants::{self, PRESSED_BUTTON, HOVERED_PRESS_BUTTON, NORMAL_BUTTON, HOVERED_BUTTON, TEXT_COLOR, GAME_STRING_FONT_SIZE},
-GameState, MatrixSize, AiDepth, despawn_screen};
+GameState, MatrixSize, AiDepth, AiThreads, WinLength, HintLimit, TimeControl, Difficulty, OpeningBook, Evaluator,
+            PlayerKind, XPlayer, OPlayer, AiEngine, XEngine, OEngine, MctsBudget, MctsPlayout, GameMode, mcts, learned, despawn_screen, history::{self, GameRecord}, replay::Replay,
+            game::PlayerSettings};
 
 pub struct MenuPlugin;
 
@@ -19,29 +21,80 @@ struct OnMatrixSizeMenuScreen;
 #[derive(Component)]
 struct OnAiDepthMenuScreen;
 
+#[derive(Component)]
+struct OnWinLengthMenuScreen;
+
+#[derive(Component)]
+struct OnHintLimitMenuScreen;
+
+#[derive(Component)]
+struct OnTimeControlMenuScreen;
+
+#[derive(Component)]
+struct OnDifficultyMenuScreen;
+
+#[derive(Component)]
+struct OnAiThreadsMenuScreen;
+
+#[derive(Component)]
+struct OnPlayersMenuScreen;
+
+// Tag component used to tag entities added on the statistics screen
+#[derive(Component)]
+struct OnStatisticsMenuScreen;
+
 // Tag component used to mark which setting is currently selected
 #[derive(Component)]
 struct SelectedOption;
 
+// Filter for the selected button among the options of setting T
+type SelectedOf<T> = (With<SelectedOption>, With<T>);
+
+// Filter for the buttons clicked, hovered or left since the last frame
+pub(crate) type ChangedButton = (Changed<Interaction>, With<Button>);
+
 // All actions that can be triggered from a button click 
 #[derive(Component)]
 pub enum MenuButtonAction {
     PlayAi,
     PlayPlayers,
+    PlayCustom,
+    PlayLan,
     Settings,
+    Statistics,
     SettingsMatrixSize,
     SettingsAiDepth,
+    SettingsWinLength,
+    SettingsHintLimit,
+    SettingsTimeControl,
+    SettingsDifficulty,
+    SettingsPlayers,
+    SettingsAiThreads,
     BackToMainMenu,
     BackToSettings,
+    OpenReplay(GameRecord),
+    LoadGame,
     Quit,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
-enum MenuState {
+pub(crate) enum MenuState {
     Main,
     Settings,
     SettingsMatrixSize,
     SettingsAiDepth,
+    SettingsWinLength,
+    SettingsHintLimit,
+    SettingsTimeControl,
+    SettingsDifficulty,
+    SettingsPlayers,
+    SettingsAiThreads,
+    Statistics,
+    // Hosting or joining a game over the network
+    Lan,
+    // Open over a game, which waits until it is resumed
+    Paused,
+    ConfirmQuit,
     Disabled,
 }
 
@@ -73,10 +126,70 @@ impl Plugin for MenuPlugin {
             .add_system_set(SystemSet::on_exit(MenuState::SettingsAiDepth)
                             .with_system(despawn_screen::<OnAiDepthMenuScreen>))
 
+            .add_system_set(SystemSet::on_enter(MenuState::SettingsWinLength)
+                            .with_system(settings_menu_win_length))
+            .add_system_set(SystemSet::on_update(MenuState::SettingsWinLength)
+                            .with_system(setting_button::<WinLength>))
+            .add_system_set(SystemSet::on_exit(MenuState::SettingsWinLength)
+                            .with_system(despawn_screen::<OnWinLengthMenuScreen>))
+
+            .add_system_set(SystemSet::on_enter(MenuState::SettingsHintLimit)
+                            .with_system(settings_menu_hint_limit))
+            .add_system_set(SystemSet::on_update(MenuState::SettingsHintLimit)
+                            .with_system(setting_button::<HintLimit>))
+            .add_system_set(SystemSet::on_exit(MenuState::SettingsHintLimit)
+                            .with_system(despawn_screen::<OnHintLimitMenuScreen>))
+
+            .add_system_set(SystemSet::on_enter(MenuState::SettingsTimeControl)
+                            .with_system(settings_menu_time_control))
+            .add_system_set(SystemSet::on_update(MenuState::SettingsTimeControl)
+                            .with_system(setting_button::<TimeControl>))
+            .add_system_set(SystemSet::on_exit(MenuState::SettingsTimeControl)
+                            .with_system(despawn_screen::<OnTimeControlMenuScreen>))
+            // Systems to handle the difficulty screen
+            .add_system_set(SystemSet::on_enter(MenuState::SettingsDifficulty)
+                            .with_system(settings_menu_difficulty))
+            .add_system_set(SystemSet::on_update(MenuState::SettingsDifficulty)
+                            .with_system(setting_button::<Difficulty>)
+                            .with_system(setting_button::<OpeningBook>)
+                            .with_system(setting_button::<Evaluator>))
+            .add_system_set(SystemSet::on_exit(MenuState::SettingsDifficulty)
+                            .with_system(despawn_screen::<OnDifficultyMenuScreen>))
+            // Systems to handle the AI threads screen
+            .add_system_set(SystemSet::on_enter(MenuState::SettingsAiThreads)
+                            .with_system(settings_menu_ai_threads))
+            .add_system_set(SystemSet::on_update(MenuState::SettingsAiThreads)
+                            .with_system(setting_button::<AiThreads>))
+            .add_system_set(SystemSet::on_exit(MenuState::SettingsAiThreads)
+                            .with_system(despawn_screen::<OnAiThreadsMenuScreen>))
+            // Systems to handle the players screen, one row of options per setting
+            .add_system_set(SystemSet::on_enter(MenuState::SettingsPlayers)
+                            .with_system(settings_menu_players))
+            .add_system_set(SystemSet::on_update(MenuState::SettingsPlayers)
+                            .with_system(setting_button::<XPlayer>)
+                            .with_system(setting_button::<OPlayer>)
+                            .with_system(setting_button::<XEngine>)
+                            .with_system(setting_button::<OEngine>)
+                            .with_system(setting_button::<MctsPlayout>)
+                            .with_system(setting_button::<MctsBudget>))
+            .add_system_set(SystemSet::on_exit(MenuState::SettingsPlayers)
+                            .with_system(despawn_screen::<OnPlayersMenuScreen>))
+
+            .add_system_set(SystemSet::on_enter(MenuState::Statistics)
+                            .with_system(statistics_menu_setup))
+            .add_system_set(SystemSet::on_exit(MenuState::Statistics)
+                            .with_system(despawn_screen::<OnStatisticsMenuScreen>))
+
             .add_system_set(
                 SystemSet::on_update(GameState::Menu)
                     .with_system(menu_action)
                     .with_system(button_system)
+            )
+            // The settings can also be changed from the pause menu
+            .add_system_set(
+                SystemSet::on_update(GameState::Game)
+                    .with_system(menu_action)
+                    .with_system(button_system)
             );
     }
 }
@@ -89,8 +202,7 @@ fn menu_setup(mut menu_state: ResMut<State<MenuState>>) {
 // SelectedOption component and then filter them by the components that
 // recently have been modified and also have a button component
 fn button_system(
-    mut interaction_query: Query<(&Interaction, &mut UiColor, Option<&SelectedOption>),
-    (Changed<Interaction>, With<Button>), >,)
+    mut interaction_query: Query<(&Interaction, &mut UiColor, Option<&SelectedOption>), ChangedButton>,)
 {
     // Go through all of the queried entities and dereference each of the 
     // component with the specified R/W "permissions"
@@ -112,9 +224,8 @@ fn button_system(
 // default settings and the new is added the selectedOption component.
 // Afterwards the global resource is modifed;
 fn setting_button<T: Component + PartialEq + Copy> (
-    interaction_query: Query<(&Interaction, &T, Entity),
-                            (Changed<Interaction>, With<Button>)>,
-    mut selected_querry: Query<(Entity, &mut UiColor), With<SelectedOption>>,
+    interaction_query: Query<(&Interaction, &T, Entity), ChangedButton>,
+    mut selected_querry: Query<(Entity, &mut UiColor), SelectedOf<T>>,
     mut commands: Commands,
     mut setting: ResMut<T>,
     ) {
@@ -131,36 +242,89 @@ fn setting_button<T: Component + PartialEq + Copy> (
 }
 
 fn menu_action(
-    interaction_query: Query<(&Interaction, &MenuButtonAction),
-    (Changed<Interaction>, With<Button>)
-    >,
+    interaction_query: Query<(&Interaction, &MenuButtonAction), ChangedButton>,
     mut app_exit_events: EventWriter<AppExit>,
     mut menu_state: ResMut<State<MenuState>>,
     mut game_state: ResMut<State<GameState>>,
+    mut game_mode: ResMut<GameMode>,
+    mut commands: Commands,
               )
 {
     for (interaction, menu_button_action) in interaction_query.iter() {
         if interaction == &Interaction::Clicked {
             match menu_button_action {
-                MenuButtonAction::PlayAi | MenuButtonAction::PlayPlayers => {
+                MenuButtonAction::PlayAi | MenuButtonAction::PlayPlayers | MenuButtonAction::PlayCustom => {
+                    *game_mode = match menu_button_action {
+                        MenuButtonAction::PlayAi => GameMode::VsAi,
+                        MenuButtonAction::PlayPlayers => GameMode::VsPlayer,
+                        _ => GameMode::Custom,
+                    };
                     game_state.set(GameState::Game).unwrap();
                     menu_state.set(MenuState::Disabled).unwrap();
                 },
+                MenuButtonAction::PlayLan =>
+                    menu_state.set(MenuState::Lan).unwrap(),
+
                 MenuButtonAction::Settings => 
                     menu_state.set(MenuState::Settings).unwrap(),
 
+                MenuButtonAction::Statistics =>
+                    menu_state.set(MenuState::Statistics).unwrap(),
+
                 MenuButtonAction::SettingsMatrixSize =>  
                     menu_state.set(MenuState::SettingsMatrixSize).unwrap(),
 
                 MenuButtonAction::SettingsAiDepth =>  
                     menu_state.set(MenuState::SettingsAiDepth).unwrap(),
+
+                MenuButtonAction::SettingsWinLength =>
+                    menu_state.set(MenuState::SettingsWinLength).unwrap(),
+
+                MenuButtonAction::SettingsHintLimit =>
+                    menu_state.set(MenuState::SettingsHintLimit).unwrap(),
+
+                MenuButtonAction::SettingsTimeControl =>
+                    menu_state.set(MenuState::SettingsTimeControl).unwrap(),
+
+                MenuButtonAction::SettingsDifficulty =>
+                    menu_state.set(MenuState::SettingsDifficulty).unwrap(),
+
+                MenuButtonAction::SettingsPlayers =>
+                    menu_state.set(MenuState::SettingsPlayers).unwrap(),
+
+                MenuButtonAction::SettingsAiThreads =>
+                    menu_state.set(MenuState::SettingsAiThreads).unwrap(),
                     
-                MenuButtonAction::BackToMainMenu =>  
-                    menu_state.set(MenuState::Main).unwrap(),
+                // During a game the settings go back to the pause menu, and
+                // the Back button of the game screen is left to the game
+                MenuButtonAction::BackToMainMenu => match game_state.current() {
+                    GameState::Game if *menu_state.current() == MenuState::Disabled => {}
+                    GameState::Game => menu_state.set(MenuState::Paused).unwrap(),
+                    GameState::Menu => menu_state.set(MenuState::Main).unwrap(),
+                },
 
                 MenuButtonAction::BackToSettings =>  
                     menu_state.set(MenuState::Settings).unwrap(),
 
+                MenuButtonAction::OpenReplay(record) => {
+                    commands.insert_resource(Replay::new(record.clone()));
+                    game_state.set(GameState::Game).unwrap();
+                    menu_state.set(MenuState::Disabled).unwrap();
+                },
+
+                // The board and the clock are set as the saved game had them
+                MenuButtonAction::LoadGame => {
+                    if let Some(saved) = history::load_saved_game() {
+                        *game_mode = saved.mode;
+                        commands.insert_resource(MatrixSize(saved.board_size));
+                        commands.insert_resource(WinLength(saved.win_length));
+                        commands.insert_resource(TimeControl(saved.time_control));
+                        commands.insert_resource(saved);
+                        game_state.set(GameState::Game).unwrap();
+                        menu_state.set(MenuState::Disabled).unwrap();
+                    }
+                },
+
                 MenuButtonAction::Quit => app_exit_events.send(AppExit),
             }
         }
@@ -190,7 +354,7 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Px(constants::LENGTH), Val::Px(constants::LENGTH)),
+                size: Size::new(Val::Px(constants::LENGTH), Val::Auto),
                 margin: UiRect::all(Val::Auto),
                 flex_direction: FlexDirection::ColumnReverse,
                 align_items: AlignItems::Center,
@@ -230,16 +394,60 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                             button_text_style.clone(),
                         ));
                     });
+                // A game saved from the pause menu can be continued
+                if history::load_saved_game().is_some() {
+                    parent
+                        .spawn_bundle(ButtonBundle {
+                            style: button_style.clone(),
+                            color: NORMAL_BUTTON.into(),
+                            ..default()
+                        })
+                        .insert(MenuButtonAction::LoadGame)
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle::from_section(
+                                constants::LOAD_GAME_STRING,
+                                button_text_style.clone(),
+                            ));
+                        });
+                }
+                // Both ways of playing another person share a row
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        },
+                        color: Color::NONE.into(),
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        for (action, text) in [(MenuButtonAction::PlayPlayers, constants::PLAY_AGAINST_PLAYER_STRING),
+                                               (MenuButtonAction::PlayLan, constants::PLAY_LAN_STRING)] {
+                            parent
+                                .spawn_bundle(ButtonBundle {
+                                    style: button_style.clone(),
+                                    color: NORMAL_BUTTON.into(),
+                                    ..default()
+                                })
+                                .insert(action)
+                                .with_children(|parent| {
+                                    parent.spawn_bundle(TextBundle::from_section(
+                                        text,
+                                        button_text_style.clone(),
+                                    ));
+                                });
+                        }
+                    });
                 parent
                     .spawn_bundle(ButtonBundle {
                         style: button_style.clone(),
                         color: NORMAL_BUTTON.into(),
                         ..default()
                     })
-                    .insert(MenuButtonAction::PlayPlayers)
+                    .insert(MenuButtonAction::Settings)
                     .with_children(|parent| {
                         parent.spawn_bundle(TextBundle::from_section(
-                            constants::PLAY_AGAINST_PLAYER_STRING,
+                            constants::SETTINGS_STRING,
                             button_text_style.clone(),
                         ));
                     });
@@ -249,10 +457,10 @@ fn main_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                         color: NORMAL_BUTTON.into(),
                         ..default()
                     })
-                    .insert(MenuButtonAction::Settings)
+                    .insert(MenuButtonAction::Statistics)
                     .with_children(|parent| {
                         parent.spawn_bundle(TextBundle::from_section(
-                            constants::SETTINGS_STRING,
+                            constants::STATISTICS_STRING,
                             button_text_style.clone(),
                         ));
                     });
@@ -277,8 +485,10 @@ fn settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.spawn_bundle( NodeBundle {
         style: Style {
             margin: UiRect::all(Val::Auto),
-            size: Size::new(Val::Px(constants::LENGTH), Val::Px(constants::LENGTH)),
-            flex_direction: FlexDirection::ColumnReverse,
+            size: Size::new(Val::Px(constants::LENGTH), Val::Auto),
+            // Two columns of settings, filled row by row from the top
+            flex_direction: FlexDirection::Row,
+            flex_wrap: FlexWrap::WrapReverse,
             align_items: AlignItems::Center,
             justify_content: JustifyContent::Center,
             ..default()
@@ -289,8 +499,14 @@ fn settings_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
     .insert(OnSettingsMenuScreen)
     .with_children(|parent| {
         for (action, text) in [
+            (MenuButtonAction::SettingsDifficulty, constants::DIFFICULTY_SETTING_STRING),
+            (MenuButtonAction::SettingsPlayers, constants::PLAYERS_SETTING_STRING),
             (MenuButtonAction::SettingsAiDepth, constants::AI_DEPTH_SETTING_STRING),
+            (MenuButtonAction::SettingsAiThreads, constants::AI_THREADS_SETTING_STRING),
             (MenuButtonAction::SettingsMatrixSize, constants::MATRIX_SIZE_SETTING_STRING),
+            (MenuButtonAction::SettingsWinLength, constants::WIN_LENGTH_SETTING_STRING),
+            (MenuButtonAction::SettingsHintLimit, constants::HINT_LIMIT_SETTING_STRING),
+            (MenuButtonAction::SettingsTimeControl, constants::TIME_CONTROL_SETTING_STRING),
             (MenuButtonAction::BackToMainMenu, constants::BACK_STRING),
         ] {
             parent
@@ -454,3 +670,545 @@ fn settings_menu_ai_depth(
         });
 }
 
+
+fn settings_menu_win_length(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    win_length: Res<WinLength>
+    ) {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnWinLengthMenuScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                color: Color::GRAY.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        "Win length",
+                        button_text_style.clone(),
+                ));
+                for current_length in 3..9 {
+                    let mut entity = parent.spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Px(50.0), Val::Px(50.0)),
+                            ..button_style.clone()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    });
+                    entity.insert(WinLength(current_length));
+                    entity.with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle::from_section(
+                                    current_length.to_string(),
+                                    button_text_style.clone(),
+                            ));
+                    });
+                    if *win_length == WinLength(current_length) {
+                        entity.insert(SelectedOption);
+                    }
+                }
+            });
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style,
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::BackToSettings)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::BACK_STRING,
+                        button_text_style,
+                ));
+            });
+
+        });
+}
+
+fn settings_menu_hint_limit(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    hint_limit: Res<HintLimit>
+    ) {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnHintLimitMenuScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                color: Color::GRAY.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        "Hints per game",
+                        button_text_style.clone(),
+                ));
+                for current_limit in [Some(0), Some(1), Some(3), Some(5), None] {
+                    let (size, text) = match current_limit {
+                        Some(limit) => (Size::new(Val::Px(50.0), Val::Px(50.0)), limit.to_string()),
+                        None => (Size::new(Val::Auto, Val::Px(50.0)), constants::NO_LIMIT_STRING.to_string()),
+                    };
+                    let mut entity = parent.spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size,
+                            ..button_style.clone()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    });
+                    entity.insert(HintLimit(current_limit));
+                    entity.with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle::from_section(
+                                    text,
+                                    button_text_style.clone(),
+                            ));
+                    });
+                    if *hint_limit == HintLimit(current_limit) {
+                        entity.insert(SelectedOption);
+                    }
+                }
+            });
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style,
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::BackToSettings)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::BACK_STRING,
+                        button_text_style,
+                ));
+            });
+
+        });
+}
+
+fn settings_menu_time_control(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    time_control: Res<TimeControl>
+    ) {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnTimeControlMenuScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                color: Color::GRAY.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        "Clock",
+                        button_text_style.clone(),
+                ));
+                for current_control in [None, Some((30, 2)), Some((60, 0)), Some((180, 2)), Some((300, 0))] {
+                    let mut entity = parent.spawn_bundle(ButtonBundle {
+                        style: Style {
+                            size: Size::new(Val::Auto, Val::Px(50.0)),
+                            ..button_style.clone()
+                        },
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    });
+                    entity.insert(TimeControl(current_control));
+                    entity.with_children(|parent| {
+                        parent
+                            .spawn_bundle(TextBundle::from_section(
+                                    TimeControl(current_control).label(),
+                                    button_text_style.clone(),
+                            ));
+                    });
+                    if *time_control == TimeControl(current_control) {
+                        entity.insert(SelectedOption);
+                    }
+                }
+            });
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style,
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::BackToSettings)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::BACK_STRING,
+                        button_text_style,
+                ));
+            });
+
+        });
+}
+
+// Difficulty levels are listed in a column since their names are long,
+// followed by how the AI uses the opening book and which evaluation it uses
+fn settings_menu_difficulty(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    difficulty: Res<Difficulty>,
+    book: Res<OpeningBook>,
+    evaluator: Res<Evaluator>,
+    ) {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnDifficultyMenuScreen)
+        .with_children(|parent| {
+            for level in Difficulty::ALL {
+                let mut entity = parent.spawn_bundle(ButtonBundle {
+                    style: button_style.clone(),
+                    color: NORMAL_BUTTON.into(),
+                    ..default()
+                });
+                entity.insert(level);
+                entity.with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle::from_section(
+                                level.name(),
+                                button_text_style.clone(),
+                        ));
+                });
+                if *difficulty == level {
+                    entity.insert(SelectedOption);
+                }
+            }
+            spawn_option_row(parent, constants::OPENING_BOOK_STRING,
+                             &[(OpeningBook::Off, constants::BOOK_OFF_STRING),
+                               (OpeningBook::Best, constants::BOOK_BEST_STRING),
+                               (OpeningBook::Varied, constants::BOOK_VARIED_STRING)],
+                             *book, &button_style, &button_text_style);
+            // The learned evaluation can only be chosen once it has been trained
+            let evaluators = [(Evaluator::Handwritten, constants::HANDWRITTEN_STRING),
+                              (Evaluator::Learned, constants::LEARNED_STRING)];
+            let offered = if learned::value_function().is_some() { evaluators.len() } else { 1 };
+            spawn_option_row(parent, constants::EVALUATOR_STRING, &evaluators[..offered],
+                             *evaluator, &button_style, &button_text_style);
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style,
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::BackToSettings)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::BACK_STRING,
+                        button_text_style,
+                ));
+            });
+
+        });
+}
+
+// A label followed by one button per option of a setting
+fn spawn_option_row<T: Component + PartialEq + Copy>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    options: &[(T, &str)],
+    current: T,
+    button_style: &Style,
+    button_text_style: &TextStyle,
+    ) {
+    parent.spawn_bundle(NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(
+                label,
+                button_text_style.clone(),
+        ));
+        for (option, text) in options {
+            let mut entity = parent.spawn_bundle(ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Auto, Val::Px(50.0)),
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..button_style.clone()
+                },
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            });
+            entity.insert(*option);
+            entity.with_children(|parent| {
+                parent
+                    .spawn_bundle(TextBundle::from_section(
+                            *text,
+                            button_text_style.clone(),
+                    ));
+            });
+            if current == *option {
+                entity.insert(SelectedOption);
+            }
+        }
+    });
+}
+
+fn settings_menu_ai_threads(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    ai_threads: Res<AiThreads>
+    ) {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnAiThreadsMenuScreen)
+        .with_children(|parent| {
+            spawn_option_row(parent, constants::AI_THREADS_SETTING_STRING,
+                             &[(AiThreads(Some(1)), "1"),
+                               (AiThreads(Some(2)), "2"),
+                               (AiThreads(Some(4)), "4"),
+                               (AiThreads(Some(8)), "8"),
+                               (AiThreads(None), constants::ALL_THREADS_STRING)],
+                             *ai_threads, &button_style, &button_text_style);
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style,
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::BackToSettings)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::BACK_STRING,
+                        button_text_style,
+                ));
+            });
+
+        });
+}
+
+// Who plays each side of a custom game, the engine used by the AI for each
+// side and how MCTS runs its searches. A custom game is started from here.
+fn settings_menu_players(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    settings: PlayerSettings,
+    ) {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+    let players = [
+        (PlayerKind::Mouse, constants::MOUSE_STRING),
+        (PlayerKind::Keyboard, constants::KEYBOARD_STRING),
+        (PlayerKind::Engine, constants::AI_STRING),
+        (PlayerKind::Random, constants::RANDOM_STRING),
+        (PlayerKind::External, constants::EXTERNAL_STRING),
+    ];
+    let engines = [(AiEngine::Minimax, constants::MINIMAX_STRING), (AiEngine::Mcts, constants::MCTS_STRING)];
+    let seconds = |seconds| MctsBudget(mcts::Budget::Time(std::time::Duration::from_secs(seconds)));
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnPlayersMenuScreen)
+        .with_children(|parent| {
+            spawn_option_row(parent, constants::X_PLAYER_STRING,
+                             &players.map(|(kind, text)| (XPlayer(kind), text)),
+                             *settings.x_player, &button_style, &button_text_style);
+            spawn_option_row(parent, constants::O_PLAYER_STRING,
+                             &players.map(|(kind, text)| (OPlayer(kind), text)),
+                             *settings.o_player, &button_style, &button_text_style);
+            spawn_option_row(parent, constants::X_ENGINE_STRING,
+                             &engines.map(|(engine, text)| (XEngine(engine), text)),
+                             *settings.x_engine, &button_style, &button_text_style);
+            spawn_option_row(parent, constants::O_ENGINE_STRING,
+                             &engines.map(|(engine, text)| (OEngine(engine), text)),
+                             *settings.o_engine, &button_style, &button_text_style);
+            spawn_option_row(parent, constants::PLAYOUT_STRING,
+                             &[(MctsPlayout(mcts::Playout::Random), constants::RANDOM_PLAYOUT_STRING),
+                               (MctsPlayout(mcts::Playout::Heuristic), constants::HEURISTIC_PLAYOUT_STRING)],
+                             *settings.playout, &button_style, &button_text_style);
+            spawn_option_row(parent, constants::BUDGET_STRING,
+                             &[(MctsBudget(mcts::Budget::Iterations(2000)), "2k"),
+                               (MctsBudget(mcts::Budget::Iterations(constants::DEFAULT_MCTS_ITERATIONS)), "20k"),
+                               (MctsBudget(mcts::Budget::Iterations(100000)), "100k"),
+                               (seconds(1), "1s"),
+                               (seconds(5), "5s")],
+                             *settings.budget, &button_style, &button_text_style);
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style.clone(),
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::PlayCustom)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::PLAY_CUSTOM_STRING,
+                        button_text_style.clone(),
+                ));
+            });
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style,
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::BackToSettings)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(
+                        constants::BACK_STRING,
+                        button_text_style,
+                ));
+            });
+
+        });
+}
+
+// Win/draw/loss rates against the AI per depth and per board size,
+// followed by the most recent games
+fn statistics_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let (font, button_style, button_text_style) = get_menu_styles(asset_server);
+    let stats_text_style = TextStyle {
+        font,
+        font_size: constants::STATS_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+
+    let records = history::load_records();
+    let by_depth = history::ai_stats_by(&records, |record| record.ai_depth.unwrap_or_default());
+    let by_size = history::ai_stats_by(&records, |record| record.board_size);
+
+    let mut lines = Vec::new();
+    for (depth, count) in by_depth.iter() {
+        let (wins, draws, losses) = count.rates();
+        lines.push(format!("AI depth {}: {} games, W {:.0}% / D {:.0}% / L {:.0}%",
+                           depth, count.total(), wins, draws, losses));
+    }
+    for (size, count) in by_size.iter() {
+        let (wins, draws, losses) = count.rates();
+        lines.push(format!("Board {}x{}: {} games, W {:.0}% / D {:.0}% / L {:.0}%",
+                           size, size, count.total(), wins, draws, losses));
+    }
+
+    commands.spawn_bundle( NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnStatisticsMenuScreen)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(
+                constants::STATISTICS_STRING,
+                button_text_style.clone(),
+        ));
+        for line in lines {
+            parent.spawn_bundle(TextBundle::from_section(line, stats_text_style.clone()));
+        }
+
+        parent.spawn_bundle(TextBundle::from_section(
+                constants::RECENT_GAMES_STRING,
+                button_text_style.clone(),
+        ));
+        // Each recent game is a button that opens it in the replay viewer
+        for record in records.iter().rev().take(constants::RECENT_GAMES_COUNT) {
+            parent.spawn_bundle(ButtonBundle {
+                style: Style {
+                    margin: UiRect::all(Val::Px(constants::REPLAY_BUTTON_MARGIN)),
+                    padding: UiRect::all(Val::Px(constants::REPLAY_BUTTON_MARGIN)),
+                    ..default()
+                },
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(MenuButtonAction::OpenReplay(record.clone()))
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(record.summary(),
+                                                             stats_text_style.clone()));
+            });
+        }
+
+        parent.spawn_bundle(ButtonBundle {
+            style: button_style,
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(MenuButtonAction::BackToMainMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                    constants::BACK_STRING,
+                    button_text_style,
+            ));
+        });
+    });
+}
@@ -0,0 +1,303 @@
+// Messages between two copies of the game playing over the network, sent as
+// one JSON object per line over TCP. The joining side opens with `Hello`,
+// and the host answers with `Welcome` and the settings of the game, or with
+// `Reject`. Both sides then send their own moves, numbered from 0, and check
+// every move they receive against their board. Spectators open with `Watch`
+// instead; the host sends them the game so far in `Watching`, then every
+// move of both sides and how the game ended, and `Watching` again whenever
+// a new game starts.
+//
+// A game server (see the `server` binary) pairs players instead: they open
+// with `Seek` and the board they want, wait after `Waiting` until another
+// player wants the same board, and are then sent `Welcome` as if by a host.
+// The server passes every checked move on, and sends `Clock` after each move
+// and `Ended` when a game ends other than on the board, e.g. by a draw agreed
+// on.
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Mutex, mpsc::{self, Receiver, Sender, TryRecvError}};
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Coordinates, GameOutcome, Tile, TileMap, bitboard};
+
+// Raised whenever a message changes in a way older copies cannot read
+pub const PROTOCOL_VERSION: u32 = 5;
+
+// How long joining waits for the host to accept the connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How long a message may take to be written before the other side is taken
+// to be gone and the connection is closed
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Longest line read from the other side, well above the longest message of a
+// game on the largest board. A longer one closes the connection.
+const MAX_LINE_LENGTH: usize = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Message {
+    Hello { version: u32 },
+    // The game settings, with the side played by whoever joined
+    Welcome { version: u32, size: u32, win_length: u32, side: Tile },
+    Reject { reason: String },
+    Watch { version: u32 },
+    // The settings and moves of the game being played, sent to spectators
+    Watching { version: u32, size: u32, win_length: u32, moves: Vec<String> },
+    // A game ended other than on the board, sent to spectators. Without an
+    // outcome the game was abandoned.
+    Ended { outcome: Option<GameOutcome>, reason: String },
+    Seek { version: u32, size: u32, win_length: u32, name: String },
+    Waiting,
+    // Milliseconds left to each side when sent; the clock of the side to
+    // move runs from then on
+    Clock { x: u64, o: u64 },
+    Move { ply: usize, notation: String },
+    Resign,
+    // Draw messages carry the number of moves on the board they were sent
+    // at. An offer stands until the other side answers it or a move is
+    // played, and an answer only counts for the position the offer was made
+    // in. An accepted offer ends the game once its maker, or the server, sends
+    // `Ended`.
+    OfferDraw { ply: usize },
+    AcceptDraw { ply: usize },
+    DeclineDraw { ply: usize },
+    // Asks for a new game with the sides swapped, which starts once both
+    // sides asked for it
+    Rematch,
+    Chat { text: String },
+    // The sender is leaving
+    Bye,
+}
+
+// A connection to the other side. Messages are read and written on threads
+// of their own so that neither a slow nor a silent peer blocks whoever holds
+// the connection; it is shut down once this is dropped and the messages sent
+// before are written.
+pub struct Connection {
+    // Lines for the writing thread
+    outgoing: Sender<String>,
+    // Behind a lock so that the connection can be kept in a resource
+    messages: Mutex<Receiver<Message>>,
+    peer: SocketAddr,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let peer = stream.peer_addr()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        // The channel closes when the stream does, or once a line is too
+        // long. Lines that are not a known message are skipped.
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut line = Vec::new();
+            loop {
+                line.clear();
+                match reader.by_ref().take(MAX_LINE_LENGTH as u64 + 1).read_until(b'\n', &mut line) {
+                    Ok(0) | Err(_) => break,
+                    Ok(length) if length > MAX_LINE_LENGTH && !line.ends_with(b"\n") => {
+                        let _ = reader.get_ref().shutdown(Shutdown::Both);
+                        break;
+                    }
+                    Ok(_) => {}
+                }
+                if let Ok(message) = serde_json::from_slice(&line) {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        // Writing stops at the first error, e.g. when the peer has not read
+        // for WRITE_TIMEOUT, which also ends the reading
+        let (outgoing, lines) = mpsc::channel::<String>();
+        thread::spawn(move || {
+            for line in lines {
+                if (&stream).write_all(line.as_bytes()).is_err() {
+                    break;
+                }
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+        });
+        Ok(Connection { outgoing, messages: Mutex::new(messages), peer })
+    }
+
+    // Connect to `address` on a thread of its own; the connection or the
+    // error arrives on the returned channel
+    pub fn connect(address: &str) -> Receiver<io::Result<Connection>> {
+        let (sender, receiver) = mpsc::channel();
+        let address = address.to_string();
+        thread::spawn(move || {
+            let connect = || -> io::Result<Connection> {
+                let mut error = io::Error::new(io::ErrorKind::NotFound, "the address could not be resolved");
+                for socket in address.to_socket_addrs()? {
+                    match TcpStream::connect_timeout(&socket, CONNECT_TIMEOUT) {
+                        Ok(stream) => return Connection::new(stream),
+                        Err(failed) => error = failed,
+                    }
+                }
+                Err(error)
+            };
+            let _ = sender.send(connect());
+        });
+        receiver
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.peer
+    }
+
+    // Queue `message` to be written, or fail once writing stopped
+    pub fn send(&self, message: &Message) -> io::Result<()> {
+        let mut line = serde_json::to_string(message)?;
+        line.push('\n');
+        self.outgoing.send(line)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the connection was closed"))
+    }
+
+    // The next message if one has arrived, or an error once the connection
+    // is closed
+    pub fn try_receive(&self) -> io::Result<Option<Message>> {
+        match self.messages.lock().unwrap().try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                Err(io::Error::new(io::ErrorKind::ConnectionAborted, "the connection was closed"))
+            }
+        }
+    }
+}
+
+// Waits for connections without blocking
+pub struct Listener(TcpListener);
+
+impl Listener {
+    // Listen on `port` of every interface of this machine
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Listener(listener))
+    }
+
+    pub fn port(&self) -> io::Result<u16> {
+        Ok(self.0.local_addr()?.port())
+    }
+
+    // A connection if one came in since the last call
+    pub fn accept(&self) -> io::Result<Option<Connection>> {
+        match self.0.accept() {
+            Ok((stream, _)) => {
+                stream.set_nonblocking(false)?;
+                Connection::new(stream).map(Some)
+            }
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(None),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+// The address of this machine on its local network, found by routing a UDP
+// socket to an outside address without sending anything
+pub fn local_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0)).ok()?;
+    socket.connect(("8.8.8.8", 80)).ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+// Whether a game on a `size` board with `win_length` in a row can be played
+// over the network
+pub fn playable(size: u32, win_length: u32) -> bool {
+    (1..=bitboard::MAX_SIZE).contains(&size) && (1..=size).contains(&win_length)
+}
+
+// The tile of move `ply` given in `notation` by the player of `side`, if it
+// is the next move of the game on `tile_map`, which has `played` moves
+pub fn validate_move(tile_map: &TileMap, win_length: u32, played: usize, side: Tile,
+                     ply: usize, notation: &str) -> Result<Coordinates, String> {
+    if tile_map.outcome(win_length).is_some() {
+        return Err(format!("move {} came after the end of the game", ply + 1));
+    }
+    if ply != played {
+        return Err(format!("move {} came when move {} was expected", ply + 1, played + 1));
+    }
+    if ply.is_multiple_of(2) != (side == Tile::X) {
+        return Err(format!("move {} came from the side not to move", ply + 1));
+    }
+    let coordinates = Coordinates::from_notation(notation).ok_or_else(|| format!("\"{}\" is not a move", notation))?;
+    match tile_map.0.get(coordinates.x as usize).and_then(|line| line.get(coordinates.y as usize)) {
+        Some(Tile::Empty) => Ok(coordinates),
+        Some(_) => Err(format!("{} is already taken", notation)),
+        None => Err(format!("{} is off the board", notation)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::Position;
+
+    fn tile_map(text: &str) -> TileMap {
+        Position::from_text(text).unwrap().to_tile_map()
+    }
+
+    #[test]
+    fn playable_boards_fit_the_bitboard() {
+        assert!(playable(3, 3));
+        assert!(playable(bitboard::MAX_SIZE, 1));
+        assert!(!playable(0, 0));
+        assert!(!playable(bitboard::MAX_SIZE + 1, 5));
+        assert!(!playable(5, 6));
+        assert!(!playable(5, 0));
+    }
+
+    #[test]
+    fn only_the_next_move_of_the_side_to_move_is_valid() {
+        let board = tile_map("x../.o./...");
+        assert_eq!(validate_move(&board, 3, 2, Tile::X, 2, "c3"), Ok(Coordinates { x: 2, y: 2 }));
+        // Wrong ply
+        assert!(validate_move(&board, 3, 2, Tile::X, 3, "c3").is_err());
+        assert!(validate_move(&board, 3, 2, Tile::X, 1, "c3").is_err());
+        // Wrong side
+        assert!(validate_move(&board, 3, 2, Tile::O, 2, "c3").is_err());
+        // Occupied squares
+        assert!(validate_move(&board, 3, 2, Tile::X, 2, "a1").is_err());
+        assert!(validate_move(&board, 3, 2, Tile::X, 2, "b2").is_err());
+        // Off the board or not a move
+        for notation in ["d1", "a4", "a0", "zz", ""] {
+            assert!(validate_move(&board, 3, 2, Tile::X, 2, notation).is_err(), "{:?}", notation);
+        }
+    }
+
+    #[test]
+    fn no_move_is_valid_after_the_game_ended() {
+        let board = tile_map("xxx/oo./...");
+        assert!(validate_move(&board, 3, 5, Tile::O, 5, "c2").is_err());
+    }
+
+    #[test]
+    fn a_line_too_long_closes_the_connection() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let connection = Connection::new(listener.accept().unwrap().0).unwrap();
+        client.write_all(b"{\"type\":\"waiting\"}\n").unwrap();
+        client.write_all(&vec![b' '; MAX_LINE_LENGTH + 1]).unwrap();
+
+        let mut received = Vec::new();
+        for _ in 0..500 {
+            match connection.try_receive() {
+                Ok(Some(message)) => received.push(message),
+                Ok(None) => thread::sleep(Duration::from_millis(10)),
+                Err(_) => break,
+            }
+        }
+        assert_eq!(received, vec![Message::Waiting]);
+        assert!(connection.try_receive().is_err());
+    }
+}
@@ -0,0 +1,1022 @@
+// Games against another copy of the game over the local network: the LAN
+// screen of the menu hosts, joins or watches a game, and the session then
+// sends the local moves, checks the remote ones and carries resignations,
+// draw offers, rematches and chat. The host also lets spectators in and sends them every
+// move. Finding a game asks a game server for an opponent instead.
+use std::{env, io};
+use std::sync::{Mutex, mpsc::Receiver};
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, text::Text2dBounds, window::ReceivedCharacter};
+
+use crate::{GameState, GameMode, GameOutcome, MatrixSize, WinLength, Tile, TileMap, Board, MoveHistory, constants::{self, NORMAL_BUTTON, TEXT_COLOR}, despawn_screen, clock::Clocks,
+            game::{Ending, GameButtonColumn, PlayingItem, PlayingState}, menu::{get_menu_styles, MenuButtonAction, MenuState},
+            net::{self, Connection, Listener, Message, PROTOCOL_VERSION},
+            player::{Player, Players, MousePlayer, RemoteMoves, RemotePlayer}};
+
+pub struct OnlinePlugin;
+
+// Tag component used to tag entities added on the LAN menu screen
+#[derive(Component)]
+struct OnLanMenuScreen;
+
+#[derive(Component)]
+struct LobbyStatusText;
+
+#[derive(Component)]
+struct AddressText;
+
+#[derive(Component)]
+struct ChatText;
+
+// The Resign and draw buttons, which only stay while the game is played
+#[derive(Component)]
+struct PlayingButton;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum LanButtonAction {
+    Host,
+    Join,
+    Watch,
+    Seek,
+}
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum OnlineButtonAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+    Rematch,
+}
+
+// What this copy of the game does in the game being set up
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Host,
+    Guest,
+    Spectator,
+    // Asking a game server for an opponent
+    Seeker,
+}
+
+// A connection waiting for the first message of the other side
+struct Handshake {
+    connection: Connection,
+    role: Role,
+    // None while waiting for an opponent in the queue of a server
+    deadline: Option<Instant>,
+}
+
+impl Handshake {
+    fn new(connection: Connection, role: Role) -> Self {
+        Handshake { connection, role, deadline: Some(Instant::now() + constants::HANDSHAKE_TIMEOUT) }
+    }
+}
+
+// The game agreed on in the handshake
+struct Agreement {
+    // None when only watching
+    local_side: Option<Tile>,
+    size: u32,
+    win_length: u32,
+    // Moves already played, sent to spectators
+    moves: Vec<String>,
+}
+
+// Hosting or joining from the LAN screen
+struct Lobby {
+    // Address of the host to join, as typed
+    address: String,
+    status: String,
+    listener: Option<Listener>,
+    // Behind a lock so that the lobby can be a resource
+    connecting: Option<Mutex<Receiver<io::Result<Connection>>>>,
+    // What the connection being made is for
+    purpose: Role,
+    handshake: Option<Handshake>,
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Lobby {
+            address: constants::DEFAULT_JOIN_ADDRESS.to_string(),
+            status: constants::LAN_HELP_STRING.to_string(),
+            listener: None,
+            connecting: None,
+            purpose: Role::Guest,
+            handshake: None,
+        }
+    }
+}
+
+impl Lobby {
+    // Stop hosting or joining
+    fn close(&mut self) {
+        self.listener = None;
+        self.connecting = None;
+        self.handshake = None;
+    }
+
+    // The address to join, on the default port unless it names one
+    fn join_address(&self) -> String {
+        if self.address.contains(':') {
+            self.address.clone()
+        } else {
+            format!("{}:{}", self.address, constants::DEFAULT_PORT)
+        }
+    }
+}
+
+// A game over the network, kept across rematches until the players leave
+pub(crate) struct Session {
+    // To the other player, or to the host when watching
+    connection: Connection,
+    // Whether the other side can still be reached
+    connected: bool,
+    // None when watching
+    local_side: Option<Tile>,
+    pub size: u32,
+    pub win_length: u32,
+    // Checked moves of the other side, waiting to be played
+    moves: RemoteMoves,
+    // The host keeps listening for spectators
+    listener: Option<Listener>,
+    spectators: Vec<Connection>,
+    // Spectators that connected but have not said what they want yet
+    arriving: Vec<(Connection, Instant)>,
+    // While watching, the board after the moves received so far, and how the
+    // game ended if the host said so
+    watched: TileMap,
+    watched_moves: usize,
+    ended: Option<(Option<GameOutcome>, String)>,
+    // Moves of the current game already sent or skipped
+    synced: usize,
+    // The number of moves on the board when this side and the other offered a
+    // draw. An offer only stands while no move was played since.
+    draw: (Option<usize>, Option<usize>),
+    // Whether this side and the other asked for a rematch
+    rematch: (bool, bool),
+    chat: Vec<String>,
+    // Chat message being typed
+    draft: String,
+}
+
+impl Session {
+    fn new(connection: Connection, agreement: Agreement, listener: Option<Listener>) -> Result<Self, String> {
+        let mut session = Session {
+            connection,
+            connected: true,
+            local_side: agreement.local_side,
+            size: agreement.size,
+            win_length: agreement.win_length,
+            moves: RemoteMoves::default(),
+            listener,
+            spectators: Vec::new(),
+            arriving: Vec::new(),
+            watched: TileMap::empty(agreement.size),
+            watched_moves: 0,
+            ended: None,
+            synced: 0,
+            draw: (None, None),
+            rematch: (false, false),
+            chat: Vec::new(),
+            draft: String::new(),
+        };
+        if session.local_side.is_none() {
+            session.watch(agreement.size, agreement.win_length, &agreement.moves)?;
+        }
+        session.notice(format!("Connected to {}", session.connection.peer()));
+        session.notice_side();
+        Ok(session)
+    }
+
+    // The local player uses the mouse and the other plays the moves
+    // received. Spectators receive the moves of both sides.
+    pub fn players(&self) -> Players {
+        let remote = || -> Box<dyn Player> { Box::new(RemotePlayer::new(self.moves.clone())) };
+        match self.local_side {
+            Some(Tile::X) => Players { x: Box::new(MousePlayer), o: remote() },
+            Some(_) => Players { x: remote(), o: Box::new(MousePlayer) },
+            None => Players { x: remote(), o: remote() },
+        }
+    }
+
+    // Start watching a new game with `moves` already played
+    fn watch(&mut self, size: u32, win_length: u32, moves: &[String]) -> Result<(), String> {
+        if !net::playable(size, win_length) {
+            return Err(format!("the host chose a {}x{} board with {} in a row", size, size, win_length));
+        }
+        (self.size, self.win_length) = (size, win_length);
+        self.watched = TileMap::empty(size);
+        self.watched_moves = 0;
+        self.ended = None;
+        self.moves = RemoteMoves::default();
+        for (ply, notation) in moves.iter().enumerate() {
+            self.watch_move(ply, notation)?;
+        }
+        Ok(())
+    }
+
+    // Check a move of the watched game and queue it to be played
+    fn watch_move(&mut self, ply: usize, notation: &str) -> Result<(), String> {
+        let side = if ply.is_multiple_of(2) { Tile::X } else { Tile::O };
+        let coordinates = net::validate_move(&self.watched, self.win_length, self.watched_moves, side, ply, notation)?;
+        self.watched.0[coordinates.x as usize][coordinates.y as usize] = side;
+        self.watched_moves += 1;
+        self.moves.push(coordinates);
+        Ok(())
+    }
+
+    fn send(&mut self, message: &Message) {
+        if self.connected && self.connection.send(message).is_err() {
+            self.connected = false;
+            self.notice(constants::OPPONENT_LEFT_STRING.to_string());
+        }
+    }
+
+    // Send to every spectator, dropping those who left
+    fn broadcast(&mut self, message: &Message) {
+        self.spectators.retain(|spectator| spectator.send(message).is_ok());
+    }
+
+    // What a spectator is sent on joining and whenever a new game starts
+    fn watching(&self, moves: &[String]) -> Message {
+        Message::Watching { version: PROTOCOL_VERSION, size: self.size, win_length: self.win_length, moves: moves.to_vec() }
+    }
+
+    // A line of the chat that nobody wrote
+    fn notice(&mut self, text: String) {
+        self.chat.push(format!("* {}", text));
+    }
+
+    fn notice_side(&mut self) {
+        let text = match self.local_side {
+            Some(Tile::X) => "You play X",
+            Some(_) => "You play O",
+            None => "You are watching",
+        };
+        self.notice(text.to_string());
+    }
+
+    // Start the next game with the sides swapped
+    fn start_rematch(&mut self) {
+        self.local_side = self.local_side.map(|side| side.opponent());
+        self.moves = RemoteMoves::default();
+        self.synced = 0;
+        self.draw = (None, None);
+        self.rematch = (false, false);
+        self.notice_side();
+        let watching = self.watching(&[]);
+        self.broadcast(&watching);
+    }
+}
+
+impl Plugin for OnlinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Lobby>()
+            .add_system_set(SystemSet::on_enter(MenuState::Lan).with_system(lobby_setup))
+            .add_system_set(SystemSet::on_update(MenuState::Lan)
+                            .with_system(lobby_action)
+                            .with_system(address_input)
+                            .with_system(lobby_poll)
+                            .with_system(lobby_display))
+            .add_system_set(SystemSet::on_exit(MenuState::Lan)
+                            .with_system(lobby_close)
+                            .with_system(despawn_screen::<OnLanMenuScreen>))
+            .add_system_set(SystemSet::on_enter(GameState::Game).with_system(chat_setup))
+            .add_system_set(SystemSet::on_update(GameState::Game)
+                            .with_system(session_receive)
+                            .with_system(session_spectators)
+                            .with_system(session_send_moves)
+                            .with_system(chat_input)
+                            .with_system(chat_display)
+                            .with_system(online_action))
+            .add_system_set(SystemSet::on_enter(PlayingState::Playing).with_system(playing_buttons_setup))
+            .add_system_set(SystemSet::on_update(PlayingState::Playing).with_system(draw_buttons_display))
+            .add_system_set(SystemSet::on_exit(PlayingState::Playing).with_system(despawn_screen::<PlayingButton>))
+            .add_system_set(SystemSet::on_enter(PlayingState::GameOver)
+                            .with_system(rematch_button_setup)
+                            .with_system(ending_broadcast))
+            // Going back to the menu leaves the session
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(session_close));
+    }
+}
+
+fn lobby_setup(mut commands: Commands, asset_server: Res<AssetServer>, mut lobby: ResMut<Lobby>) {
+    let (font, button_style, button_text_style) = get_menu_styles(asset_server);
+    let status_text_style = TextStyle {
+        font,
+        font_size: constants::STATS_FONT_SIZE,
+        color: TEXT_COLOR,
+    };
+    lobby.status = constants::LAN_HELP_STRING.to_string();
+
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(OnLanMenuScreen)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(
+                constants::PLAY_LAN_STRING,
+                button_text_style.clone(),
+        ));
+        parent.spawn_bundle(TextBundle::from_section(lobby.status.clone(), status_text_style.clone()))
+            .insert(LobbyStatusText);
+        parent.spawn_bundle(TextBundle::from_section(String::new(), button_text_style.clone()))
+            .insert(AddressText);
+
+        // Playing on this network and through a server, then watching
+        let rows = [[(LanButtonAction::Host, constants::HOST_STRING), (LanButtonAction::Join, constants::JOIN_STRING)],
+                    [(LanButtonAction::Seek, constants::FIND_GAME_STRING), (LanButtonAction::Watch, constants::WATCH_STRING)]];
+        for row in rows {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    ..default()
+                },
+                color: Color::NONE.into(),
+                ..default()
+            })
+            .with_children(|parent| {
+                for (action, text) in row {
+                    parent.spawn_bundle(ButtonBundle {
+                        style: button_style.clone(),
+                        color: NORMAL_BUTTON.into(),
+                        ..default()
+                    })
+                    .insert(action)
+                    .with_children(|parent| {
+                        parent.spawn_bundle(TextBundle::from_section(text, button_text_style.clone()));
+                    });
+                }
+            });
+        }
+
+        parent.spawn_bundle(ButtonBundle {
+            style: button_style,
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(MenuButtonAction::BackToMainMenu)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(constants::BACK_STRING, button_text_style));
+        });
+    });
+}
+
+fn lobby_action(interaction_query: Query<(&Interaction, &LanButtonAction), Changed<Interaction>>,
+                mut lobby: ResMut<Lobby>) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        lobby.close();
+        match action {
+            LanButtonAction::Host => match Listener::bind(constants::DEFAULT_PORT) {
+                Ok(listener) => {
+                    let address = net::local_address().map_or("this machine".to_string(), |address| address.to_string());
+                    lobby.status = format!("Waiting for a player on {}:{}", address, constants::DEFAULT_PORT);
+                    lobby.listener = Some(listener);
+                }
+                Err(error) => lobby.status = format!("Could not host: {}", error),
+            },
+            LanButtonAction::Join | LanButtonAction::Watch | LanButtonAction::Seek => {
+                let address = lobby.join_address();
+                lobby.status = format!("Connecting to {}", address);
+                lobby.connecting = Some(Mutex::new(Connection::connect(&address)));
+                lobby.purpose = match action {
+                    LanButtonAction::Watch => Role::Spectator,
+                    LanButtonAction::Seek => Role::Seeker,
+                    _ => Role::Guest,
+                };
+            }
+        }
+    }
+}
+
+// Type the address to join
+fn address_input(mut characters: EventReader<ReceivedCharacter>,
+                 keys: Res<Input<KeyCode>>,
+                 mut lobby: ResMut<Lobby>) {
+    for event in characters.iter() {
+        let allowed = event.char.is_ascii_alphanumeric() || ".:-[]".contains(event.char);
+        if allowed && lobby.address.len() < constants::MAX_ADDRESS_LENGTH {
+            lobby.address.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        lobby.address.pop();
+    }
+}
+
+// Accept or make the connection, then agree on the game with the other side
+fn lobby_poll(mut commands: Commands,
+              mut lobby: ResMut<Lobby>,
+              size: Res<MatrixSize>,
+              win_length: Res<WinLength>,
+              mut game_mode: ResMut<GameMode>,
+              mut game_state: ResMut<State<GameState>>,
+              mut menu_state: ResMut<State<MenuState>>) {
+    let lobby = &mut *lobby;
+
+    // Only one player is let in at a time; others are turned away
+    if let Some(listener) = &lobby.listener {
+        match listener.accept() {
+            Ok(Some(connection)) if lobby.handshake.is_none() => {
+                lobby.handshake = Some(Handshake::new(connection, Role::Host));
+            }
+            Ok(_) => {}
+            Err(error) => {
+                lobby.status = format!("Could not host: {}", error);
+                lobby.listener = None;
+            }
+        }
+    }
+
+    let connected = lobby.connecting.as_ref().and_then(|connecting| connecting.lock().unwrap().try_recv().ok());
+    match connected {
+        Some(Ok(connection)) => {
+            lobby.connecting = None;
+            let greeting = match lobby.purpose {
+                Role::Spectator => Message::Watch { version: PROTOCOL_VERSION },
+                Role::Seeker => Message::Seek {
+                    version: PROTOCOL_VERSION,
+                    size: size.0,
+                    win_length: win_length.0.min(size.0),
+                    name: player_name(),
+                },
+                _ => Message::Hello { version: PROTOCOL_VERSION },
+            };
+            match connection.send(&greeting) {
+                Ok(()) => lobby.handshake = Some(Handshake::new(connection, lobby.purpose)),
+                Err(error) => lobby.status = format!("Could not join: {}", error),
+            }
+        }
+        Some(Err(error)) => {
+            lobby.connecting = None;
+            lobby.status = format!("Could not join: {}", error);
+        }
+        None => {}
+    }
+
+    let Some(handshake) = &mut lobby.handshake else { return };
+    let agreed = match handshake.connection.try_receive() {
+        Ok(Some(Message::Waiting)) if handshake.role == Role::Seeker => {
+            handshake.deadline = None;
+            lobby.status = format!("Waiting for an opponent on a {}x{} board with {} in a row",
+                                   size.0, size.0, win_length.0.min(size.0));
+            return;
+        }
+        Ok(Some(message)) => match handshake.role {
+            Role::Host => welcome(&handshake.connection, message, size.0, win_length.0),
+            Role::Guest | Role::Seeker => welcomed(message),
+            Role::Spectator => admitted(message),
+        },
+        Ok(None) if handshake.deadline.is_some_and(|deadline| Instant::now() > deadline) => Err("no answer came".to_string()),
+        Ok(None) => return,
+        Err(error) => Err(error.to_string()),
+    };
+    let handshake = lobby.handshake.take().unwrap();
+    let session = match agreed {
+        // The host keeps listening for spectators
+        Ok(agreement) => Session::new(handshake.connection, agreement, lobby.listener.take()),
+        Err(reason) => Err(reason),
+    };
+    match session {
+        Ok(session) => {
+            lobby.close();
+            commands.insert_resource(session);
+            // Only a game server puts network games on the clock
+            commands.remove_resource::<Clocks>();
+            *game_mode = GameMode::Network;
+            game_state.set(GameState::Game).unwrap();
+            menu_state.set(MenuState::Disabled).unwrap();
+        }
+        // The host keeps waiting for another player
+        Err(reason) if handshake.role == Role::Host => {
+            lobby.status = format!("A player could not join: {}. Still waiting on port {}", reason, constants::DEFAULT_PORT);
+        }
+        Err(reason) => lobby.status = format!("Could not join: {}", reason),
+    }
+}
+
+// Answer the greeting of a joining player with the game settings. The host
+// plays X.
+fn welcome(connection: &Connection, message: Message, size: u32, win_length: u32) -> Result<Agreement, String> {
+    match message {
+        Message::Hello { version } if version == PROTOCOL_VERSION => {
+            let win_length = win_length.min(size);
+            connection.send(&Message::Welcome { version, size, win_length, side: Tile::O })
+                .map_err(|error| error.to_string())?;
+            Ok(Agreement { local_side: Some(Tile::X), size, win_length, moves: Vec::new() })
+        }
+        Message::Hello { version } => {
+            let _ = connection.send(&Message::Reject { reason: format!("the host speaks version {}", PROTOCOL_VERSION) });
+            Err(format!("it speaks version {}", version))
+        }
+        Message::Watch { .. } => {
+            let _ = connection.send(&Message::Reject { reason: "no game is being played yet".to_string() });
+            Err("a spectator came before a player".to_string())
+        }
+        _ => Err("it did not say hello".to_string()),
+    }
+}
+
+// Take the settings sent by the host, if they can be played
+fn welcomed(message: Message) -> Result<Agreement, String> {
+    match message {
+        Message::Welcome { version, .. } if version != PROTOCOL_VERSION => {
+            Err(format!("the host speaks version {}", version))
+        }
+        Message::Welcome { size, win_length, side, .. } => {
+            if !net::playable(size, win_length) {
+                return Err(format!("the host chose a {}x{} board with {} in a row", size, size, win_length));
+            }
+            if side == Tile::Empty {
+                return Err("the host gave no side".to_string());
+            }
+            Ok(Agreement { local_side: Some(side), size, win_length, moves: Vec::new() })
+        }
+        Message::Reject { reason } => Err(format!("the host refused: {}", reason)),
+        _ => Err("the host did not welcome us".to_string()),
+    }
+}
+
+// Take the game sent to a spectator; its moves are checked by the session
+fn admitted(message: Message) -> Result<Agreement, String> {
+    match message {
+        Message::Watching { version, .. } if version != PROTOCOL_VERSION => {
+            Err(format!("the host speaks version {}", version))
+        }
+        Message::Watching { size, win_length, moves, .. } => Ok(Agreement { local_side: None, size, win_length, moves }),
+        Message::Reject { reason } => Err(format!("the host refused: {}", reason)),
+        _ => Err("the host did not let us watch".to_string()),
+    }
+}
+
+fn lobby_display(lobby: Res<Lobby>,
+                 mut status_query: Query<&mut Text, (With<LobbyStatusText>, Without<AddressText>)>,
+                 mut address_query: Query<&mut Text, With<AddressText>>) {
+    if !lobby.is_changed() {
+        return;
+    }
+    for mut text in status_query.iter_mut() {
+        text.sections[0].value = lobby.status.clone();
+    }
+    for mut text in address_query.iter_mut() {
+        text.sections[0].value = format!("{}: {}_", constants::ADDRESS_STRING, lobby.address);
+    }
+}
+
+fn lobby_close(mut lobby: ResMut<Lobby>) {
+    lobby.close();
+}
+
+fn session_close(mut commands: Commands, session: Option<ResMut<Session>>) {
+    if let Some(mut session) = session {
+        session.send(&Message::Bye);
+        session.broadcast(&Message::Bye);
+        commands.remove_resource::<Session>();
+        commands.remove_resource::<Clocks>();
+    }
+}
+
+// Act on the messages of the other side
+fn session_receive(mut commands: Commands,
+                   session: Option<ResMut<Session>>,
+                   board: Res<Board>,
+                   moves: Res<MoveHistory>,
+                   mut ending: ResMut<Ending>,
+                   mut playing_states: ResMut<State<PlayingState>>,
+                   mut game_state: ResMut<State<GameState>>) {
+    let Some(mut session) = session else { return };
+    while session.connected {
+        let message = match session.connection.try_receive() {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(_) => Message::Bye,
+        };
+        let Some(local_side) = session.local_side else {
+            watch_message(&mut session, message, &mut ending, &mut playing_states, &mut game_state);
+            continue;
+        };
+        match message {
+            // Moves crossing the end of the game, e.g. a resignation, are dropped
+            Message::Move { .. } if *playing_states.current() != PlayingState::Playing => {}
+            Message::Move { ply, notation } => {
+                match net::validate_move(&board.tile_map, board.win_length, moves.0.len(), local_side.opponent(), ply, &notation) {
+                    Ok(coordinates) => session.moves.push(coordinates),
+                    Err(error) => {
+                        session.send(&Message::Bye);
+                        session.connected = false;
+                        session.notice(format!("{}: {}", constants::WRONG_MOVE_STRING, error));
+                        ending.decide(&mut playing_states, None, constants::WRONG_MOVE_STRING.to_string());
+                    }
+                }
+            }
+            Message::Resign => {
+                let outcome = GameOutcome::win_for(local_side);
+                ending.decide(&mut playing_states, Some(outcome), constants::OPPONENT_RESIGNED_STRING.to_string());
+            }
+            Message::OfferDraw { ply } if *playing_states.current() == PlayingState::Playing => {
+                session.draw.1 = Some(ply);
+                session.notice(format!("{} offers a draw", constants::OPPONENT_STRING));
+            }
+            // The acceptance of an offer that still stands ends the game on
+            // both sides, the other one once told so
+            Message::AcceptDraw { ply }
+                if session.draw.0 == Some(ply) && ply == moves.0.len()
+                    && *playing_states.current() == PlayingState::Playing => {
+                let reason = constants::DRAW_AGREED_STRING.to_string();
+                session.send(&Message::Ended { outcome: Some(GameOutcome::Draw), reason: reason.clone() });
+                ending.decide(&mut playing_states, Some(GameOutcome::Draw), reason);
+            }
+            Message::DeclineDraw { ply } if session.draw.0 == Some(ply) => {
+                session.draw.0 = None;
+                session.notice(format!("{} declined the draw", constants::OPPONENT_STRING));
+            }
+            // Offers crossing the end of the game, or answers to an offer that
+            // no longer stands
+            Message::OfferDraw { .. } | Message::AcceptDraw { .. } | Message::DeclineDraw { .. } => {}
+            Message::Rematch => {
+                session.rematch.1 = true;
+                session.notice(format!("{} asks for a rematch", constants::OPPONENT_STRING));
+            }
+            Message::Chat { text } => {
+                let text: String = text.chars().filter(|c| !c.is_control()).take(constants::CHAT_MAX_LENGTH).collect();
+                session.chat.push(format!("{}: {}", constants::OPPONENT_STRING, text));
+            }
+            Message::Bye => {
+                session.connected = false;
+                session.notice(constants::OPPONENT_LEFT_STRING.to_string());
+                ending.decide(&mut playing_states, None, constants::OPPONENT_LEFT_STRING.to_string());
+            }
+            // Sent by a server, or by the other side once it took up the draw
+            // this side accepted
+            Message::Ended { outcome, reason } => ending.decide(&mut playing_states, outcome, reason),
+            // The server runs the clocks, sending them after every move
+            Message::Clock { x, o } => {
+                let remaining = [Duration::from_millis(x), Duration::from_millis(o)];
+                commands.insert_resource(Clocks::new(remaining, Duration::ZERO, false, moves.0.len()));
+            }
+            // Only part of the handshake, or only sent to spectators
+            Message::Hello { .. } | Message::Welcome { .. } | Message::Reject { .. } | Message::Watch { .. }
+                | Message::Watching { .. } | Message::Seek { .. } | Message::Waiting => {}
+        }
+    }
+
+    // Both sides asked for a rematch
+    if session.rematch == (true, true) && *playing_states.current() == PlayingState::GameOver {
+        session.start_rematch();
+        let _ = game_state.restart();
+    }
+
+    // A watched game ends once the moves before its end have been played
+    if session.ended.is_some() && session.moves.is_empty() && *playing_states.current() == PlayingState::Playing {
+        let (outcome, reason) = session.ended.take().unwrap();
+        ending.decide(&mut playing_states, outcome, reason);
+    }
+}
+
+// Act on a message of the host while watching
+fn watch_message(session: &mut Session,
+                 message: Message,
+                 ending: &mut Ending,
+                 playing_states: &mut State<PlayingState>,
+                 game_state: &mut State<GameState>) {
+    match message {
+        Message::Move { ply, notation } => {
+            if let Err(error) = session.watch_move(ply, &notation) {
+                session.send(&Message::Bye);
+                session.connected = false;
+                session.notice(format!("{}: {}", constants::WRONG_MOVE_STRING, error));
+                ending.decide(playing_states, None, constants::WRONG_MOVE_STRING.to_string());
+            }
+        }
+        Message::Watching { size, win_length, moves, .. } => {
+            match session.watch(size, win_length, &moves) {
+                Ok(()) => {
+                    session.notice(constants::NEW_GAME_STRING.to_string());
+                    let _ = game_state.restart();
+                }
+                Err(error) => {
+                    session.send(&Message::Bye);
+                    session.connected = false;
+                    session.notice(error);
+                    ending.decide(playing_states, None, constants::WRONG_MOVE_STRING.to_string());
+                }
+            }
+        }
+        Message::Ended { outcome, reason } => session.ended = Some((outcome, reason)),
+        Message::Bye => {
+            session.connected = false;
+            session.notice(constants::HOST_LEFT_STRING.to_string());
+            ending.decide(playing_states, None, constants::HOST_LEFT_STRING.to_string());
+        }
+        _ => {}
+    }
+}
+
+// Let spectators in while hosting, and notice those who leave
+fn session_spectators(session: Option<ResMut<Session>>, moves: Res<MoveHistory>, ending: Res<Ending>) {
+    let Some(mut session) = session else { return };
+    let Some(listener) = &session.listener else { return };
+    if let Ok(Some(connection)) = listener.accept() {
+        session.arriving.push((connection, Instant::now()));
+    }
+
+    // Spectators are sent the moves passed on to the other player so far;
+    // later ones follow as they are played
+    let mut index = 0;
+    while index < session.arriving.len() {
+        let (connection, arrived) = &session.arriving[index];
+        let admitted = match connection.try_receive() {
+            Ok(Some(Message::Watch { version })) if version == PROTOCOL_VERSION => true,
+            Ok(Some(Message::Watch { .. })) => {
+                let _ = connection.send(&Message::Reject { reason: format!("the host speaks version {}", PROTOCOL_VERSION) });
+                false
+            }
+            Ok(Some(Message::Hello { .. })) => {
+                let _ = connection.send(&Message::Reject { reason: "a game is already being played".to_string() });
+                false
+            }
+            Ok(None) if arrived.elapsed() <= constants::HANDSHAKE_TIMEOUT => {
+                index += 1;
+                continue;
+            }
+            _ => false,
+        };
+        let (connection, _) = session.arriving.remove(index);
+        if admitted {
+            let played: Vec<String> = moves.0[..session.synced.min(moves.0.len())].iter().map(|coordinates| coordinates.notation()).collect();
+            let mut sent = connection.send(&session.watching(&played));
+            if let Some(decision) = &ending.0 {
+                sent = sent.and(connection.send(&Message::Ended { outcome: decision.outcome, reason: decision.reason.clone() }));
+            }
+            if sent.is_ok() {
+                session.spectators.push(connection);
+            }
+        }
+    }
+
+    // Spectators only ever say goodbye
+    let staying: Vec<bool> = session.spectators.iter().map(|spectator| matches!(spectator.try_receive(), Ok(None))).collect();
+    if staying.contains(&false) {
+        let mut staying = staying.into_iter();
+        session.spectators.retain(|_| staying.next().unwrap());
+    }
+}
+
+// Tell spectators how a game ended when the board did not decide it
+fn ending_broadcast(session: Option<ResMut<Session>>, ending: Res<Ending>) {
+    let (Some(mut session), Some(decision)) = (session, &ending.0) else { return };
+    session.broadcast(&Message::Ended { outcome: decision.outcome, reason: decision.reason.clone() });
+}
+
+// Send the moves of the local player as they are played, and every move to
+// spectators
+fn session_send_moves(session: Option<ResMut<Session>>, moves: Res<MoveHistory>) {
+    let Some(mut session) = session else { return };
+    while session.synced < moves.0.len() {
+        let ply = session.synced;
+        let side = if ply.is_multiple_of(2) { Tile::X } else { Tile::O };
+        let message = Message::Move { ply, notation: moves.0[ply].notation() };
+        if session.local_side == Some(side) {
+            session.send(&message);
+        }
+        session.broadcast(&message);
+        session.synced += 1;
+    }
+}
+
+// Type chat messages, sent with Enter
+fn chat_input(session: Option<ResMut<Session>>,
+              mut characters: EventReader<ReceivedCharacter>,
+              keys: Res<Input<KeyCode>>) {
+    let Some(mut session) = session else { return };
+    // Spectators only read
+    if session.local_side.is_none() {
+        return;
+    }
+    for event in characters.iter() {
+        if !event.char.is_control() && session.draft.chars().count() < constants::CHAT_MAX_LENGTH {
+            session.draft.push(event.char);
+        }
+    }
+    if keys.just_pressed(KeyCode::Back) {
+        session.draft.pop();
+    }
+    if keys.just_pressed(KeyCode::Return) && !session.draft.trim().is_empty() {
+        let text = std::mem::take(&mut session.draft);
+        session.send(&Message::Chat { text: text.clone() });
+        session.chat.push(format!("{}: {}", constants::YOU_STRING, text));
+    }
+}
+
+// The chat sits at the bottom left of the board, beyond the evaluation bar
+fn chat_setup(mut commands: Commands, session: Option<Res<Session>>, asset_server: Res<AssetServer>) {
+    if session.is_none() {
+        return;
+    }
+    let (font, _, _) = get_menu_styles(asset_server);
+    let x = -constants::LENGTH / 2.0 - 2.0 * constants::EVAL_BAR_MARGIN - constants::EVAL_BAR_WIDTH - constants::CHAT_WIDTH;
+    commands.spawn_bundle(Text2dBundle {
+        text: Text::from_section(String::new(), TextStyle {
+            font,
+            font_size: constants::STATS_FONT_SIZE,
+            color: TEXT_COLOR,
+        })
+        .with_alignment(TextAlignment::BOTTOM_LEFT),
+        text_2d_bounds: Text2dBounds {
+            size: Vec2::new(constants::CHAT_WIDTH, constants::LENGTH / 2.0),
+        },
+        transform: Transform::from_xyz(x, -constants::LENGTH / 2.0, 1.0),
+        ..default()
+    })
+    .insert(Name::new("ChatText"))
+    .insert(ChatText)
+    .insert(PlayingItem);
+}
+
+// Show the last lines of the chat and the message being typed
+fn chat_display(session: Option<Res<Session>>,
+                mut text_query: Query<&mut Text, With<ChatText>>) {
+    let Some(session) = session else { return };
+    if !session.is_changed() {
+        return;
+    }
+    let start = session.chat.len().saturating_sub(constants::CHAT_LINES);
+    let mut lines = Vec::new();
+    if session.listener.is_some() {
+        lines.push(format!("{}: {}", constants::SPECTATORS_STRING, session.spectators.len()));
+    }
+    lines.extend_from_slice(&session.chat[start..]);
+    if session.local_side.is_some() {
+        lines.push(format!("> {}_", session.draft));
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = lines.join("\n");
+    }
+}
+
+// The name given to game servers, taken from the account running the game
+fn player_name() -> String {
+    env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_else(|_| constants::DEFAULT_PLAYER_NAME.to_string())
+}
+
+fn spawn_online_button(commands: &mut Commands, asset_server: Res<AssetServer>, column: Entity,
+                       action: OnlineButtonAction, label: &str) -> Entity {
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+    let mut button = None;
+    commands.entity(column).with_children(|parent| {
+        button = Some(parent.spawn_bundle(ButtonBundle {
+            style: button_style,
+            color: NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(action)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(label, button_text_style));
+        })
+        .id());
+    });
+    button.unwrap()
+}
+
+// The buttons answering a draw offer are only shown while one stands
+fn playing_buttons_setup(mut commands: Commands,
+                         session: Option<Res<Session>>,
+                         asset_server: Res<AssetServer>,
+                         column_query: Query<Entity, With<GameButtonColumn>>) {
+    // Spectators can only watch
+    if session.is_none_or(|session| session.local_side.is_none()) {
+        return;
+    }
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+    for column in column_query.iter() {
+        commands.entity(column).with_children(|parent| {
+            for (action, label, shown) in [
+                (OnlineButtonAction::Resign, constants::RESIGN_STRING, true),
+                (OnlineButtonAction::OfferDraw, constants::OFFER_DRAW_STRING, true),
+                (OnlineButtonAction::AcceptDraw, constants::ACCEPT_DRAW_STRING, false),
+                (OnlineButtonAction::DeclineDraw, constants::DECLINE_DRAW_STRING, false),
+            ] {
+                parent.spawn_bundle(ButtonBundle {
+                    style: Style {
+                        display: if shown { Display::Flex } else { Display::None },
+                        ..button_style.clone()
+                    },
+                    color: NORMAL_BUTTON.into(),
+                    ..default()
+                })
+                .insert(action)
+                .insert(PlayingButton)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(label, button_text_style.clone()));
+                });
+            }
+        });
+    }
+}
+
+// Show the answers to a draw offered by the other side instead of the other
+// buttons, and whether the draw offered by this side stands
+fn draw_buttons_display(session: Option<Res<Session>>,
+                        moves: Res<MoveHistory>,
+                        mut button_query: Query<(&OnlineButtonAction, &mut Style, &Children), With<PlayingButton>>,
+                        mut text_query: Query<&mut Text>) {
+    let Some(session) = session else { return };
+    if !session.is_changed() && !moves.is_changed() {
+        return;
+    }
+    let standing = |offer: Option<usize>| offer == Some(moves.0.len());
+    let (offered, answering) = (standing(session.draw.0), standing(session.draw.1));
+    for (action, mut style, children) in button_query.iter_mut() {
+        let shown = match action {
+            OnlineButtonAction::AcceptDraw | OnlineButtonAction::DeclineDraw => answering,
+            _ => !answering,
+        };
+        style.display = if shown { Display::Flex } else { Display::None };
+        if *action != OnlineButtonAction::OfferDraw {
+            continue;
+        }
+        let label = if offered { constants::DRAW_OFFERED_STRING } else { constants::OFFER_DRAW_STRING };
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                text.sections[0].value = label.to_string();
+            }
+        }
+    }
+}
+
+fn rematch_button_setup(mut commands: Commands,
+                        session: Option<Res<Session>>,
+                        asset_server: Res<AssetServer>,
+                        column_query: Query<Entity, With<GameButtonColumn>>) {
+    // Spectators can only watch
+    if session.is_none_or(|session| session.local_side.is_none()) {
+        return;
+    }
+    if let Some(column) = column_query.iter().next() {
+        spawn_online_button(&mut commands, asset_server, column, OnlineButtonAction::Rematch, constants::REMATCH_STRING);
+    }
+}
+
+fn online_action(interaction_query: Query<(&Interaction, &OnlineButtonAction), Changed<Interaction>>,
+                 session: Option<ResMut<Session>>,
+                 moves: Res<MoveHistory>,
+                 mut ending: ResMut<Ending>,
+                 mut playing_states: ResMut<State<PlayingState>>) {
+    let Some(mut session) = session else { return };
+    let Some(local_side) = session.local_side else { return };
+    // Draw messages are about the position on the board now
+    let ply = moves.0.len();
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match action {
+            OnlineButtonAction::Resign => {
+                session.send(&Message::Resign);
+                let outcome = GameOutcome::win_for(local_side.opponent());
+                ending.decide(&mut playing_states, Some(outcome), constants::YOU_RESIGNED_STRING.to_string());
+            }
+            OnlineButtonAction::OfferDraw if session.draw.0 != Some(ply) => {
+                session.send(&Message::OfferDraw { ply });
+                session.draw.0 = Some(ply);
+                session.notice(format!("{} offered a draw", constants::YOU_STRING));
+            }
+            // The game ends once whoever decides it, the other side or the
+            // server, sends `Ended`, unless a move crossed the acceptance
+            OnlineButtonAction::AcceptDraw if session.draw.1 == Some(ply) => {
+                session.send(&Message::AcceptDraw { ply });
+                session.draw.1 = None;
+                session.notice(format!("{} accepted the draw", constants::YOU_STRING));
+            }
+            OnlineButtonAction::DeclineDraw if session.draw.1 == Some(ply) => {
+                session.send(&Message::DeclineDraw { ply });
+                session.draw.1 = None;
+                session.notice(format!("{} declined the draw", constants::YOU_STRING));
+            }
+            OnlineButtonAction::OfferDraw | OnlineButtonAction::AcceptDraw | OnlineButtonAction::DeclineDraw => {}
+            OnlineButtonAction::Rematch if !session.connected => {
+                session.notice(constants::OPPONENT_LEFT_STRING.to_string());
+            }
+            OnlineButtonAction::Rematch if !session.rematch.0 => {
+                session.send(&Message::Rematch);
+                session.rematch.0 = true;
+                session.notice(format!("{} asked for a rematch", constants::YOU_STRING));
+            }
+            OnlineButtonAction::Rematch => {}
+        }
+    }
+}
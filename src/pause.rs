@@ -0,0 +1,212 @@
+// The pause menu, opened with Escape during a game. While it or a menu opened
+// from it is shown the clocks stand still, the AI does not search and the
+// board takes no moves. Network games are not paused, since the other side
+// plays on: the menu only keeps the board from taking this side's clicks.
+use bevy::prelude::*;
+
+use crate::{constants::{self, NORMAL_BUTTON, TEXT_COLOR}, GameState, GameMode, Board, Coordinates, MoveHistory, TimeControl, despawn_screen,
+            clock::Clocks, history::{self, SavedGame}, game::PlayingState, menu::{get_menu_styles, MenuButtonAction, MenuState}};
+
+pub struct PausePlugin;
+
+// Tag component used to tag entities added on the pause menu
+#[derive(Component)]
+struct OnPauseMenuScreen;
+
+// Tag component used to tag entities added on the quit confirmation
+#[derive(Component)]
+struct OnConfirmQuitScreen;
+
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum PauseButtonAction {
+    Resume,
+    Restart,
+    Save,
+    Quit,
+    ConfirmQuit,
+    CancelQuit,
+}
+
+#[derive(Component)]
+struct SaveButtonText;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::Game).with_system(pause_key))
+            .add_system_set(SystemSet::on_enter(MenuState::Paused).with_system(pause_menu_setup))
+            .add_system_set(SystemSet::on_update(MenuState::Paused)
+                            .with_system(pause_action)
+                            .with_system(save_action))
+            .add_system_set(SystemSet::on_exit(MenuState::Paused)
+                            .with_system(despawn_screen::<OnPauseMenuScreen>))
+            .add_system_set(SystemSet::on_enter(MenuState::ConfirmQuit).with_system(confirm_quit_setup))
+            .add_system_set(SystemSet::on_update(MenuState::ConfirmQuit).with_system(pause_action))
+            .add_system_set(SystemSet::on_exit(MenuState::ConfirmQuit)
+                            .with_system(despawn_screen::<OnConfirmQuitScreen>));
+    }
+}
+
+// Escape pauses the game being played and resumes it from the pause menu.
+// From the menus opened over the pause menu it goes back to the pause menu.
+fn pause_key(keys: Res<Input<KeyCode>>,
+             playing_states: Res<State<PlayingState>>,
+             mut menu_state: ResMut<State<MenuState>>) {
+    if !keys.just_pressed(constants::PAUSE_KEY) {
+        return;
+    }
+    let next = match menu_state.current() {
+        MenuState::Disabled if *playing_states.current() == PlayingState::Playing => MenuState::Paused,
+        MenuState::Disabled => return,
+        MenuState::Paused => MenuState::Disabled,
+        _ => MenuState::Paused,
+    };
+    let _ = menu_state.set(next);
+}
+
+fn spawn_pause_button(parent: &mut ChildBuilder, button_style: &Style, button_text_style: &TextStyle,
+                      action: impl Component, label: &str) {
+    parent.spawn_bundle(ButtonBundle {
+        style: button_style.clone(),
+        color: NORMAL_BUTTON.into(),
+        ..default()
+    })
+    .insert(action)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(label, button_text_style.clone()));
+    });
+}
+
+// Games over the network can neither be restarted nor saved on one side
+fn pause_menu_setup(mut commands: Commands, asset_server: Res<AssetServer>, mode: Res<GameMode>) {
+    let (font, button_style, button_text_style) = get_menu_styles(asset_server);
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(Name::new("PauseMenu"))
+    .insert(OnPauseMenuScreen)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(constants::PAUSED_STRING, TextStyle {
+            font,
+            font_size: constants::HUD_FONT_SIZE,
+            color: TEXT_COLOR,
+        }));
+        spawn_pause_button(parent, &button_style, &button_text_style, PauseButtonAction::Resume, constants::RESUME_STRING);
+        if *mode != GameMode::Network {
+            spawn_pause_button(parent, &button_style, &button_text_style, PauseButtonAction::Restart, constants::RESTART_STRING);
+            parent.spawn_bundle(ButtonBundle {
+                style: button_style.clone(),
+                color: NORMAL_BUTTON.into(),
+                ..default()
+            })
+            .insert(PauseButtonAction::Save)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle::from_section(constants::SAVE_STRING, button_text_style.clone()))
+                    .insert(SaveButtonText);
+            });
+        }
+        spawn_pause_button(parent, &button_style, &button_text_style, MenuButtonAction::Settings, constants::SETTINGS_STRING);
+        spawn_pause_button(parent, &button_style, &button_text_style, PauseButtonAction::Quit, constants::QUIT_TO_MENU_STRING);
+    });
+}
+
+fn confirm_quit_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let (font, button_style, button_text_style) = get_menu_styles(asset_server);
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            margin: UiRect::all(Val::Auto),
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(Name::new("ConfirmQuit"))
+    .insert(OnConfirmQuitScreen)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(constants::CONFIRM_QUIT_STRING, TextStyle {
+            font,
+            font_size: constants::HUD_FONT_SIZE,
+            color: TEXT_COLOR,
+        }));
+        parent.spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Row,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            spawn_pause_button(parent, &button_style, &button_text_style, PauseButtonAction::ConfirmQuit, constants::YES_STRING);
+            spawn_pause_button(parent, &button_style, &button_text_style, PauseButtonAction::CancelQuit, constants::NO_STRING);
+        });
+    });
+}
+
+fn pause_action(interaction_query: Query<(&Interaction, &PauseButtonAction), Changed<Interaction>>,
+                mut menu_state: ResMut<State<MenuState>>,
+                mut game_state: ResMut<State<GameState>>,
+                mut playing_states: ResMut<State<PlayingState>>) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        match action {
+            PauseButtonAction::Resume => menu_state.set(MenuState::Disabled).unwrap(),
+            // The game is set up again with the settings as they are now
+            PauseButtonAction::Restart => {
+                menu_state.set(MenuState::Disabled).unwrap();
+                let _ = game_state.restart();
+            }
+            PauseButtonAction::Quit => menu_state.set(MenuState::ConfirmQuit).unwrap(),
+            PauseButtonAction::ConfirmQuit => {
+                game_state.set(GameState::Menu).unwrap();
+                playing_states.set(PlayingState::Init).unwrap();
+            }
+            PauseButtonAction::CancelQuit => menu_state.set(MenuState::Paused).unwrap(),
+            PauseButtonAction::Save => {}
+        }
+    }
+}
+
+// Keep the game being played, replacing the one saved before, so that it can
+// be continued from the main menu
+fn save_action(interaction_query: Query<(&Interaction, &PauseButtonAction), Changed<Interaction>>,
+               board: Res<Board>,
+               moves: Res<MoveHistory>,
+               mode: Res<GameMode>,
+               time_control: Res<TimeControl>,
+               clocks: Option<Res<Clocks>>,
+               mut text_query: Query<&mut Text, With<SaveButtonText>>) {
+    let clicked = interaction_query.iter().any(|(interaction, action)| {
+        *interaction == Interaction::Clicked && *action == PauseButtonAction::Save
+    });
+    if !clicked {
+        return;
+    }
+    let game = SavedGame {
+        date: history::current_date(),
+        mode: *mode,
+        board_size: board.tile_map.0.len() as u32,
+        win_length: board.win_length,
+        time_control: clocks.as_ref().and(time_control.0),
+        clock: clocks.map(|clocks| clocks.remaining.map(|time| time.as_millis() as u64)),
+        moves: moves.0.iter().map(Coordinates::notation).collect(),
+    };
+    match history::save_game(&game) {
+        Ok(()) => {
+            for mut text in text_query.iter_mut() {
+                text.sections[0].value = constants::SAVED_STRING.to_string();
+            }
+        }
+        Err(error) => error!("Could not save the game: {}", error),
+    }
+}
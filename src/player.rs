@@ -0,0 +1,364 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, tasks::{AsyncComputeTaskPool, Task}};
+use futures_lite::future;
+
+use crate::{constants, AiEngine, Coordinates, Tile, TileMap, ai::{SearchProgress, SearchResult}, bitboard::Position, clock, mcts,
+            engine::{EngineConfig, EngineMove}, game::EngineReport, protocol::{Command, EngineProcess, Limit, Reply}};
+
+// What a player gets to see when it is asked for a move
+pub struct Turn<'a> {
+    pub tile_map: &'a TileMap,
+    pub side: Tile,
+    pub win_length: u32,
+    // Tile clicked with the mouse during this frame
+    pub clicked: Option<Coordinates>,
+    pub keys: &'a Input<KeyCode>,
+    // Searches report their progress here so that it can be shown
+    pub report: &'a mut EngineReport,
+    // Time left to the side to move and its increment, when playing on the
+    // clock
+    pub clock: Option<(Duration, Duration)>,
+}
+
+// Anything that can play one side of a game
+pub trait Player: Send + Sync {
+    // Called every frame of the player's turn until it returns a move
+    fn poll_move(&mut self, turn: &mut Turn) -> Option<Coordinates>;
+
+    // Called when the game stops while the player is still thinking
+    fn cancel(&mut self) {}
+
+    // Whether the player shows its own search in the engine report
+    fn reports_search(&self) -> bool {
+        false
+    }
+
+    // Tile to highlight while waiting for the player
+    fn cursor(&self) -> Option<Coordinates> {
+        None
+    }
+
+    // Whether a person in front of this screen plays the side, and so resigns
+    // and answers draw offers with the buttons
+    fn is_local_human(&self) -> bool {
+        false
+    }
+}
+
+// The players of the current game
+pub struct Players {
+    pub x: Box<dyn Player>,
+    pub o: Box<dyn Player>,
+}
+
+impl Players {
+    pub fn get(&self, side: Tile) -> &dyn Player {
+        if side == Tile::O { self.o.as_ref() } else { self.x.as_ref() }
+    }
+
+    pub fn get_mut(&mut self, side: Tile) -> &mut dyn Player {
+        if side == Tile::O { self.o.as_mut() } else { self.x.as_mut() }
+    }
+}
+
+// Plays the tile clicked with the mouse
+pub struct MousePlayer;
+
+impl Player for MousePlayer {
+    fn poll_move(&mut self, turn: &mut Turn) -> Option<Coordinates> {
+        turn.clicked
+    }
+
+    fn is_local_human(&self) -> bool {
+        true
+    }
+}
+
+// Moves a cursor with the arrow keys and plays it with Enter or Space
+#[derive(Default)]
+pub struct KeyboardPlayer {
+    cursor: Option<Coordinates>,
+}
+
+impl Player for KeyboardPlayer {
+    fn poll_move(&mut self, turn: &mut Turn) -> Option<Coordinates> {
+        let size = turn.tile_map.0.len() as i32;
+        // Start in the centre of the board
+        let cursor = self.cursor.get_or_insert(Coordinates { x: (size / 2) as u16, y: (size / 2) as u16 });
+
+        // Row 0 is at the top of the screen
+        for (key, dx, dy) in [
+            (KeyCode::Up, -1, 0),
+            (KeyCode::Down, 1, 0),
+            (KeyCode::Left, 0, -1),
+            (KeyCode::Right, 0, 1),
+        ] {
+            if turn.keys.just_pressed(key) {
+                cursor.x = (cursor.x as i32 + dx).clamp(0, size - 1) as u16;
+                cursor.y = (cursor.y as i32 + dy).clamp(0, size - 1) as u16;
+            }
+        }
+
+        if turn.keys.any_just_pressed([KeyCode::Return, KeyCode::Space]) {
+            return Some(*cursor);
+        }
+        None
+    }
+
+    fn cursor(&self) -> Option<Coordinates> {
+        self.cursor
+    }
+
+    fn is_local_human(&self) -> bool {
+        true
+    }
+}
+
+// Searches its move in the background and plays it once found
+pub struct EnginePlayer {
+    config: EngineConfig,
+    task: Option<Task<EngineMove>>,
+    // When the running search is stopped, if playing on the clock
+    deadline: Option<Instant>,
+}
+
+impl EnginePlayer {
+    pub fn new(config: EngineConfig) -> Self {
+        EnginePlayer { config, task: None, deadline: None }
+    }
+}
+
+impl Player for EnginePlayer {
+    fn poll_move(&mut self, turn: &mut Turn) -> Option<Coordinates> {
+        let task = match self.task.as_mut() {
+            Some(task) => task,
+            None => {
+                let progress = turn.report.restart();
+                let (mut config, tile_map) = (self.config, turn.tile_map.clone());
+                let (side, win_length) = (turn.side, turn.win_length);
+                // On the clock the search stops once it used its share of the
+                // time left, keeping the deepest line it finished
+                self.deadline = turn.clock.map(|(remaining, increment)| {
+                    let budget = clock::move_budget(remaining, increment);
+                    if config.engine == AiEngine::Mcts {
+                        config.budget = mcts::Budget::Time(budget);
+                    }
+                    Instant::now() + budget
+                });
+                self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+                    config.search(&tile_map, side, win_length, &fastrand::Rng::new(), &progress)
+                }));
+                return None;
+            }
+        };
+        if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            turn.report.progress.stop();
+            self.deadline = None;
+        }
+        let EngineMove { played, search } = future::block_on(future::poll_once(task))?;
+        self.task = None;
+        self.deadline = None;
+        if let Some(coordinates) = played {
+            let stats = turn.report.progress.stats();
+            info!("{:?} plays {}: score {}, depth {}, {} nodes in {:.1?} ({:.0} nodes/s), {} cutoffs",
+                  turn.side, coordinates.notation(), search.score, search.depth,
+                  stats.nodes, stats.elapsed, stats.nodes_per_second(), stats.cutoffs);
+        }
+        played
+    }
+
+    fn reports_search(&self) -> bool {
+        true
+    }
+
+    fn cancel(&mut self) {
+        self.task = None;
+        self.deadline = None;
+    }
+}
+
+// An engine in another process, spoken to through the engine protocol. If
+// it cannot be started, crashes, runs out of time or sends a move that cannot
+// be played, the built-in AI takes over its side for the rest of the game.
+pub struct ExternalPlayer {
+    process: Option<EngineProcess>,
+    fallback: EnginePlayer,
+    // Whether the engine was told about the board of this game
+    started: bool,
+    // When the best move of the running search is due
+    deadline: Option<Instant>,
+    progress: SearchProgress,
+    // Readiness checks sent after stopping a search whose `readyok` is still
+    // to come. Every reply before it belongs to the stopped search.
+    unsynced: u32,
+}
+
+impl ExternalPlayer {
+    pub fn new(command: Option<&str>, config: EngineConfig) -> Self {
+        let process = match command.map(EngineProcess::spawn) {
+            Some(Ok(process)) => Some(process),
+            Some(Err(error)) => {
+                warn!("Could not start the external engine: {}", error);
+                None
+            }
+            None => {
+                warn!("No external engine was given with --engine");
+                None
+            }
+        };
+        ExternalPlayer {
+            process,
+            fallback: EnginePlayer::new(config),
+            started: false,
+            deadline: None,
+            progress: SearchProgress::default(),
+            unsynced: 0,
+        }
+    }
+
+    // Send the position the first time, then wait for the engine's move.
+    // Errors describe why the engine can no longer play.
+    fn poll_engine(&mut self, turn: &mut Turn) -> Result<Option<Coordinates>, String> {
+        let process = self.process.as_mut().unwrap();
+        let Some(deadline) = self.deadline else {
+            let size = turn.tile_map.0.len() as u32;
+            let mut commands = Vec::new();
+            if !self.started {
+                commands.push(Command::NewGame { size, win_length: turn.win_length });
+                self.started = true;
+            }
+            commands.push(Command::Board(Position::from_tile_map(turn.tile_map)));
+            let move_time = match turn.clock {
+                Some((remaining, increment)) => clock::move_budget(remaining, increment),
+                None => constants::EXTERNAL_MOVE_TIME,
+            };
+            commands.push(Command::Go(Limit::MoveTime(move_time)));
+            for command in commands.iter() {
+                process.send(command).map_err(|error| error.to_string())?;
+            }
+            self.progress = turn.report.restart();
+            self.deadline = Some(Instant::now() + move_time + constants::EXTERNAL_TIME_MARGIN);
+            return Ok(None);
+        };
+
+        while let Some(reply) = process.try_reply().map_err(|error| error.to_string())? {
+            if self.unsynced > 0 {
+                if reply == Reply::ReadyOk {
+                    self.unsynced -= 1;
+                }
+                continue;
+            }
+            match reply {
+                Reply::Hello(name) => info!("External engine {} plays {:?}", name, turn.side),
+                Reply::ReadyOk => {}
+                Reply::Info { depth, score, pv } => {
+                    let result = SearchResult { player: turn.side, best_move: pv.first().copied(), score, pv, depth };
+                    self.progress.publish(&result);
+                }
+                Reply::BestMove(None) => return Err("found no move".to_string()),
+                Reply::BestMove(Some(coordinates)) => {
+                    let tile = turn.tile_map.0.get(coordinates.x as usize)
+                        .and_then(|line| line.get(coordinates.y as usize));
+                    if tile != Some(&Tile::Empty) {
+                        return Err(format!("sent the move {}, which cannot be played", coordinates.notation()));
+                    }
+                    self.deadline = None;
+                    info!("{:?} plays {} from the external engine", turn.side, coordinates.notation());
+                    return Ok(Some(coordinates));
+                }
+            }
+        }
+        if Instant::now() > deadline {
+            return Err("ran out of time".to_string());
+        }
+        Ok(None)
+    }
+}
+
+impl Player for ExternalPlayer {
+    fn poll_move(&mut self, turn: &mut Turn) -> Option<Coordinates> {
+        if self.process.is_some() {
+            match self.poll_engine(turn) {
+                Ok(coordinates) => return coordinates,
+                Err(reason) => {
+                    warn!("The external engine playing {:?} {}; the AI takes over", turn.side, reason);
+                    self.process = None;
+                    self.deadline = None;
+                }
+            }
+        }
+        self.fallback.poll_move(turn)
+    }
+
+    fn reports_search(&self) -> bool {
+        true
+    }
+
+    // The engine answers a stop with the move it found, which may also have
+    // been sent already, so the game waits for its answer to a readiness
+    // check to know where the replies of the stopped search end
+    fn cancel(&mut self) {
+        if let (Some(process), Some(_)) = (self.process.as_mut(), self.deadline.take()) {
+            if process.send(&Command::Stop).and_then(|_| process.send(&Command::IsReady)).is_ok() {
+                self.unsynced += 1;
+            }
+        }
+        self.fallback.cancel();
+    }
+}
+
+// Plays a random empty tile
+pub struct RandomPlayer;
+
+impl Player for RandomPlayer {
+    fn poll_move(&mut self, turn: &mut Turn) -> Option<Coordinates> {
+        let size = turn.tile_map.0.len() as u16;
+        let empty: Vec<Coordinates> = (0..size)
+            .flat_map(|x| (0..size).map(move |y| Coordinates { x, y }))
+            .filter(|coordinates| turn.tile_map.0[coordinates.x as usize][coordinates.y as usize] == Tile::Empty)
+            .collect();
+        if empty.is_empty() {
+            return None;
+        }
+        Some(empty[fastrand::usize(..empty.len())])
+    }
+}
+
+// Moves received from outside the game, e.g. over a connection. Clones share
+// the same queue, so one end can be kept by whoever receives the moves.
+#[derive(Clone, Default)]
+pub struct RemoteMoves(Arc<Mutex<VecDeque<Coordinates>>>);
+
+impl RemoteMoves {
+    pub fn push(&self, coordinates: Coordinates) {
+        self.0.lock().unwrap().push_back(coordinates);
+    }
+
+    fn pop(&self) -> Option<Coordinates> {
+        self.0.lock().unwrap().pop_front()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().is_empty()
+    }
+}
+
+// Plays the moves pushed to its queue
+pub struct RemotePlayer {
+    moves: RemoteMoves,
+}
+
+impl RemotePlayer {
+    pub fn new(moves: RemoteMoves) -> Self {
+        RemotePlayer { moves }
+    }
+}
+
+impl Player for RemotePlayer {
+    fn poll_move(&mut self, _turn: &mut Turn) -> Option<Coordinates> {
+        self.moves.pop()
+    }
+}
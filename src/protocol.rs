@@ -0,0 +1,287 @@
+// A line-based text protocol between the game and an engine running in its
+// own process, in the spirit of UCI. The game writes commands to the
+// engine's standard input and reads replies from its standard output:
+//
+//   hello 1                     protocol version; answered by `hello NAME`
+//   newgame 7 4                 board size and win length of a new game
+//   position board x../.o./...  the board, as written by `Position::to_text`
+//   position moves c3 d4        or the moves played from the empty board
+//   go movetime 500             search for at most this many milliseconds
+//   go depth 5                  or to this depth
+//   stop                        answer with the best move found so far
+//   isready                     answered by `readyok`
+//   quit
+//
+// While searching the engine may send `info depth 4 score 12 pv c3 d4`, where
+// the game skips any other key and its value before `pv`, and it ends every
+// search with `bestmove c3`, or `bestmove none` when it has no move. Unknown
+// lines are ignored by both sides.
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command as Process, Stdio};
+use std::sync::{Mutex, mpsc::{self, Receiver, TryRecvError}};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Coordinates, bitboard::Position};
+
+pub const VERSION: u32 = 1;
+
+// How long a search may go on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    MoveTime(Duration),
+    Depth(u32),
+    // Until stopped or the engine's own limit
+    None,
+}
+
+// What the game sends to an engine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Hello(u32),
+    NewGame { size: u32, win_length: u32 },
+    Board(Position),
+    Moves(Vec<Coordinates>),
+    Go(Limit),
+    Stop,
+    IsReady,
+    Quit,
+}
+
+// What an engine sends back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    Hello(String),
+    ReadyOk,
+    Info { depth: u32, score: i32, pv: Vec<Coordinates> },
+    BestMove(Option<Coordinates>),
+}
+
+fn parse_moves<'a>(words: impl Iterator<Item = &'a str>) -> Option<Vec<Coordinates>> {
+    words.map(Coordinates::from_notation).collect()
+}
+
+fn notations(moves: &[Coordinates]) -> String {
+    moves.iter().map(|coordinates| coordinates.notation()).collect::<Vec<_>>().join(" ")
+}
+
+impl Command {
+    // None for lines that are not a known command
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let command = match words.next()? {
+            "hello" => Command::Hello(words.next()?.parse().ok()?),
+            "newgame" => Command::NewGame { size: words.next()?.parse().ok()?, win_length: words.next()?.parse().ok()? },
+            "position" => match words.next()? {
+                "board" => Command::Board(Position::from_text(words.next()?)?),
+                "moves" => Command::Moves(parse_moves(words)?),
+                _ => return None,
+            },
+            "go" => Command::Go(match (words.next(), words.next()) {
+                (Some("movetime"), Some(milliseconds)) => Limit::MoveTime(Duration::from_millis(milliseconds.parse().ok()?)),
+                (Some("depth"), Some(depth)) => Limit::Depth(depth.parse().ok()?),
+                (None, _) => Limit::None,
+                _ => return None,
+            }),
+            "stop" => Command::Stop,
+            "isready" => Command::IsReady,
+            "quit" => Command::Quit,
+            _ => return None,
+        };
+        Some(command)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Hello(version) => write!(f, "hello {}", version),
+            Command::NewGame { size, win_length } => write!(f, "newgame {} {}", size, win_length),
+            Command::Board(position) => write!(f, "position board {}", position.to_text()),
+            Command::Moves(moves) => write!(f, "position moves {}", notations(moves)),
+            Command::Go(Limit::MoveTime(time)) => write!(f, "go movetime {}", time.as_millis()),
+            Command::Go(Limit::Depth(depth)) => write!(f, "go depth {}", depth),
+            Command::Go(Limit::None) => write!(f, "go"),
+            Command::Stop => write!(f, "stop"),
+            Command::IsReady => write!(f, "isready"),
+            Command::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+impl Reply {
+    // None for lines that are not a known reply
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+        let reply = match words.next()? {
+            "hello" => Reply::Hello(words.collect::<Vec<_>>().join(" ")),
+            "readyok" => Reply::ReadyOk,
+            "info" => {
+                let (mut depth, mut score, mut pv) = (0, 0, Vec::new());
+                while let Some(key) = words.next() {
+                    match key {
+                        "depth" => depth = words.next()?.parse().ok()?,
+                        "score" => score = words.next()?.parse().ok()?,
+                        // The line takes the rest of the reply
+                        "pv" => pv = parse_moves(words.by_ref())?,
+                        // Other engines may send more, e.g. `nodes 1234`
+                        _ => {
+                            words.next()?;
+                        }
+                    }
+                }
+                Reply::Info { depth, score, pv }
+            }
+            "bestmove" => match words.next()? {
+                "none" => Reply::BestMove(None),
+                notation => Reply::BestMove(Some(Coordinates::from_notation(notation)?)),
+            },
+            _ => return None,
+        };
+        Some(reply)
+    }
+}
+
+impl fmt::Display for Reply {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reply::Hello(name) => write!(f, "hello {}", name),
+            Reply::ReadyOk => write!(f, "readyok"),
+            Reply::Info { depth, score, pv } if pv.is_empty() => write!(f, "info depth {} score {}", depth, score),
+            Reply::Info { depth, score, pv } => write!(f, "info depth {} score {} pv {}", depth, score, notations(pv)),
+            Reply::BestMove(Some(coordinates)) => write!(f, "bestmove {}", coordinates.notation()),
+            Reply::BestMove(None) => write!(f, "bestmove none"),
+        }
+    }
+}
+
+// An engine process. Its replies are read on a thread of their own so that
+// they can be polled without blocking; the process is stopped when this is
+// dropped.
+pub struct EngineProcess {
+    child: Child,
+    input: ChildStdin,
+    // Behind a lock so that players holding the process can be shared
+    replies: Mutex<Receiver<Reply>>,
+}
+
+impl EngineProcess {
+    // Start the program and arguments of `command`, split on whitespace, and
+    // send it the protocol version
+    pub fn spawn(command: &str) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty engine command"))?;
+        let mut child = Process::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let input = child.stdin.take().unwrap();
+        let output = BufReader::new(child.stdout.take().unwrap());
+
+        // The channel closes when the engine's output does, e.g. if it crashed
+        let (sender, replies) = mpsc::channel();
+        thread::spawn(move || {
+            for line in output.lines() {
+                let Ok(line) = line else { break };
+                if let Some(reply) = Reply::parse(&line) {
+                    if sender.send(reply).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        let mut process = EngineProcess { child, input, replies: Mutex::new(replies) };
+        process.send(&Command::Hello(VERSION))?;
+        Ok(process)
+    }
+
+    pub fn send(&mut self, command: &Command) -> io::Result<()> {
+        writeln!(self.input, "{}", command)?;
+        self.input.flush()
+    }
+
+    // The next reply if one has arrived, or an error once the engine is gone
+    pub fn try_reply(&mut self) -> io::Result<Option<Reply>> {
+        match self.replies.lock().unwrap().try_recv() {
+            Ok(reply) => Ok(Some(reply)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                let status = self.child.try_wait()?;
+                let message = match status {
+                    Some(status) => format!("the engine exited with {}", status),
+                    None => "the engine closed its output".to_string(),
+                };
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, message))
+            }
+        }
+    }
+}
+
+impl Drop for EngineProcess {
+    fn drop(&mut self) {
+        let _ = self.send(&Command::Quit);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves(notations: &[&str]) -> Vec<Coordinates> {
+        notations.iter().map(|notation| Coordinates::from_notation(notation).unwrap()).collect()
+    }
+
+    #[test]
+    fn commands_read_back_as_written() {
+        for command in [
+            Command::Hello(VERSION),
+            Command::NewGame { size: 7, win_length: 4 },
+            Command::Board(Position::from_text("x../.o./...").unwrap()),
+            Command::Moves(moves(&["c3", "d4"])),
+            Command::Moves(Vec::new()),
+            Command::Go(Limit::MoveTime(Duration::from_millis(500))),
+            Command::Go(Limit::Depth(5)),
+            Command::Go(Limit::None),
+            Command::Stop,
+            Command::IsReady,
+            Command::Quit,
+        ] {
+            assert_eq!(Command::parse(&command.to_string()), Some(command));
+        }
+    }
+
+    #[test]
+    fn replies_read_back_as_written() {
+        for reply in [
+            Reply::Hello("tic_tac_toe Minimax".to_string()),
+            Reply::ReadyOk,
+            Reply::Info { depth: 4, score: -12, pv: moves(&["c3", "d4", "a1"]) },
+            Reply::Info { depth: 1, score: 0, pv: Vec::new() },
+            Reply::BestMove(Some(Coordinates { x: 2, y: 3 })),
+            Reply::BestMove(None),
+        ] {
+            assert_eq!(Reply::parse(&reply.to_string()), Some(reply));
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_not_read() {
+        for line in ["", "go movetime x", "go movetime", "go depth -1", "go fast", "newgame 7", "hello one",
+                     "position board x../.o", "position moves c3 !!", "position", "start"] {
+            assert_eq!(Command::parse(line), None, "{:?}", line);
+        }
+        for line in ["", "bestmove zz", "bestmove", "info depth 4 score 12 pv c3 ??", "info depth x",
+                     "info score", "info nodes", "move c3"] {
+            assert_eq!(Reply::parse(line), None, "{:?}", line);
+        }
+        assert_eq!(Reply::parse("info nodes 100 depth 3"), Some(Reply::Info { depth: 3, score: 0, pv: Vec::new() }));
+        assert_eq!(Reply::parse("info depth 5 nodes 1234 score 12 time 40 pv c3 d4"),
+                   Some(Reply::Info { depth: 5, score: 12, pv: moves(&["c3", "d4"]) }));
+    }
+}
@@ -0,0 +1,342 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+
+use crate::{constants, Board, Coordinates, GameState, Tile, history::GameRecord, menu::get_menu_styles,
+            game::{PlayingState, PlayingItem, Piece, WhoseTurn, spawn_piece, render_piece, game_button_action}};
+
+pub struct ReplayPlugin;
+
+// A recorded game being shown move by move
+pub struct Replay {
+    pub record: GameRecord,
+    moves: Vec<Coordinates>,
+    // Number of moves currently placed on the board
+    shown: usize,
+    // Number of moves that should be placed on the board
+    target: usize,
+    autoplay: bool,
+    // Moves per second while autoplaying
+    speed: f32,
+    timer: Timer,
+}
+
+// All actions that can be triggered from a replay control button
+#[derive(Component, Clone, Copy)]
+enum ReplayButtonAction {
+    First,
+    Previous,
+    TogglePlay,
+    Next,
+    Last,
+    Slower,
+    Faster,
+}
+
+#[derive(Component)]
+struct ReplayStatusText;
+
+#[derive(Component)]
+struct ReplayTimeline;
+
+#[derive(Component)]
+struct ReplayTimelineFill;
+
+impl Replay {
+    pub fn new(record: GameRecord) -> Self {
+        let moves = record.moves.iter()
+            .filter_map(|notation| Coordinates::from_notation(notation))
+            .collect();
+        Replay {
+            record,
+            moves,
+            shown: 0,
+            target: 0,
+            autoplay: false,
+            speed: constants::REPLAY_DEFAULT_SPEED,
+            timer: Timer::from_seconds(1.0 / constants::REPLAY_DEFAULT_SPEED, true),
+        }
+    }
+
+    fn set_target(&mut self, target: usize) {
+        self.target = target.min(self.moves.len());
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(constants::REPLAY_MIN_SPEED, constants::REPLAY_MAX_SPEED);
+        self.timer.set_duration(Duration::from_secs_f32(1.0 / self.speed));
+    }
+
+    fn status(&self) -> String {
+        let state = if self.autoplay { "Playing" } else { "Paused" };
+        match self.target.checked_sub(1).map(|index| (index, self.moves[index])) {
+            Some((index, coordinates)) => {
+                let side = if index.is_multiple_of(2) { "X" } else { "O" };
+                format!("Move {}/{}: {} {}\n{} at {}x", self.target, self.moves.len(),
+                        side, coordinates.notation(), state, self.speed)
+            }
+            None => format!("Move 0/{}\n{} at {}x", self.moves.len(), state, self.speed),
+        }
+    }
+}
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(PlayingState::Replaying)
+                            .with_system(replay_setup))
+            .add_system_set(SystemSet::on_update(PlayingState::Replaying)
+                            .with_system(replay_keyboard)
+                            .with_system(replay_button_action)
+                            .with_system(replay_timeline)
+                            .with_system(replay_autoplay)
+                            .with_system(replay_apply)
+                            .with_system(replay_remove_pieces)
+                            .with_system(replay_status)
+                            .with_system(render_piece)
+                            .with_system(game_button_action))
+            .add_system_set(SystemSet::on_exit(GameState::Game)
+                            .with_system(replay_cleanup));
+    }
+}
+
+// Spawn the replay controls: status text, timeline and buttons
+fn replay_setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let (font, button_style, button_text_style) = get_menu_styles(asset_server);
+    let small_button_style = Style {
+        size: Size::new(Val::Px(constants::REPLAY_BUTTON_SIZE), Val::Px(constants::REPLAY_BUTTON_SIZE)),
+        margin: UiRect::all(Val::Px(constants::REPLAY_BUTTON_MARGIN)),
+        ..button_style
+    };
+
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(constants::HUD_MARGIN),
+                top: Val::Px(constants::HUD_MARGIN),
+                ..default()
+            },
+            flex_direction: FlexDirection::ColumnReverse,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        color: Color::GRAY.into(),
+        ..default()
+    })
+    .insert(Name::new("ReplayControls"))
+    .insert(PlayingItem)
+    .with_children(|parent| {
+        parent.spawn_bundle(TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: constants::STATS_FONT_SIZE,
+                color: constants::TEXT_COLOR,
+            },
+        ))
+        .insert(ReplayStatusText);
+
+        parent.spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Px(constants::REPLAY_TIMELINE_WIDTH),
+                                Val::Px(constants::REPLAY_TIMELINE_HEIGHT)),
+                margin: UiRect::all(Val::Px(constants::REPLAY_BUTTON_MARGIN)),
+                ..default()
+            },
+            color: constants::NORMAL_BUTTON.into(),
+            ..default()
+        })
+        .insert(Interaction::default())
+        .insert(ReplayTimeline)
+        .with_children(|parent| {
+            parent.spawn_bundle(NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                    ..default()
+                },
+                color: constants::PRESSED_BUTTON.into(),
+                focus_policy: bevy::ui::FocusPolicy::Pass,
+                ..default()
+            })
+            .insert(ReplayTimelineFill);
+        });
+
+        parent.spawn_bundle(NodeBundle {
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .with_children(|parent| {
+            for (action, text) in [
+                (ReplayButtonAction::Slower, "-"),
+                (ReplayButtonAction::First, "|<"),
+                (ReplayButtonAction::Previous, "<"),
+                (ReplayButtonAction::TogglePlay, ">||"),
+                (ReplayButtonAction::Next, ">"),
+                (ReplayButtonAction::Last, ">|"),
+                (ReplayButtonAction::Faster, "+"),
+            ] {
+                parent.spawn_bundle(ButtonBundle {
+                    style: small_button_style.clone(),
+                    color: constants::NORMAL_BUTTON.into(),
+                    ..default()
+                })
+                .insert(action)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        text,
+                        button_text_style.clone(),
+                    ));
+                });
+            }
+        });
+    });
+}
+
+fn apply_action(replay: &mut Replay, action: ReplayButtonAction) {
+    match action {
+        ReplayButtonAction::First => replay.set_target(0),
+        ReplayButtonAction::Previous => replay.set_target(replay.target.saturating_sub(1)),
+        ReplayButtonAction::Next => replay.set_target(replay.target + 1),
+        ReplayButtonAction::Last => replay.set_target(replay.moves.len()),
+        ReplayButtonAction::TogglePlay => {
+            // Restart from the beginning when autoplay is started at the end
+            if !replay.autoplay && replay.target == replay.moves.len() {
+                replay.set_target(0);
+            }
+            replay.autoplay = !replay.autoplay;
+            replay.timer.reset();
+        }
+        ReplayButtonAction::Slower => {
+            let speed = replay.speed / 2.0;
+            replay.set_speed(speed);
+        }
+        ReplayButtonAction::Faster => {
+            let speed = replay.speed * 2.0;
+            replay.set_speed(speed);
+        }
+    }
+}
+
+fn replay_keyboard(keys: Res<Input<KeyCode>>, mut replay: ResMut<Replay>) {
+    for (key, action) in [
+        (KeyCode::Home, ReplayButtonAction::First),
+        (KeyCode::Left, ReplayButtonAction::Previous),
+        (KeyCode::Space, ReplayButtonAction::TogglePlay),
+        (KeyCode::Right, ReplayButtonAction::Next),
+        (KeyCode::End, ReplayButtonAction::Last),
+        (KeyCode::Down, ReplayButtonAction::Slower),
+        (KeyCode::Up, ReplayButtonAction::Faster),
+    ] {
+        if keys.just_pressed(key) {
+            apply_action(&mut replay, action);
+        }
+    }
+}
+
+fn replay_button_action(
+    interaction_query: Query<(&Interaction, &ReplayButtonAction), Changed<Interaction>>,
+    mut replay: ResMut<Replay>,
+    ) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction == Interaction::Clicked {
+            apply_action(&mut replay, *action);
+        }
+    }
+}
+
+// Jump to the move under the cursor while the timeline is held down
+fn replay_timeline(
+    windows: Res<Windows>,
+    timeline_query: Query<(&Interaction, &Node, &GlobalTransform), With<ReplayTimeline>>,
+    mut replay: ResMut<Replay>,
+    ) {
+    let window = windows.get_primary().unwrap();
+    let cursor = match window.cursor_position() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    for (interaction, node, transform) in timeline_query.iter() {
+        if *interaction == Interaction::Clicked {
+            let left = transform.translation().x - node.size.x / 2.0;
+            let fraction = ((cursor.x - left) / node.size.x).clamp(0.0, 1.0);
+            let target = (fraction * replay.moves.len() as f32).round() as usize;
+            replay.set_target(target);
+        }
+    }
+}
+
+fn replay_autoplay(time: Res<Time>, mut replay: ResMut<Replay>) {
+    if !replay.autoplay {
+        return;
+    }
+    if replay.timer.tick(time.delta()).just_finished() {
+        let target = replay.target + 1;
+        replay.set_target(target);
+    }
+    if replay.target == replay.moves.len() {
+        replay.autoplay = false;
+    }
+}
+
+// Place or remove pieces until the board shows `target` moves
+fn replay_apply(
+    mut commands: Commands,
+    mut replay: ResMut<Replay>,
+    mut board: ResMut<Board>,
+    ) {
+    while replay.shown < replay.target {
+        let coordinates = replay.moves[replay.shown];
+        let (tile, whose_turn) = if replay.shown.is_multiple_of(2) {
+            (Tile::X, WhoseTurn::XTurn)
+        } else {
+            (Tile::O, WhoseTurn::OTurn)
+        };
+        board.tile_map.0[coordinates.x as usize][coordinates.y as usize] = tile;
+        spawn_piece(&mut commands, board.coord_to_tile.get(&coordinates), &whose_turn);
+        replay.shown += 1;
+    }
+    while replay.shown > replay.target {
+        replay.shown -= 1;
+        let coordinates = replay.moves[replay.shown];
+        board.tile_map.0[coordinates.x as usize][coordinates.y as usize] = Tile::Empty;
+        if let Some(tile) = board.coord_to_tile.get(&coordinates) {
+            commands.entity(*tile).remove::<Tile>();
+        }
+    }
+}
+
+// Remove the sprites of pieces that were taken back. This runs separately from
+// `replay_apply` because a sprite is only spawned a frame after its tile is set.
+fn replay_remove_pieces(
+    mut commands: Commands,
+    board: Res<Board>,
+    pieces: Query<(Entity, &Piece)>,
+    ) {
+    for (entity, piece) in pieces.iter() {
+        let Piece(coordinates) = piece;
+        if board.tile_map.0[coordinates.x as usize][coordinates.y as usize] == Tile::Empty {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn replay_status(
+    replay: Res<Replay>,
+    mut text_query: Query<&mut Text, With<ReplayStatusText>>,
+    mut fill_query: Query<&mut Style, With<ReplayTimelineFill>>,
+    ) {
+    if !replay.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = replay.status();
+    }
+    let progress = 100.0 * replay.target as f32 / replay.moves.len().max(1) as f32;
+    for mut style in fill_query.iter_mut() {
+        style.size.width = Val::Percent(progress);
+    }
+}
+
+fn replay_cleanup(mut commands: Commands) {
+    commands.remove_resource::<Replay>();
+}
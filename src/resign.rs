@@ -0,0 +1,272 @@
+// Resigning and offering draws in games played on this computer. A person
+// resigns or offers a draw for the side they play, or for the side to move
+// when people play both sides. Another person answers an offer with the
+// buttons, while the AI accepts it only when a search of the position with
+// the settings it plays with finds it drawn or worse for the AI. Network
+// games have their own, see `online`.
+use bevy::{prelude::*, tasks::{AsyncComputeTaskPool, Task}};
+use futures_lite::future;
+
+use crate::{constants, despawn_screen, Board, GameMode, GameOutcome, MoveHistory, Tile, ai::SearchProgress,
+            engine::EngineMove, menu::{get_menu_styles, MenuState}, player::Players,
+            game::{Ending, GameButtonColumn, PlayerSettings, PlayingState}};
+
+pub struct ResignPlugin;
+
+// The buttons, which only stay while the game is played
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+enum ResignButtonAction {
+    Resign,
+    OfferDraw,
+    AcceptDraw,
+    DeclineDraw,
+}
+
+// A draw offered by `side` when `moves` moves were on the board
+#[derive(Clone, Copy)]
+struct DrawOffer {
+    side: Tile,
+    moves: usize,
+}
+
+impl DrawOffer {
+    // The offer lapses once the side it was made to plays a move instead
+    fn lapsed(&self, moves: usize) -> bool {
+        let offerer_to_move = self.moves.is_multiple_of(2) == (self.side == Tile::X);
+        moves > self.moves + usize::from(offerer_to_move)
+    }
+}
+
+// The search the AI answers an offer from, started when `moves` moves were
+// on the board
+struct DrawAnswer {
+    moves: usize,
+    task: Task<EngineMove>,
+    progress: SearchProgress,
+}
+
+// The draw offer of the current game waiting for an answer, and the number of
+// moves on the board when the last one was declined
+#[derive(Default)]
+struct DrawOffers {
+    pending: Option<DrawOffer>,
+    declined: Option<usize>,
+    answer: Option<DrawAnswer>,
+}
+
+impl DrawOffers {
+    fn stop_answer(&mut self) {
+        if let Some(answer) = self.answer.take() {
+            answer.progress.stop();
+        }
+    }
+}
+
+impl Plugin for ResignPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DrawOffers>()
+            .add_system_set(SystemSet::on_enter(PlayingState::Playing).with_system(resign_buttons_setup))
+            .add_system_set(SystemSet::on_update(PlayingState::Playing)
+                            .with_system(resign_action)
+                            .with_system(draw_answer_search)
+                            .with_system(draw_answer)
+                            .with_system(resign_buttons_display))
+            .add_system_set(SystemSet::on_exit(PlayingState::Playing)
+                            .with_system(stop_draw_answer)
+                            .with_system(despawn_screen::<ResignButtonAction>))
+            .add_system_set(SystemSet::on_enter(MenuState::Paused).with_system(stop_draw_answer));
+    }
+}
+
+// The side resigning or offering a draw from this computer, if a person
+// plays either side
+fn person_side(players: &Players, moves: &MoveHistory) -> Option<Tile> {
+    let side = moves.side_to_move();
+    [side, side.opponent()].into_iter().find(|side| players.get(*side).is_local_human())
+}
+
+// Add the buttons below the other game buttons. Those answering an offer are
+// only shown while one waits for a person.
+fn resign_buttons_setup(mut commands: Commands,
+                        mode: Res<GameMode>,
+                        asset_server: Res<AssetServer>,
+                        column_query: Query<Entity, With<GameButtonColumn>>) {
+    commands.insert_resource(DrawOffers::default());
+    if *mode == GameMode::Network {
+        return;
+    }
+    let (_, button_style, button_text_style) = get_menu_styles(asset_server);
+    for column in column_query.iter() {
+        commands.entity(column).with_children(|parent| {
+            for (action, name, label) in [
+                (ResignButtonAction::Resign, "ResignButton", constants::RESIGN_STRING),
+                (ResignButtonAction::OfferDraw, "OfferDrawButton", constants::OFFER_DRAW_STRING),
+                (ResignButtonAction::AcceptDraw, "AcceptDrawButton", constants::ACCEPT_DRAW_STRING),
+                (ResignButtonAction::DeclineDraw, "DeclineDrawButton", constants::DECLINE_DRAW_STRING),
+            ] {
+                parent.spawn_bundle(ButtonBundle {
+                    style: Style {
+                        display: Display::None,
+                        ..button_style.clone()
+                    },
+                    color: constants::NORMAL_BUTTON.into(),
+                    ..default()
+                })
+                .insert(Name::new(name))
+                .insert(action)
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(label, button_text_style.clone()));
+                });
+            }
+        });
+    }
+}
+
+fn resign_action(interaction_query: Query<(&Interaction, &ResignButtonAction), Changed<Interaction>>,
+                 players: Res<Players>,
+                 moves: Res<MoveHistory>,
+                 mut offers: ResMut<DrawOffers>,
+                 mut ending: ResMut<Ending>,
+                 mut playing_states: ResMut<State<PlayingState>>) {
+    for (interaction, action) in interaction_query.iter() {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        let Some(side) = person_side(&players, &moves) else { continue };
+        match action {
+            ResignButtonAction::Resign => {
+                let reason = match side {
+                    Tile::O => constants::O_RESIGNED_STRING,
+                    _ => constants::X_RESIGNED_STRING,
+                };
+                ending.decide(&mut playing_states, Some(GameOutcome::win_for(side.opponent())), reason.to_string());
+            }
+            // One offer per move, and none right after a declined one
+            ResignButtonAction::OfferDraw if offers.pending.is_none() && offers.declined != Some(moves.0.len()) => {
+                offers.pending = Some(DrawOffer { side, moves: moves.0.len() });
+            }
+            ResignButtonAction::AcceptDraw if offers.pending.is_some() => {
+                ending.decide(&mut playing_states, Some(GameOutcome::Draw), constants::DRAW_AGREED_STRING.to_string());
+            }
+            ResignButtonAction::DeclineDraw if offers.pending.is_some() => {
+                offers.pending = None;
+                offers.declined = Some(moves.0.len());
+            }
+            _ => {}
+        }
+    }
+}
+
+// Search the position an offer to the AI is answered from, as deep as the AI
+// plays. The search starts again if a move is played before it finishes, and
+// once the game is resumed after a pause.
+fn draw_answer_search(settings: PlayerSettings,
+                      menu_state: Res<State<MenuState>>,
+                      players: Res<Players>,
+                      board: Res<Board>,
+                      moves: Res<MoveHistory>,
+                      mut offers: ResMut<DrawOffers>) {
+    let Some(offer) = offers.pending.filter(|offer| !offer.lapsed(moves.0.len())) else { return };
+    let answering = offer.side.opponent();
+    if players.get(answering).is_local_human() || *menu_state.current() != MenuState::Disabled {
+        return;
+    }
+    if offers.answer.as_ref().is_some_and(|answer| answer.moves == moves.0.len()) {
+        return;
+    }
+    offers.stop_answer();
+    let progress = SearchProgress::default();
+    let (config, tile_map) = (settings.engine_config(answering), board.tile_map.clone());
+    let (side, win_length) = (moves.side_to_move(), board.win_length);
+    let task_progress = progress.clone();
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        config.search(&tile_map, side, win_length, &fastrand::Rng::new(), &task_progress)
+    });
+    offers.answer = Some(DrawAnswer { moves: moves.0.len(), task, progress });
+}
+
+// Drop offers that lapsed, and answer those made to the AI once its search of
+// the current position finished
+fn draw_answer(moves: Res<MoveHistory>,
+               mut offers: ResMut<DrawOffers>,
+               mut ending: ResMut<Ending>,
+               mut playing_states: ResMut<State<PlayingState>>) {
+    let Some(offer) = offers.pending else { return };
+    if offer.lapsed(moves.0.len()) {
+        offers.pending = None;
+        offers.stop_answer();
+        return;
+    }
+    let Some(answer) = offers.answer.as_mut().filter(|answer| answer.moves == moves.0.len()) else { return };
+    let Some(EngineMove { search, .. }) = future::block_on(future::poll_once(&mut answer.task)) else { return };
+    offers.answer = None;
+    let answering = offer.side.opponent();
+    // The search is made for the side to move, which need not be the AI
+    let score = if search.player == answering { search.score } else { -search.score };
+    if score <= 0 {
+        ending.decide(&mut playing_states, Some(GameOutcome::Draw), constants::DRAW_AGREED_STRING.to_string());
+    } else {
+        offers.pending = None;
+        offers.declined = Some(moves.0.len());
+    }
+}
+
+fn stop_draw_answer(mut offers: ResMut<DrawOffers>) {
+    offers.stop_answer();
+}
+
+// Show the buttons a person can use now, and what became of their offer
+fn resign_buttons_display(players: Res<Players>,
+                          moves: Res<MoveHistory>,
+                          offers: Res<DrawOffers>,
+                          mut button_query: Query<(&ResignButtonAction, &mut Style, &Children)>,
+                          mut text_query: Query<&mut Text>) {
+    let person = person_side(&players, &moves).is_some();
+    let answering = offers.pending.is_some_and(|offer| players.get(offer.side.opponent()).is_local_human());
+    for (action, mut style, children) in button_query.iter_mut() {
+        let shown = match action {
+            ResignButtonAction::Resign | ResignButtonAction::OfferDraw => person && !answering,
+            ResignButtonAction::AcceptDraw | ResignButtonAction::DeclineDraw => answering,
+        };
+        let display = if shown { Display::Flex } else { Display::None };
+        if style.display != display {
+            style.display = display;
+        }
+
+        if *action != ResignButtonAction::OfferDraw {
+            continue;
+        }
+        let label = match (offers.pending, offers.declined) {
+            (Some(_), _) => constants::DRAW_OFFERED_STRING,
+            (None, Some(declined)) if declined == moves.0.len() => constants::DRAW_DECLINED_STRING,
+            _ => constants::OFFER_DRAW_STRING,
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.sections[0].value != label {
+                    text.sections[0].value = label.to_string();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offer_of_the_side_to_move_lapses_after_two_moves() {
+        let offer = DrawOffer { side: Tile::X, moves: 2 };
+        assert!(!offer.lapsed(2));
+        assert!(!offer.lapsed(3));
+        assert!(offer.lapsed(4));
+    }
+
+    #[test]
+    fn offer_of_the_side_not_to_move_lapses_after_one_move() {
+        let offer = DrawOffer { side: Tile::O, moves: 2 };
+        assert!(!offer.lapsed(2));
+        assert!(offer.lapsed(3));
+    }
+}
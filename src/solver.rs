@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::{constants, Tile, TileMap, ai::{self, SearchResult}, bitboard::{self, Lines, Position}};
+
+// Largest board that can be solved; its keys hold both sides in a u32
+pub const MAX_SIZE: u32 = 4;
+
+const MAGIC: &[u8; 8] = b"TTTSOLV1";
+
+// Bytes of the file before the positions, and of each position
+const HEADER_LENGTH: u64 = 14;
+const ENTRY_LENGTH: u64 = 5;
+
+// Game-theoretic value of a position for the side to move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Outcome {
+    Loss,
+    Draw,
+    Win,
+}
+
+// Value of a position under perfect play and the number of moves, counting
+// both sides, until the game ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+    pub outcome: Outcome,
+    pub distance: u32,
+}
+
+impl Solution {
+    // Stored as one byte: the outcome in the low two bits, the distance above
+    fn encode(&self) -> u8 {
+        self.outcome as u8 | (self.distance as u8) << 2
+    }
+
+    fn decode(byte: u8) -> Self {
+        let outcome = match byte & 3 {
+            0 => Outcome::Loss,
+            1 => Outcome::Draw,
+            _ => Outcome::Win,
+        };
+        Solution { outcome, distance: (byte >> 2) as u32 }
+    }
+
+    // Value for the side that made the move leading to this position
+    fn before_move(&self) -> Self {
+        let outcome = match self.outcome {
+            Outcome::Loss => Outcome::Win,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Win => Outcome::Loss,
+        };
+        Solution { outcome, distance: self.distance + 1 }
+    }
+
+    // Win as fast as possible, lose as slowly as possible
+    fn is_better_than(&self, other: &Solution) -> bool {
+        match (self.outcome, other.outcome) {
+            (Outcome::Win, Outcome::Win) => self.distance < other.distance,
+            (Outcome::Loss, Outcome::Loss) => self.distance > other.distance,
+            _ => self.outcome > other.outcome,
+        }
+    }
+
+    // Score in the units of the search, so that both can be shown alike
+    pub fn score(&self) -> i32 {
+        match self.outcome {
+            Outcome::Win => ai::WIN_SCORE - self.distance as i32,
+            Outcome::Draw => 0,
+            Outcome::Loss => -(ai::WIN_SCORE - self.distance as i32),
+        }
+    }
+}
+
+// Every position reachable from the empty board before the game ends, one
+// per symmetry class, sorted by key
+pub struct Database {
+    pub size: u32,
+    pub win_length: u32,
+    entries: Vec<(u32, u8)>,
+}
+
+impl Database {
+    // Solve the game by searching every reachable position once
+    pub fn solve(size: u32, win_length: u32) -> Self {
+        assert!(size <= MAX_SIZE, "boards larger than {} tiles cannot be solved", MAX_SIZE);
+        let mut solver = Solver {
+            lines: bitboard::lines(size, win_length),
+            symmetries: symmetries(size),
+            solved: HashMap::new(),
+        };
+        solver.solve(Position::empty(size), Tile::X);
+
+        let mut entries: Vec<(u32, u8)> = solver.solved.into_iter().collect();
+        entries.sort_unstable();
+        Database { size, win_length, entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, position: &Position) -> Option<Solution> {
+        let key = canonical_key(position, symmetries(self.size));
+        self.entries.binary_search_by_key(&key, |(key, _)| *key).ok()
+            .map(|index| Solution::decode(self.entries[index].1))
+    }
+
+    // Best move of `player` and the value it leads to, None if the position
+    // is not in the database
+    pub fn best_move(&self, position: &Position, player: Tile) -> Option<(u32, Solution)> {
+        let lines = bitboard::lines(self.size, self.win_length);
+        let mut best: Option<(u32, Solution)> = None;
+        for square in bitboard::centre_order(self.size) {
+            if position.get(*square) != Tile::Empty {
+                continue;
+            }
+            let solution = self.move_value(position, player, *square, lines)?;
+            if best.is_none_or(|(_, best)| solution.is_better_than(&best)) {
+                best = Some((*square, solution));
+            }
+        }
+        best
+    }
+
+    // Value for `player` of playing `square`
+    fn move_value(&self, position: &Position, player: Tile, square: u32, lines: &Lines) -> Option<Solution> {
+        let mut child = *position;
+        child.set(square, player);
+        if lines.is_win(child.pieces(player), square) {
+            Some(Solution { outcome: Outcome::Win, distance: 1 })
+        } else if child.is_full() {
+            Some(Solution { outcome: Outcome::Draw, distance: 1 })
+        } else {
+            self.get(&child).map(|solution| solution.before_move())
+        }
+    }
+
+    // Check that the empty board is solved and that every stored value
+    // follows from the values of the positions after each move. Returns the
+    // number of positions checked.
+    pub fn verify(&self) -> Result<usize, String> {
+        let empty = Position::empty(self.size);
+        if self.get(&empty).is_none() {
+            return Err("the empty board is missing".to_string());
+        }
+        for (key, value) in self.entries.iter() {
+            let position = Position {
+                x: (*key & 0xffff) as u64,
+                o: (*key >> 16) as u64,
+                size: self.size,
+            };
+            let player = position.side_to_move();
+            let expected = self.best_move(&position, player).map(|(_, solution)| solution);
+            let stored = Solution::decode(*value);
+            if expected != Some(stored) {
+                return Err(format!("position {:#010x} is stored as {:?} but its moves give {:?}",
+                                   key, stored, expected));
+            }
+        }
+        Ok(self.entries.len())
+    }
+
+    pub fn save(&self, path: &PathBuf) -> io::Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[self.size as u8, self.win_length as u8])?;
+        file.write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        for (key, value) in self.entries.iter() {
+            file.write_all(&key.to_le_bytes())?;
+            file.write_all(&[*value])?;
+        }
+        file.flush()
+    }
+
+    // Read the database of a `size` board with `win_length` in a row, failing
+    // if the file holds another board
+    pub fn load(path: &PathBuf, size: u32, win_length: u32) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let file = File::open(path)?;
+        let length = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+        let mut header = [0; HEADER_LENGTH as usize];
+        file.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid("not a solved position database"));
+        }
+        if (header[8] as u32, header[9] as u32) != (size, win_length) {
+            return Err(invalid(&format!("holds a {}x{} k{} board", header[8], header[8], header[9])));
+        }
+        let count = u32::from_le_bytes([header[10], header[11], header[12], header[13]]) as usize;
+        // Checked before anything is allocated for the positions
+        if length != HEADER_LENGTH + count as u64 * ENTRY_LENGTH {
+            return Err(invalid("the number of positions does not match the file length"));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        let mut entry = [0; ENTRY_LENGTH as usize];
+        for _ in 0..count {
+            file.read_exact(&mut entry)?;
+            entries.push((u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]), entry[4]));
+        }
+        // Positions are found by binary search
+        if !entries.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            return Err(invalid("the positions are not sorted"));
+        }
+        Ok(Database { size, win_length, entries })
+    }
+}
+
+// Where the database of a board size and win length is kept
+pub fn path(size: u32, win_length: u32) -> PathBuf {
+    PathBuf::from(constants::SOLVED_DIRECTORY).join(format!("{}x{}k{}.db", size, size, win_length))
+}
+
+// Database of a board size and win length, loaded from its file the first
+// time it is asked for. None when the file does not exist.
+pub fn database(size: u32, win_length: u32) -> Option<Arc<Database>> {
+    type Loaded = HashMap<(u32, u32), Option<Arc<Database>>>;
+    static LOADED: OnceLock<Mutex<Loaded>> = OnceLock::new();
+    if size > MAX_SIZE {
+        return None;
+    }
+    let mut loaded = LOADED.get_or_init(Default::default).lock().unwrap();
+    loaded.entry((size, win_length))
+        .or_insert_with(|| Database::load(&path(size, win_length), size, win_length).ok().map(Arc::new))
+        .clone()
+}
+
+// Perfect move and line of `player` if the board has been solved
+pub fn lookup(tile_map: &TileMap, player: Tile, win_length: u32) -> Option<SearchResult> {
+    let database = database(tile_map.0.len() as u32, win_length)?;
+    let mut position = Position::from_tile_map(tile_map);
+    let (square, solution) = database.best_move(&position, player)?;
+
+    // Follow the perfect moves of both sides until the game ends
+    let lines = bitboard::lines(database.size, win_length);
+    let mut pv = Vec::new();
+    let mut side = player;
+    let mut next = Some(square);
+    while let Some(square) = next {
+        pv.push(position.coordinates(square));
+        position.set(square, side);
+        if lines.is_win(position.pieces(side), square) || position.is_full() {
+            break;
+        }
+        side = side.opponent();
+        next = database.best_move(&position, side).map(|(square, _)| square);
+    }
+
+    Some(SearchResult {
+        player,
+        best_move: pv.first().copied(),
+        score: solution.score(),
+        depth: solution.distance,
+        pv,
+    })
+}
+
+struct Solver {
+    lines: &'static Lines,
+    symmetries: &'static [Vec<u32>],
+    solved: HashMap<u32, u8>,
+}
+
+impl Solver {
+    // Value of a position where the game is not over yet
+    fn solve(&mut self, position: Position, player: Tile) -> Solution {
+        let key = canonical_key(&position, self.symmetries);
+        if let Some(value) = self.solved.get(&key) {
+            return Solution::decode(*value);
+        }
+
+        let mut best: Option<Solution> = None;
+        for square in bitboard::squares(position.empty_squares()) {
+            let mut child = position;
+            child.set(square, player);
+            let solution = if self.lines.is_win(child.pieces(player), square) {
+                Solution { outcome: Outcome::Win, distance: 1 }
+            } else if child.is_full() {
+                Solution { outcome: Outcome::Draw, distance: 1 }
+            } else {
+                self.solve(child, player.opponent()).before_move()
+            };
+            if best.is_none_or(|best| solution.is_better_than(&best)) {
+                best = Some(solution);
+            }
+        }
+
+        let best = best.unwrap();
+        self.solved.insert(key, best.encode());
+        best
+    }
+}
+
+// Where each square goes under each of the 8 rotations and reflections
+fn symmetries(size: u32) -> &'static [Vec<u32>] {
+    static SYMMETRIES: OnceLock<Vec<Vec<Vec<u32>>>> = OnceLock::new();
+    let symmetries = SYMMETRIES.get_or_init(|| (0..=MAX_SIZE).map(|size| {
+        let last = size.saturating_sub(1);
+        // Maps (last index, x, y) to the new coordinates
+        type Transform = fn(u32, u32, u32) -> (u32, u32);
+        let transforms: [Transform; 8] = [
+            |_, x, y| (x, y),
+            |last, x, y| (y, last - x),
+            |last, x, y| (last - x, last - y),
+            |last, x, y| (last - y, x),
+            |last, x, y| (x, last - y),
+            |last, x, y| (last - x, y),
+            |_, x, y| (y, x),
+            |last, x, y| (last - y, last - x),
+        ];
+        transforms.iter().map(|transform| {
+            (0..size * size).map(|square| {
+                let (x, y) = transform(last, square / size, square % size);
+                x * size + y
+            }).collect()
+        }).collect()
+    }).collect());
+    &symmetries[size as usize]
+}
+
+// Smallest key of the position over all its symmetries
+fn canonical_key(position: &Position, symmetries: &[Vec<u32>]) -> u32 {
+    symmetries.iter().map(|map| {
+        let transform = |pieces: u64| {
+            bitboard::squares(pieces).fold(0u32, |mask, square| mask | 1 << map[square as usize])
+        };
+        transform(position.x) | transform(position.o) << 16
+    }).min().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn three_by_three_is_a_draw_in_nine_moves() {
+        let database = Database::solve(3, 3);
+        let root = database.get(&Position::empty(3)).unwrap();
+        assert_eq!(root, Solution { outcome: Outcome::Draw, distance: 9 });
+        // The 765 positions that differ up to symmetry, less the 138 where the game is over
+        assert_eq!(database.len(), 627);
+        assert_eq!(database.verify(), Ok(627));
+
+        // Two in a row is won by the first player with their second move
+        let root = Database::solve(3, 2).get(&Position::empty(3)).unwrap();
+        assert_eq!(root, Solution { outcome: Outcome::Win, distance: 3 });
+    }
+
+    #[test]
+    fn verify_finds_a_wrong_value() {
+        let mut database = Database::solve(3, 3);
+        let (_, value) = &mut database.entries[100];
+        let wrong = match Solution::decode(*value).outcome {
+            Outcome::Win => Outcome::Loss,
+            _ => Outcome::Win,
+        };
+        *value = Solution { outcome: wrong, distance: 1 }.encode();
+        assert!(database.verify().is_err());
+    }
+
+    #[test]
+    fn symmetric_positions_share_their_value() {
+        let database = Database::solve(3, 3);
+        for text in ["x../.../...", "xo./.../...", "x../.o./..x", "xx./o../..o"] {
+            let position = Position::from_text(text).unwrap();
+            let key = canonical_key(&position, symmetries(3));
+            for map in symmetries(3) {
+                let transform = |pieces: u64| bitboard::squares(pieces).fold(0, |mask, square| mask | 1 << map[square as usize]);
+                let image = Position { x: transform(position.x), o: transform(position.o), size: 3 };
+                assert_eq!(canonical_key(&image, symmetries(3)), key);
+                assert_eq!(database.get(&image), database.get(&position));
+            }
+        }
+        // The corner and the edge openings are not the same position
+        let (corner, edge) = (Position::from_text("x../.../...").unwrap(), Position::from_text(".x./.../...").unwrap());
+        assert_ne!(canonical_key(&corner, symmetries(3)), canonical_key(&edge, symmetries(3)));
+    }
+
+    #[test]
+    fn load_reads_what_save_wrote_and_rejects_other_files() {
+        let path = std::env::temp_dir().join(format!("solver-test-{}.db", std::process::id()));
+        let database = Database::solve(3, 3);
+        database.save(&path).unwrap();
+        let loaded = Database::load(&path, 3, 3).unwrap();
+        assert_eq!((loaded.size, loaded.win_length), (3, 3));
+        assert_eq!(loaded.entries, database.entries);
+        for (size, win_length) in [(4, 3), (3, 2), (2, 2)] {
+            let error = Database::load(&path, size, win_length).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+
+        let bytes = fs::read(&path).unwrap();
+        let mut unsorted = bytes.clone();
+        let (first, second) = (HEADER_LENGTH as usize, (HEADER_LENGTH + ENTRY_LENGTH) as usize);
+        unsorted[first..second + ENTRY_LENGTH as usize].rotate_left(ENTRY_LENGTH as usize);
+        fs::write(&path, &unsorted).unwrap();
+        assert_eq!(Database::load(&path, 3, 3).err().unwrap().kind(), io::ErrorKind::InvalidData);
+
+        let mut wrong_count = bytes;
+        wrong_count[10..14].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &wrong_count).unwrap();
+        assert_eq!(Database::load(&path, 3, 3).err().unwrap().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
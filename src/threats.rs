@@ -0,0 +1,272 @@
+use crate::{Coordinates, Tile, TileMap, ai::SearchProgress, bitboard::{self, Lines, Position}};
+
+// Threat sequences are only looked for from this win length on; shorter rows
+// are short enough for the main search
+const MIN_WIN_LENGTH: u32 = 4;
+
+// Attacking moves looked ahead with fours only, and with threes as well
+const VCF_DEPTH: u32 = 12;
+const VCT_DEPTH: u32 = 4;
+
+// A search gives up after visiting this many positions, or once the search
+// it is part of is stopped
+const NODE_LIMIT: u32 = 20_000;
+
+// Proof search over forcing moves only. The attacker plays moves that make a
+// four, a row one piece short of winning, or with `threes` a three, a row
+// that wins next move in two ways. The defender answers a four on its
+// winning tile, any other answer losing at once. A three is answered on any
+// tile of the rows that would make the double threat, or with a four of its
+// own, which misses quieter defences; with `every_reply` it is answered on
+// every empty tile instead, so that the win found is proven.
+struct ThreatSearch<'a> {
+    lines: &'static Lines,
+    win_length: u32,
+    threes: bool,
+    every_reply: bool,
+    nodes: u32,
+    progress: &'a SearchProgress,
+}
+
+impl ThreatSearch<'_> {
+    // Union of the empty squares of the rows holding `count` pieces of `own`
+    // and none of `other`
+    fn row_squares(&self, own: u64, other: u64, count: u32) -> u64 {
+        self.lines.masks.iter()
+            .filter(|mask| *mask & other == 0 && (*mask & own).count_ones() == count)
+            .fold(0, |squares, mask| squares | (mask & !own))
+    }
+
+    // Empty squares where `own` completes a row
+    fn winning_squares(&self, own: u64, other: u64) -> u64 {
+        self.row_squares(own, other, self.win_length - 1)
+    }
+
+    // Empty squares where `own` makes two winning squares at once
+    fn double_threat_squares(&self, own: u64, other: u64) -> u64 {
+        bitboard::squares(self.row_squares(own, other, self.win_length - 2))
+            .filter(|square| self.winning_squares(own | 1 << square, other).count_ones() >= 2)
+            .fold(0, |squares, square| squares | 1 << square)
+    }
+
+    // Squares breaking every row that a move on `threats` turns into a four
+    fn three_defences(&self, own: u64, other: u64, threats: u64) -> u64 {
+        self.lines.masks.iter()
+            .filter(|mask| *mask & threats != 0 && *mask & other == 0
+                    && (*mask & own).count_ones() == self.win_length - 2)
+            .fold(0, |squares, mask| squares | (mask & !own))
+    }
+
+    // Forcing moves of `attacker`, to move in `position`, that win, or None
+    // when none is found within `depth` attacking moves. The line holds the
+    // moves of both sides and ends with the winning move.
+    fn attack(&mut self, position: Position, attacker: Tile, depth: u32) -> Option<Vec<u32>> {
+        self.nodes += 1;
+        let (own, other) = (position.pieces(attacker), position.pieces(attacker.opponent()));
+        let wins = self.winning_squares(own, other);
+        if wins != 0 {
+            return Some(vec![wins.trailing_zeros()]);
+        }
+        if depth == 0 || self.nodes > NODE_LIMIT || self.progress.is_stopped() {
+            return None;
+        }
+
+        // A four of the defender has to be blocked first
+        let blocks = self.winning_squares(other, own);
+        let moves = match blocks.count_ones() {
+            0 if self.threes => self.row_squares(own, other, self.win_length - 2)
+                | self.row_squares(own, other, self.win_length - 3),
+            0 => self.row_squares(own, other, self.win_length - 2),
+            1 => blocks,
+            _ => return None,
+        };
+        for square in bitboard::squares(moves) {
+            let mut next = position;
+            next.set(square, attacker);
+            if let Some(mut line) = self.defend(next, attacker, depth) {
+                line.insert(0, square);
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    // Line after the attacker's last move if every answer of the defender
+    // still loses
+    fn defend(&mut self, position: Position, attacker: Tile, depth: u32) -> Option<Vec<u32>> {
+        let defender = attacker.opponent();
+        let (own, other) = (position.pieces(attacker), position.pieces(defender));
+        if self.winning_squares(other, own) != 0 {
+            return None;
+        }
+
+        let wins = self.winning_squares(own, other);
+        let replies = match wins.count_ones() {
+            0 if self.threes => {
+                let threats = self.double_threat_squares(own, other);
+                if threats == 0 {
+                    return None;
+                }
+                if self.every_reply {
+                    position.empty_squares()
+                } else {
+                    self.three_defences(own, other, threats) | self.row_squares(other, own, self.win_length - 2)
+                }
+            }
+            0 => return None,
+            1 => wins,
+            // Only one of the winning squares can be blocked
+            _ => return Some(bitboard::squares(wins).take(2).collect()),
+        };
+
+        let mut line = None;
+        for reply in bitboard::squares(replies & position.empty_squares()) {
+            let mut next = position;
+            next.set(reply, defender);
+            let rest = self.attack(next, attacker, depth - 1)?;
+            line.get_or_insert_with(|| [vec![reply], rest].concat());
+        }
+        line
+    }
+}
+
+// Searches for fours only first, since they are much narrower, then for
+// fours and threes. A win with threes is only kept once the search answering
+// them on every tile proves it too. The positions visited are added to the
+// work of `progress`.
+fn find_win(position: &Position, attacker: Tile, win_length: u32, progress: &SearchProgress) -> Option<Vec<u32>> {
+    if win_length < MIN_WIN_LENGTH {
+        return None;
+    }
+    let search = |threes: bool, every_reply: bool, depth: u32| {
+        let mut search = ThreatSearch {
+            lines: bitboard::lines(position.size, win_length),
+            win_length,
+            threes,
+            every_reply,
+            nodes: 0,
+            progress,
+        };
+        let line = search.attack(*position, attacker, depth);
+        progress.add_work(search.nodes as u64, 0);
+        line
+    };
+    search(false, false, VCF_DEPTH).or_else(|| {
+        search(true, false, VCT_DEPTH)?;
+        search(true, true, VCT_DEPTH)
+    })
+}
+
+// Forced win of `player`, to move, through a sequence of threats: the moves
+// of both sides until the winning one
+pub fn winning_line(tile_map: &TileMap, player: Tile, win_length: u32,
+                    progress: &SearchProgress) -> Option<Vec<Coordinates>> {
+    let position = Position::from_tile_map(tile_map);
+    let line = find_win(&position, player, win_length, progress)?;
+    Some(line.into_iter().map(|square| position.coordinates(square)).collect())
+}
+
+// Moves of `player` after which the opponent has no forced win through a
+// sequence of threats, or None when the opponent has none to begin with or
+// the search was stopped before every move was tried
+pub fn defences(tile_map: &TileMap, player: Tile, win_length: u32,
+                progress: &SearchProgress) -> Option<Vec<Coordinates>> {
+    let position = Position::from_tile_map(tile_map);
+    find_win(&position, player.opponent(), win_length, progress)?;
+    let moves = position.candidate_moves().into_iter()
+        .filter(|square| {
+            let mut next = position;
+            next.set(*square, player);
+            find_win(&next, player.opponent(), win_length, progress).is_none()
+        })
+        .map(|square| position.coordinates(square))
+        .collect();
+    (!progress.is_stopped()).then_some(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 7x7 boards with four in a row to win and X to move
+    const FOURS: &str = "......./......o/..o..../x...x.x/......./....x../...oo..";
+    const THREES: &str = ".o...../x.x..../......./......./.o...../......./.......";
+    const QUIET: &str = "......./......./......./...x.../...o.../......./.......";
+
+    // Play `line` from `text`, X first, checking that only its last move wins
+    // and, with `fours`, that every answer of O blocks a winning tile of X,
+    // the only one until the last answer
+    fn check_line(text: &str, line: &[u32], fours: bool) {
+        let mut position = Position::from_text(text).unwrap();
+        let lines = bitboard::lines(7, 4);
+        let search = ThreatSearch { lines, win_length: 4, threes: false, every_reply: false, nodes: 0,
+                                    progress: &SearchProgress::default() };
+        let mut player = Tile::X;
+        for (index, square) in line.iter().copied().enumerate() {
+            assert_eq!(position.get(square), Tile::Empty);
+            if fours && player == Tile::O {
+                let wins = search.winning_squares(position.x, position.o);
+                assert!(wins & 1 << square != 0);
+                assert!(wins.count_ones() == 1 || index == line.len() - 2);
+            }
+            position.set(square, player);
+            assert_eq!(lines.is_win(position.pieces(player), square), index == line.len() - 1);
+            player = player.opponent();
+        }
+        assert_eq!(player, Tile::O);
+    }
+
+    #[test]
+    fn finds_a_win_with_fours() {
+        let position = Position::from_text(FOURS).unwrap();
+        let progress = SearchProgress::default();
+        let line = find_win(&position, Tile::X, 4, &progress).unwrap();
+        check_line(FOURS, &line, true);
+        assert!(progress.stats().nodes > 0);
+    }
+
+    #[test]
+    fn finds_a_win_with_threes_once_proven() {
+        let position = Position::from_text(THREES).unwrap();
+        let progress = SearchProgress::default();
+        let mut fours = ThreatSearch { lines: bitboard::lines(7, 4), win_length: 4, threes: false, every_reply: false,
+                                       nodes: 0, progress: &progress };
+        assert_eq!(fours.attack(position, Tile::X, VCF_DEPTH), None);
+        let line = find_win(&position, Tile::X, 4, &progress).unwrap();
+        check_line(THREES, &line, false);
+    }
+
+    #[test]
+    fn finds_no_win_on_a_quiet_board() {
+        let position = Position::from_text(QUIET).unwrap();
+        assert_eq!(find_win(&position, Tile::X, 4, &SearchProgress::default()), None);
+        // Nor with rows too short for threats
+        let position = Position::from_text(FOURS).unwrap();
+        assert_eq!(find_win(&position, Tile::X, 3, &SearchProgress::default()), None);
+    }
+
+    #[test]
+    fn stopped_search_finds_no_win() {
+        let progress = SearchProgress::default();
+        progress.stop();
+        let tile_map = Position::from_text(FOURS).unwrap().to_tile_map();
+        assert_eq!(winning_line(&tile_map, Tile::X, 4, &progress), None);
+        assert_eq!(defences(&tile_map, Tile::O, 4, &progress), None);
+    }
+
+    #[test]
+    fn defences_are_the_moves_leaving_no_win() {
+        let position = Position::from_text(FOURS).unwrap();
+        let tile_map = position.to_tile_map();
+        let progress = SearchProgress::default();
+        let moves = defences(&tile_map, Tile::O, 4, &progress).unwrap();
+        assert!(!moves.is_empty());
+        for square in position.candidate_moves() {
+            let mut next = position;
+            next.set(square, Tile::O);
+            let defends = find_win(&next, Tile::X, 4, &progress).is_none();
+            assert_eq!(moves.contains(&position.coordinates(square)), defends);
+        }
+        assert_eq!(defences(&Position::from_text(QUIET).unwrap().to_tile_map(), Tile::O, 4, &progress), None);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{GameOutcome, Tile, ai::{self, Weights}, bitboard::{self, Position}, engine::{self, EngineConfig}};
+
+// The local search stops after this many passes over the weights even if
+// it still finds improvements
+const MAX_PASSES: u32 = 100;
+
+// The sigmoid scales tried when fitting it to the results
+const SCALE_STEPS: i32 = 80;
+const SCALE_FACTOR: f64 = 1.2;
+
+// A position seen in a game and the result of that game
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub position: Position,
+    pub win_length: u32,
+    // 1 when X won, 0.5 for a draw and 0 when O won
+    pub result: f64,
+}
+
+// Quiet positions of `games` games the engine plays against itself, each
+// from a random opening of `opening` moves
+pub fn self_play_samples(config: &EngineConfig, size: u32, win_length: u32, games: u32, opening: u32,
+                         rng: &fastrand::Rng) -> Vec<Sample> {
+    let mut samples = Vec::new();
+    for _ in 0..games {
+        let opening = engine::random_opening(size, win_length, opening, rng);
+        let game = engine::play_game(config, config, size, win_length, &opening, rng);
+        let result = match game.outcome {
+            GameOutcome::XWins => 1.0,
+            GameOutcome::Draw => 0.5,
+            GameOutcome::OWins => 0.0,
+        };
+
+        let mut position = Position::empty(size);
+        let mut player = Tile::X;
+        for coordinates in game.moves.iter() {
+            position.set(position.square(*coordinates), player);
+            player = player.opponent();
+            if is_quiet(&position, win_length) {
+                samples.push(Sample { position, win_length, result });
+            }
+        }
+    }
+    samples
+}
+
+// Whether the evaluation can be trusted: the game goes on and neither side
+// is one piece short of a row
+fn is_quiet(position: &Position, win_length: u32) -> bool {
+    !position.is_full() && bitboard::lines(position.size, win_length).masks.iter().all(|mask| {
+        let (x, o) = ((mask & position.x).count_ones(), (mask & position.o).count_ones());
+        (x > 0 && o > 0) || x.max(o) + 1 < win_length
+    })
+}
+
+// Mean squared difference between the results and the chances of X that
+// the evaluation predicts through a sigmoid of width `scale`
+pub fn mean_squared_error(samples: &[Sample], weights: &Weights, scale: f64) -> f64 {
+    let total: f64 = samples.iter().map(|sample| {
+        let lines = bitboard::lines(sample.position.size, sample.win_length);
+        let score = ai::evaluate_with(&sample.position, Tile::X, lines, weights) as f64;
+        let predicted = 1.0 / (1.0 + (-score / scale).exp());
+        (sample.result - predicted).powi(2)
+    }).sum();
+    total / samples.len().max(1) as f64
+}
+
+// Sigmoid width that fits the results best with the given weights
+pub fn fit_scale(samples: &[Sample], weights: &Weights) -> f64 {
+    (0..SCALE_STEPS)
+        .map(|step| SCALE_FACTOR.powi(step))
+        .map(|scale| (scale, mean_squared_error(samples, weights, scale)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(scale, _)| scale)
+        .unwrap()
+}
+
+// Texel's local search: change one weight at a time by a small step in
+// either direction and keep the changes that lower the error, until a pass
+// over every weight changes none. `pass_done` is called after every pass
+// with its number and the error reached.
+pub fn tune<F: FnMut(u32, f64)>(samples: &[Sample], initial: Weights, scale: f64, mut pass_done: F) -> Weights {
+    // Rows one piece short of winning never occur in quiet positions
+    let max_pieces = samples.iter().map(|sample| sample.win_length).max().unwrap_or(2).saturating_sub(2) as usize;
+
+    let mut best = initial;
+    let mut best_error = mean_squared_error(samples, &best, scale);
+    for pass in 1..=MAX_PASSES {
+        let mut improved = false;
+        for index in 0..=max_pieces {
+            let value = *parameter(&mut best, index);
+            let step = if index == 0 { 1 } else { (value.abs() / 8).max(1) };
+            for delta in [step, -step] {
+                let mut candidate = best;
+                *parameter(&mut candidate, index) = value + delta;
+                let error = mean_squared_error(samples, &candidate, scale);
+                if error < best_error {
+                    (best, best_error, improved) = (candidate, error, true);
+                    break;
+                }
+            }
+        }
+        pass_done(pass, best_error);
+        if !improved {
+            break;
+        }
+    }
+    best
+}
+
+// The weight tuned as parameter `index`: the centre weight, then the weight
+// of each number of pieces in a segment
+fn parameter(weights: &mut Weights, index: usize) -> &mut i32 {
+    if index == 0 { &mut weights.centre } else { &mut weights.lines[index] }
+}